@@ -0,0 +1,587 @@
+//! The analysis pipeline behind both the GUI and the command line: spectra of frames
+//! scheduled over a live stream, level meters and a filter bank fed every captured
+//! sample, and the same results for a whole recording at once.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::capture::MultiChannelBuffer;
+use crate::dsp::averaging::{average_spectrum_with_progress, Averager, AveragingMode};
+use crate::dsp::bands::{FilterBank, OctaveFraction};
+use crate::dsp::decimate::envelope;
+use crate::dsp::metrics::{LevelMeter, WeightedLevel};
+use crate::dsp::spectrum::SpectrumAnalyzer;
+use crate::dsp::weighting::Weighting;
+use crate::dsp::window::WindowFunction;
+use crate::types::{Channel, Spectrum};
+
+/// Samples kept beyond one FFT frame so that overlapping frames which arrive between
+/// updates can still be analyzed.
+pub const CAPTURE_BACKLOG: usize = 1 << 16;
+/// Upper bound on frames analyzed per update; older pending frames are skipped.
+pub const MAX_FRAMES_PER_UPDATE: u64 = 64;
+/// Integration time of live filter-bank levels in seconds, IEC 61672 Slow.
+pub const BAND_TIME_CONSTANT: f64 = 1.0;
+/// Integration time of the level meters in seconds, IEC 61672 Fast.
+pub const METER_TIME_CONSTANT: f64 = 0.125;
+/// Samples a whole-file pass processes between progress reports.
+const PROGRESS_CHUNK: usize = 1 << 16;
+
+/// One channel or mix-down under analysis, with its latest results.
+pub struct Trace {
+    /// The signal analyzed.
+    pub channel: Channel,
+    /// Applied to every frame's spectrum before averaging, and to the level meter.
+    pub weighting: Weighting,
+    /// Combines the spectra of successive frames.
+    pub averager: Averager,
+    /// The averaged spectrum.
+    pub spectrum: Option<Spectrum>,
+    /// Weighted RMS level.
+    pub meter: Option<WeightedLevel>,
+    /// The latest frame of samples, or an envelope of a whole recording.
+    pub waveform: Vec<f32>,
+    /// Samples between consecutive `waveform` points; above 1 when it holds an envelope.
+    pub waveform_step: f64,
+}
+
+impl Trace {
+    /// An unweighted trace with nothing analyzed yet.
+    pub fn new(channel: Channel) -> Self {
+        Trace {
+            channel,
+            weighting: Weighting::Z,
+            averager: Averager::new(AveragingMode::Off),
+            spectrum: None,
+            meter: None,
+            waveform: Vec::new(),
+            waveform_step: 1.0,
+        }
+    }
+
+    /// The channel, marked with its weighting if it has one.
+    pub fn name(&self) -> String {
+        match self.weighting {
+            Weighting::Z => self.channel.to_string(),
+            weighting => format!("{} ({weighting})", self.channel),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.spectrum = None;
+        self.averager.reset();
+        self.meter = None;
+    }
+}
+
+/// Analyzes an interleaved stream as it arrives: a frame every hop for each trace,
+/// and every sample for the meters and filter bank.
+pub struct Analysis {
+    /// Computes the spectrum of each frame.
+    pub analyzer: SpectrumAnalyzer,
+    /// How much of each frame the next one repeats.
+    pub overlap_percent: f32,
+    /// How each trace combines the spectra of successive frames.
+    pub averaging: AveragingMode,
+    /// The first trace is the selected channel; the rest are overlays.
+    pub traces: Vec<Trace>,
+    /// One per captured channel, fed every sample.
+    pub meters: Vec<LevelMeter>,
+    /// Fed from the first trace's channel while enabled by [`Analysis::set_filter_bank`].
+    pub filter_bank: Option<FilterBank>,
+    /// The latest samples of every channel, enough for the next few frames.
+    pub history: MultiChannelBuffer,
+    filter_bank_bands: Option<OctaveFraction>,
+    sample_rate: u32,
+    next_frame_end: u64,
+    frame: Vec<f32>,
+}
+
+impl Analysis {
+    /// One channel at no particular rate, until [`Analysis::reset`] says otherwise.
+    pub fn new(fft_size: usize, window: WindowFunction) -> Self {
+        Analysis {
+            analyzer: SpectrumAnalyzer::new(fft_size, window),
+            overlap_percent: 50.0,
+            averaging: AveragingMode::Off,
+            traces: vec![Trace::new(Channel::Single(0))],
+            meters: Vec::new(),
+            filter_bank: None,
+            history: MultiChannelBuffer::new(1, capture_capacity(fft_size)),
+            filter_bank_bands: None,
+            sample_rate: 0,
+            next_frame_end: 0,
+            frame: Vec::new(),
+        }
+    }
+
+    /// Sample rate of the stream, 0 without one.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Samples between the starts of consecutive frames.
+    pub fn hop_size(&self) -> usize {
+        crate::dsp::hop_size(self.analyzer.fft_size(), self.overlap_percent as f64)
+    }
+
+    /// Starts over with an empty history for a stream of `channel_count` channels,
+    /// dropping the traces it lacks.
+    pub fn reset(&mut self, channel_count: usize, sample_rate: u32) {
+        let capacity = capture_capacity(self.analyzer.fft_size());
+        self.history = MultiChannelBuffer::new(channel_count, capacity);
+        self.sample_rate = sample_rate;
+        self.meters.clear();
+        self.traces
+            .retain(|trace| trace.channel.is_available(channel_count));
+        if self.traces.is_empty() {
+            self.traces.push(Trace::new(Channel::Single(0)));
+        }
+        self.restart();
+    }
+
+    /// Re-analyzes the buffered history after a setting that affects every frame changed.
+    pub fn restart(&mut self) {
+        self.next_frame_end = 0;
+        self.filter_bank = None;
+        self.traces.iter_mut().for_each(Trace::reset);
+    }
+
+    /// Changes the frame length, keeping enough history for it.
+    pub fn set_fft_size(&mut self, fft_size: usize) {
+        self.history.set_capacity(capture_capacity(fft_size));
+        self.analyzer.set_fft_size(fft_size);
+    }
+
+    /// Feeds a filter bank of `bands` from now on, or stops feeding it.
+    pub fn set_filter_bank(&mut self, bands: Option<OctaveFraction>) {
+        if bands != self.filter_bank_bands {
+            self.filter_bank_bands = bands;
+            self.filter_bank = None;
+        }
+    }
+
+    /// Replaces the history with the interleaved `samples` without metering them, so
+    /// the views reflect a new position even before more samples arrive.
+    pub fn preload(&mut self, samples: &[f32]) {
+        let capacity = capture_capacity(self.analyzer.fft_size());
+        self.history = MultiChannelBuffer::new(self.history.channel_count(), capacity);
+        for &sample in samples {
+            self.history.push_interleaved(sample);
+        }
+        self.restart();
+    }
+
+    /// Takes the next interleaved samples of the stream and analyzes every frame
+    /// completed since the last call, stepping by the hop size. `on_frame` gets each
+    /// new spectrum of the first trace with the time it covers.
+    pub fn process(&mut self, incoming: &[f32], mut on_frame: impl FnMut(&Spectrum, f64)) {
+        for &sample in incoming {
+            self.history.push_interleaved(sample);
+        }
+        self.feed_meters(incoming);
+
+        let fft_size = self.analyzer.fft_size();
+        let hop = self.hop_size() as u64;
+        let buffer = &mut self.history;
+        let written = buffer.written();
+        let earliest = (buffer.oldest() + fft_size as u64)
+            .max(written.saturating_sub(hop * MAX_FRAMES_PER_UPDATE));
+        if self.next_frame_end < earliest {
+            self.next_frame_end = earliest;
+        }
+        let hop_duration = hop as f64 / self.sample_rate.max(1) as f64;
+        while self.next_frame_end <= written {
+            for (i, trace) in self.traces.iter_mut().enumerate() {
+                if !buffer.copy_frame(
+                    trace.channel,
+                    self.next_frame_end,
+                    fft_size,
+                    &mut self.frame,
+                ) {
+                    continue;
+                }
+                let Some(mut spectrum) = self.analyzer.process(&self.frame, self.sample_rate)
+                else {
+                    continue;
+                };
+                trace.weighting.apply(&mut spectrum);
+                if i == 0 {
+                    on_frame(&spectrum, hop_duration);
+                }
+                trace.averager.set_mode(self.averaging);
+                trace.averager.add(&spectrum, hop_duration);
+                trace.spectrum = trace.averager.spectrum();
+            }
+            self.next_frame_end += hop;
+        }
+
+        for trace in &mut self.traces {
+            buffer.copy_latest(trace.channel, fft_size, &mut trace.waveform);
+            trace.waveform_step = 1.0;
+        }
+    }
+
+    /// Feeds the level meters and filter bank every captured sample, since the
+    /// history only keeps enough for the next few frames.
+    fn feed_meters(&mut self, incoming: &[f32]) {
+        let sample_rate = self.sample_rate;
+        let channel_count = self.history.channel_count();
+        let time_constant = Some(METER_TIME_CONSTANT);
+        let stale = self.meters.len() != channel_count
+            || self.meters.iter().any(|meter| {
+                meter.sample_rate() != sample_rate || meter.time_constant() != time_constant
+            });
+        if stale {
+            self.meters = (0..channel_count)
+                .map(|_| LevelMeter::new(sample_rate, time_constant))
+                .collect();
+        }
+        for (index, meter) in self.meters.iter_mut().enumerate() {
+            Channel::Single(index).extract(incoming, channel_count, &mut self.frame);
+            meter.process(&self.frame);
+        }
+        for trace in &mut self.traces {
+            let meter = match &mut trace.meter {
+                Some(meter) if meter.sample_rate() == sample_rate => meter,
+                _ => trace.meter.insert(WeightedLevel::new(
+                    trace.weighting,
+                    sample_rate,
+                    Some(METER_TIME_CONSTANT),
+                )),
+            };
+            trace
+                .channel
+                .extract(incoming, channel_count, &mut self.frame);
+            meter.process(&self.frame);
+        }
+
+        let Some(fraction) = self.filter_bank_bands else {
+            return;
+        };
+        let bank = match &mut self.filter_bank {
+            Some(bank) if bank.sample_rate() == sample_rate => bank,
+            _ => {
+                let bands = fraction.audio_bands(sample_rate);
+                let bank = FilterBank::new(&bands, sample_rate, Some(BAND_TIME_CONSTANT));
+                self.filter_bank.insert(bank)
+            }
+        };
+        let channel = self.traces[0].channel;
+        channel.extract(incoming, channel_count, &mut self.frame);
+        bank.process(&self.frame);
+    }
+
+    /// The same analysis over a whole recording at once, with at most `max_rows`
+    /// spectrogram rows and `max_waveform_points` points per waveform.
+    pub fn whole_file(&self, max_rows: usize, max_waveform_points: usize) -> FileAnalysis {
+        let mut analyzer =
+            SpectrumAnalyzer::new(self.analyzer.fft_size(), self.analyzer.window().function);
+        analyzer.set_scaling(self.analyzer.scaling());
+        FileAnalysis {
+            analyzer,
+            hop: self.hop_size(),
+            traces: self
+                .traces
+                .iter()
+                .map(|trace| (trace.channel, trace.weighting))
+                .collect(),
+            meter_channels: (0..self.history.channel_count())
+                .map(Channel::Single)
+                .collect(),
+            filter_bank: self.filter_bank_bands,
+            max_rows,
+            max_waveform_points,
+        }
+    }
+
+    /// Shows the results of a whole-file analysis in place of the live ones, for the
+    /// traces that are still the same.
+    pub fn set_file_results(&mut self, results: FileResults) {
+        for (trace, result) in self.traces.iter_mut().zip(results.traces) {
+            if trace.channel == result.channel && trace.weighting == result.weighting {
+                *trace = result;
+            }
+        }
+        self.meters = results.meters;
+        self.filter_bank = results.filter_bank;
+    }
+}
+
+/// A whole recording's worth of analysis, for the whole-file view and the `analyze`
+/// command.
+pub struct FileAnalysis {
+    /// Computes the spectrum of each frame.
+    pub analyzer: SpectrumAnalyzer,
+    /// Samples between the starts of consecutive frames.
+    pub hop: usize,
+    /// Channel and weighting of each trace; the first one also feeds the filter bank
+    /// and the spectrogram rows.
+    pub traces: Vec<(Channel, Weighting)>,
+    /// The channels to meter, each with a [`LevelMeter`].
+    pub meter_channels: Vec<Channel>,
+    /// Bands of a filter bank for the first trace, if any.
+    pub filter_bank: Option<OctaveFraction>,
+    /// Most spectrogram rows to compute, none when 0.
+    pub max_rows: usize,
+    /// Most points in each waveform envelope, none when 0.
+    pub max_waveform_points: usize,
+}
+
+/// How far a [`FileAnalysis`] running on another thread has got, and a way to stop
+/// it. Clones share the same state.
+#[derive(Clone, Default)]
+pub struct Progress {
+    /// The completed fraction, as the bits of an `f32`.
+    fraction: Arc<AtomicU32>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Progress {
+    /// The completed fraction of the work, from 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        f32::from_bits(self.fraction.load(Ordering::Relaxed))
+    }
+
+    /// Asks the analysis to stop at its next report.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [`Progress::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Records the completed fraction, returning `false` once cancelled.
+    fn report(&self, fraction: f32) -> bool {
+        self.fraction.store(fraction.to_bits(), Ordering::Relaxed);
+        !self.is_cancelled()
+    }
+}
+
+/// What [`FileAnalysis::run`] found.
+pub struct FileResults {
+    /// One per requested trace, with the power-averaged spectrum of every frame and
+    /// the level of the whole signal.
+    pub traces: Vec<Trace>,
+    /// One per metered channel.
+    pub meters: Vec<LevelMeter>,
+    /// Band levels of the first trace over the whole signal.
+    pub filter_bank: Option<FilterBank>,
+    /// Spectra of frames of the first trace spread evenly across the recording.
+    pub rows: Vec<Spectrum>,
+    /// Time each row stands for in seconds.
+    pub row_duration: f64,
+}
+
+impl FileAnalysis {
+    /// Analyzes the interleaved `samples`, `channel_count` per frame.
+    pub fn run(&mut self, samples: &[f32], channel_count: usize, sample_rate: u32) -> FileResults {
+        let progress = Progress::default();
+        self.run_with_progress(samples, channel_count, sample_rate, &progress)
+            .expect("nothing else can cancel it")
+    }
+
+    /// Like [`FileAnalysis::run`], reporting to `progress` as it goes and giving up
+    /// with `None` once that is cancelled.
+    pub fn run_with_progress(
+        &mut self,
+        samples: &[f32],
+        channel_count: usize,
+        sample_rate: u32,
+        progress: &Progress,
+    ) -> Option<FileResults> {
+        let fft_size = self.analyzer.fft_size();
+        let hop = self.hop;
+        let len = samples.len() / channel_count;
+        let frame_count = match len.checked_sub(fft_size) {
+            Some(remaining) => remaining / hop + 1,
+            None => 0,
+        };
+        let frames_per_row = frame_count.div_ceil(self.max_rows.max(1)).max(1);
+        let mut results = FileResults {
+            traces: Vec::new(),
+            meters: Vec::new(),
+            filter_bank: None,
+            rows: Vec::new(),
+            row_duration: (hop * frames_per_row) as f64 / sample_rate as f64,
+        };
+
+        // Each trace, the rows, the filter bank and each meter take one pass over a
+        // channel, which is what progress counts.
+        let passes = self.traces.len()
+            + usize::from(self.max_rows > 0)
+            + usize::from(self.filter_bank.is_some())
+            + self.meter_channels.len();
+        let mut pass = 0;
+        let report = |pass: usize, done: usize| {
+            let fraction = (pass as f32 + done as f32 / len.max(1) as f32) / passes.max(1) as f32;
+            progress.report(fraction)
+        };
+        let mut signal = Vec::new();
+        for (i, &(channel, weighting)) in self.traces.iter().enumerate() {
+            channel.extract(samples, channel_count, &mut signal);
+            let mut trace = Trace::new(channel);
+            trace.weighting = weighting;
+            trace.spectrum = average_spectrum_with_progress(
+                &mut self.analyzer,
+                &signal,
+                hop,
+                sample_rate,
+                |done| report(pass, done),
+            );
+            pass += 1;
+            if !report(pass, 0) {
+                return None;
+            }
+            if let Some(spectrum) = &mut trace.spectrum {
+                weighting.apply(spectrum);
+            }
+            let mut meter = WeightedLevel::new(weighting, sample_rate, None);
+            meter.process(&signal);
+            trace.meter = Some(meter);
+            if self.max_waveform_points > 0 {
+                trace.waveform_step =
+                    envelope(&signal, self.max_waveform_points, &mut trace.waveform);
+            }
+            results.traces.push(trace);
+            if i > 0 {
+                continue;
+            }
+
+            if self.max_rows > 0 {
+                for start in (0..frame_count).step_by(frames_per_row).map(|k| k * hop) {
+                    if !report(pass, start) {
+                        return None;
+                    }
+                    let frame = &signal[start..start + fft_size];
+                    if let Some(mut spectrum) = self.analyzer.process(frame, sample_rate) {
+                        weighting.apply(&mut spectrum);
+                        results.rows.push(spectrum);
+                    }
+                }
+                pass += 1;
+            }
+            if let Some(fraction) = self.filter_bank {
+                let bands = fraction.audio_bands(sample_rate);
+                let mut bank = FilterBank::new(&bands, sample_rate, None);
+                for (k, chunk) in signal.chunks(PROGRESS_CHUNK).enumerate() {
+                    if !report(pass, k * PROGRESS_CHUNK) {
+                        return None;
+                    }
+                    bank.process(chunk);
+                }
+                results.filter_bank = Some(bank);
+                pass += 1;
+            }
+        }
+
+        for &channel in &self.meter_channels {
+            channel.extract(samples, channel_count, &mut signal);
+            let mut meter = LevelMeter::new(sample_rate, None);
+            for (k, chunk) in signal.chunks(PROGRESS_CHUNK).enumerate() {
+                if !report(pass, k * PROGRESS_CHUNK) {
+                    return None;
+                }
+                meter.process(chunk);
+            }
+            results.meters.push(meter);
+            pass += 1;
+        }
+        report(passes, 0).then_some(results)
+    }
+}
+
+/// History each channel keeps for `fft_size`-point frames.
+fn capture_capacity(fft_size: usize) -> usize {
+    fft_size + CAPTURE_BACKLOG
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::spectrum::Scaling;
+    use crate::source::sine;
+
+    /// A sine on the left and the same at half the level on the right.
+    fn stereo_sine(frequency: f64, sample_rate: u32, frames: usize) -> Vec<f32> {
+        sine(frequency, 1.0, sample_rate, frames)
+            .into_iter()
+            .flat_map(|x| [x, 0.5 * x])
+            .collect()
+    }
+
+    #[test]
+    fn streaming_analyzes_every_hop() {
+        let mut analysis = Analysis::new(256, WindowFunction::Hann);
+        analysis.reset(2, 48000);
+        analysis.traces.push(Trace::new(Channel::Single(1)));
+        let samples = stereo_sine(1500.0, 48000, 1024);
+        let mut frames = 0;
+        for chunk in samples.chunks(100) {
+            analysis.process(chunk, |spectrum, duration| {
+                assert_eq!(spectrum.fft_size, 256);
+                assert_eq!(duration, 128.0 / 48000.0);
+                frames += 1;
+            });
+        }
+        // Frames end at 256, 384, ..., 1024.
+        assert_eq!(frames, 7);
+        assert_eq!(analysis.meters.len(), 2);
+        let left = analysis.meters[0].peak();
+        let right = analysis.meters[1].peak();
+        assert!((left - 2.0 * right).abs() < 1e-6, "{left} {right}");
+        for trace in &analysis.traces {
+            assert!(trace.spectrum.is_some());
+            assert_eq!(trace.waveform.len(), 256);
+        }
+    }
+
+    #[test]
+    fn whole_file_matches_streaming_levels() {
+        let samples = stereo_sine(1000.0, 48000, 48000);
+        let mut analysis = Analysis::new(1024, WindowFunction::Hann);
+        analysis.reset(2, 48000);
+        analysis.analyzer.set_scaling(Scaling::Amplitude);
+        analysis.set_filter_bank(Some(OctaveFraction::Full));
+        let results = analysis.whole_file(16, 100).run(&samples, 2, 48000);
+
+        let frames = (48000 - 1024) / 512 + 1;
+        let spectrum = results.traces[0].spectrum.as_ref().unwrap();
+        assert_eq!(spectrum.averages, frames);
+        let strongest = (0..spectrum.magnitudes_db.len())
+            .max_by(|&a, &b| spectrum.magnitudes_db[a].total_cmp(&spectrum.magnitudes_db[b]))
+            .unwrap();
+        assert!((spectrum.frequency(strongest) - 1000.0).abs() < 47.0);
+        assert_eq!(results.rows.len(), 16);
+        assert!(results.traces[0].waveform.len() <= 100);
+        assert!(results.filter_bank.is_some());
+        assert_eq!(results.meters.len(), 2);
+        let level = results.traces[0].meter.as_ref().unwrap().level();
+        assert!((level - 1.0).abs() < 1e-3, "{level}");
+
+        analysis.set_file_results(results);
+        assert!(analysis.traces[0].spectrum.is_some());
+        assert!(analysis.filter_bank.is_some());
+    }
+
+    #[test]
+    fn whole_file_reports_progress_until_cancelled() {
+        let samples = stereo_sine(1000.0, 48000, 48000);
+        let mut analysis = Analysis::new(1024, WindowFunction::Hann);
+        analysis.reset(2, 48000);
+        let mut job = analysis.whole_file(16, 100);
+        let progress = Progress::default();
+        assert!(job
+            .run_with_progress(&samples, 2, 48000, &progress)
+            .is_some());
+        assert_eq!(progress.fraction(), 1.0);
+
+        let progress = Progress::default();
+        progress.cancel();
+        assert!(job
+            .run_with_progress(&samples, 2, 48000, &progress)
+            .is_none());
+    }
+}
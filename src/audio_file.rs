@@ -0,0 +1,199 @@
+//! Audio files decoded into memory, and real-time playback of them.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::decoder;
+use crate::error::AudioError;
+use crate::source::{AudioSource, Pacer, Pacing};
+use crate::types::Channel;
+
+/// A decoded audio file, held in memory as interleaved samples in [-1, 1].
+pub struct AudioFile {
+    /// Where the file was read from.
+    pub path: PathBuf,
+    /// Sample rate in Hz.
+    pub sample_rate: u32,
+    /// Number of interleaved channels.
+    pub channels: usize,
+    /// Human-readable encoding, e.g. "16-bit PCM".
+    pub encoding: String,
+    /// Speaker positions, when the container declares them.
+    pub channel_layout: Option<String>,
+    /// Metadata tags as (key, value) pairs, in file order.
+    pub tags: Vec<(String, String)>,
+    /// Interleaved samples, `channels` per frame.
+    pub samples: Vec<f32>,
+}
+
+impl AudioFile {
+    /// Opens WAV files directly and everything else through the compressed decoder.
+    pub fn open(path: &Path) -> Result<Self, AudioError> {
+        let is_wav = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"));
+        if is_wav {
+            AudioFile::open_wav(path)
+        } else {
+            decoder::decode(path)
+        }
+    }
+
+    /// Decodes a WAV file with integer PCM of 8 to 32 bits or 32-bit float samples.
+    pub fn open_wav(path: &Path) -> Result<Self, AudioError> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        let samples = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                // hound already re-centres unsigned 8-bit data around zero.
+                let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|s| s as f32 * scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        let encoding = match spec.sample_format {
+            hound::SampleFormat::Float => format!("{}-bit float", spec.bits_per_sample),
+            hound::SampleFormat::Int => format!("{}-bit PCM", spec.bits_per_sample),
+        };
+        Ok(AudioFile {
+            path: path.to_path_buf(),
+            sample_rate: spec.sample_rate,
+            channels: spec.channels as usize,
+            encoding,
+            channel_layout: None,
+            tags: Vec::new(),
+            samples,
+        })
+    }
+
+    /// Length in frames.
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// Length in seconds.
+    pub fn duration(&self) -> f64 {
+        self.frames() as f64 / self.sample_rate as f64
+    }
+
+    /// Interleaved samples of frames `start..end`, clamped to the file.
+    pub fn interleaved(&self, start: usize, end: usize) -> &[f32] {
+        let end = end.min(self.frames());
+        let start = start.min(end);
+        &self.samples[start * self.channels..end * self.channels]
+    }
+
+    /// Copies `len` frames of a channel or mix-down starting at frame `start` into `out`.
+    pub fn copy_channel(&self, channel: Channel, start: usize, len: usize, out: &mut Vec<f32>) {
+        channel.extract(self.interleaved(start, start + len), self.channels, out);
+    }
+}
+
+/// Plays an [`AudioFile`] back in real time for the live analysis path.
+pub struct FilePlayer {
+    /// The file being played, shareable with analyses running on other threads.
+    pub file: Arc<AudioFile>,
+    position: usize,
+    pacer: Pacer,
+}
+
+impl FilePlayer {
+    /// A paused player positioned at the start of `file`.
+    pub fn new(file: AudioFile) -> Self {
+        FilePlayer {
+            file: Arc::new(file),
+            position: 0,
+            pacer: Pacer::new(Pacing::RealTime),
+        }
+    }
+
+    /// Changes how far each [`FilePlayer::advance`] moves the playhead.
+    pub fn with_pacing(mut self, pacing: Pacing) -> Self {
+        self.pacer.pacing = pacing;
+        self
+    }
+
+    /// Whether the playhead is moving.
+    pub fn is_playing(&self) -> bool {
+        self.pacer.is_running()
+    }
+
+    /// Starts playing from the current position, or from the start if at the end.
+    pub fn play(&mut self) {
+        if self.position >= self.file.frames() {
+            self.position = 0;
+        }
+        self.pacer.start(self.position as u64);
+    }
+
+    /// Stops the playhead where it is.
+    pub fn pause(&mut self) {
+        self.pacer.stop();
+    }
+
+    /// Current playback position in frames.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Moves the playhead to `frame`, clamped to the file.
+    pub fn seek(&mut self, frame: usize) {
+        self.position = frame.min(self.file.frames());
+        if self.is_playing() {
+            self.play();
+        }
+    }
+
+    /// Moves the playhead to where the pacing says it should be and returns the
+    /// interleaved samples it passed over. Pauses at the end of the file.
+    pub fn advance(&mut self) -> &[f32] {
+        if !self.is_playing() {
+            return &[];
+        }
+        let target = self
+            .pacer
+            .target(self.position as u64, self.file.sample_rate);
+        let target = (target as usize).min(self.file.frames());
+        if target == self.file.frames() {
+            self.pacer.stop();
+        }
+        let from = self.position;
+        self.position = target;
+        self.file.interleaved(from, target)
+    }
+}
+
+impl AudioSource for FilePlayer {
+    fn name(&self) -> String {
+        let name = self.file.path.file_name().unwrap_or_default();
+        name.to_string_lossy().into_owned()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.file.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.file.channels
+    }
+
+    fn start(&mut self) -> Result<(), AudioError> {
+        self.play();
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.pause();
+    }
+
+    fn is_running(&self) -> bool {
+        self.is_playing()
+    }
+
+    fn read(&mut self, out: &mut Vec<f32>) {
+        out.extend_from_slice(self.advance());
+    }
+}
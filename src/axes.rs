@@ -0,0 +1,244 @@
+use eframe::egui::plot::{GridInput, GridMark};
+use std::fmt;
+
+/// How frequency maps to the horizontal axis of the spectrum plot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrequencyAxis {
+    Linear,
+    /// Logarithmic, with gridlines at 1, 2, 5 and the other multiples of each decade.
+    LogDecades,
+    /// Logarithmic, with gridlines on the octaves of 1 kHz and thinner ones on thirds.
+    LogOctaves,
+}
+
+impl FrequencyAxis {
+    pub const ALL: [FrequencyAxis; 3] = [
+        FrequencyAxis::Linear,
+        FrequencyAxis::LogDecades,
+        FrequencyAxis::LogOctaves,
+    ];
+
+    pub fn is_log(self) -> bool {
+        self != FrequencyAxis::Linear
+    }
+
+    /// Plot coordinate of a frequency; log axes plot log10 of it.
+    pub fn to_plot(self, hz: f64) -> f64 {
+        if self.is_log() {
+            hz.log10()
+        } else {
+            hz
+        }
+    }
+
+    /// Frequency at a plot coordinate.
+    pub fn frequency_at(self, x: f64) -> f64 {
+        if self.is_log() {
+            10f64.powf(x)
+        } else {
+            x
+        }
+    }
+
+    /// Gridlines for a log axis. `step_size` is the log distance to the next line
+    /// of the same weight, which egui uses to fade lines and labels as they crowd.
+    pub fn log_grid(self, input: GridInput) -> Vec<GridMark> {
+        let (min, max) = input.bounds;
+        let mut marks = Vec::new();
+        match self {
+            FrequencyAxis::Linear => {}
+            FrequencyAxis::LogDecades => {
+                for decade in min.floor() as i32..=max.ceil() as i32 {
+                    for multiple in 1..=9 {
+                        let step_size = match multiple {
+                            1 => 1.0,
+                            2 | 5 => 2f64.log10(),
+                            _ => (10.0f64 / 9.0).log10(),
+                        };
+                        let value = decade as f64 + (multiple as f64).log10();
+                        marks.push(GridMark { value, step_size });
+                    }
+                }
+            }
+            FrequencyAxis::LogOctaves => {
+                let octave = 2f64.log10();
+                let first = ((min - 3.0) / octave).floor() as i32;
+                let last = ((max - 3.0) / octave).ceil() as i32;
+                for n in first..=last {
+                    for third in 0..3 {
+                        let value = 3.0 + (n as f64 + third as f64 / 3.0) * octave;
+                        let step_size = if third == 0 { octave } else { octave / 3.0 };
+                        marks.push(GridMark { value, step_size });
+                    }
+                }
+            }
+        }
+        marks.retain(|mark| (min..=max).contains(&mark.value));
+        marks
+    }
+}
+
+impl fmt::Display for FrequencyAxis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrequencyAxis::Linear => write!(f, "Linear"),
+            FrequencyAxis::LogDecades => write!(f, "Log, decades"),
+            FrequencyAxis::LogOctaves => write!(f, "Log, octaves"),
+        }
+    }
+}
+
+/// How analyzer levels in dBFS or dBFS/Hz are shown.
+#[derive(Clone, Debug)]
+pub struct Levels {
+    /// Decibels added to every level.
+    pub offset_db: f64,
+    /// Show amplitudes rather than decibels.
+    pub linear: bool,
+    pub unit: String,
+}
+
+impl Levels {
+    pub fn convert(&self, db: f32) -> f64 {
+        let db = db as f64 + self.offset_db;
+        if self.linear {
+            10f64.powf(db / 20.0)
+        } else {
+            db
+        }
+    }
+
+    /// A converted level with `decimals` decimal places, or that many more
+    /// significant digits than three when linear.
+    pub fn format(&self, value: f64, decimals: usize) -> String {
+        if self.linear {
+            format!("{} {}", significant(value, 3 + decimals as i32), self.unit)
+        } else {
+            format!("{value:.decimals$} {}", self.unit)
+        }
+    }
+
+    /// The difference between two converted levels.
+    pub fn format_delta(&self, delta: f64) -> String {
+        if self.linear {
+            format!("{} {}", significant(delta, 3), self.unit)
+        } else {
+            format!("{delta:.1} dB")
+        }
+    }
+}
+
+/// "20 Hz", "315 Hz", "2.5 kHz".
+pub fn format_frequency(hz: f64) -> String {
+    if hz.abs() >= 1000.0 {
+        format!("{} kHz", significant(hz / 1000.0, 3))
+    } else {
+        format!("{} Hz", significant(hz, 3))
+    }
+}
+
+/// "250 µs", "12.5 ms", "1.5 s".
+pub fn format_duration(seconds: f64) -> String {
+    if seconds.abs() >= 1.0 || seconds == 0.0 {
+        format!("{} s", significant(seconds, 3))
+    } else if seconds.abs() >= 1e-3 {
+        format!("{} ms", significant(seconds * 1e3, 3))
+    } else {
+        format!("{} µs", significant(seconds * 1e6, 3))
+    }
+}
+
+/// `value` to `digits` significant digits, without trailing zeros.
+fn significant(value: f64, digits: i32) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return format!("{value}");
+    }
+    let decimals = (digits - 1 - value.abs().log10().floor() as i32).max(0) as usize;
+    let text = format!("{value:.decimals$}");
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(axis: FrequencyAxis, min_hz: f64, max_hz: f64) -> Vec<(f64, f64)> {
+        let input = GridInput {
+            bounds: (min_hz.log10() - 1e-9, max_hz.log10() + 1e-9),
+            base_step_size: 0.01,
+        };
+        axis.log_grid(input)
+            .into_iter()
+            .map(|mark| (10f64.powf(mark.value), mark.step_size))
+            .collect()
+    }
+
+    #[test]
+    fn decade_grid_marks_every_multiple() {
+        let marks = grid(FrequencyAxis::LogDecades, 10.0, 100.0);
+        assert_eq!(marks.len(), 10);
+        for (k, &(hz, step_size)) in marks.iter().enumerate() {
+            assert!((hz - 10.0 * (k + 1) as f64).abs() < 1e-6, "{hz}");
+            // Decades are the heaviest lines.
+            assert_eq!(step_size == 1.0, k == 0 || k == 9, "{hz}");
+        }
+        assert!(grid(FrequencyAxis::Linear, 10.0, 100.0).is_empty());
+    }
+
+    #[test]
+    fn octave_grid_marks_thirds_of_octaves_of_one_kilohertz() {
+        let marks = grid(FrequencyAxis::LogOctaves, 1000.0, 4000.0);
+        assert_eq!(marks.len(), 7);
+        for (k, &(hz, step_size)) in marks.iter().enumerate() {
+            assert!(
+                (hz - 1000.0 * 2f64.powf(k as f64 / 3.0)).abs() < 1e-6,
+                "{hz}"
+            );
+            assert_eq!(step_size == 2f64.log10(), k % 3 == 0, "{hz}");
+        }
+    }
+
+    #[test]
+    fn significant_digits_without_trailing_zeros() {
+        assert_eq!(significant(0.012345, 3), "0.0123");
+        assert_eq!(significant(2.5, 3), "2.5");
+        assert_eq!(significant(-1.25, 2), "-1.2");
+        assert_eq!(significant(100.0, 3), "100");
+        assert_eq!(significant(12345.6, 3), "12346");
+        assert_eq!(significant(0.0, 3), "0");
+    }
+
+    #[test]
+    fn frequencies_and_durations_pick_their_units() {
+        assert_eq!(format_frequency(20.0), "20 Hz");
+        assert_eq!(format_frequency(315.0), "315 Hz");
+        assert_eq!(format_frequency(2500.0), "2.5 kHz");
+        assert_eq!(format_frequency(-1000.0), "-1 kHz");
+        assert_eq!(format_duration(0.00025), "250 µs");
+        assert_eq!(format_duration(0.0125), "12.5 ms");
+        assert_eq!(format_duration(1.5), "1.5 s");
+        assert_eq!(format_duration(0.0), "0 s");
+    }
+
+    #[test]
+    fn levels_convert_with_their_offset() {
+        let dbu = Levels {
+            offset_db: 4.0,
+            linear: false,
+            unit: "dBu".into(),
+        };
+        assert_eq!(dbu.convert(-20.0), -16.0);
+        assert_eq!(dbu.format(dbu.convert(-20.0), 1), "-16.0 dBu");
+        let linear = Levels {
+            offset_db: 0.0,
+            linear: true,
+            unit: "FS".into(),
+        };
+        assert!((linear.convert(-6.0206) - 0.5).abs() < 1e-4);
+        assert_eq!(linear.format(0.5, 1), "0.5 FS");
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+
+use crate::types::Channel;
+
+/// History of captured samples kept on the UI side.
+///
+/// Besides the samples themselves it counts every sample ever written, so the
+/// reader can address frames by absolute position and tell how many it missed.
+pub struct CaptureBuffer {
+    samples: VecDeque<f32>,
+    capacity: usize,
+    written: u64,
+}
+
+impl CaptureBuffer {
+    /// An empty buffer keeping the `capacity` most recent samples.
+    pub fn new(capacity: usize) -> Self {
+        CaptureBuffer {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            written: 0,
+        }
+    }
+
+    /// Appends a sample, dropping the oldest one if the buffer is full.
+    pub fn push(&mut self, sample: f32) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.written += 1;
+    }
+
+    /// Changes how much history is kept, discarding the oldest samples if it shrinks.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        if self.samples.len() > capacity {
+            self.samples.drain(..self.samples.len() - capacity);
+        }
+        self.samples
+            .reserve(capacity.saturating_sub(self.samples.len()));
+        self.capacity = capacity;
+    }
+
+    /// Total number of samples pushed since the buffer was created.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Absolute position of the oldest sample still held.
+    pub fn oldest(&self) -> u64 {
+        self.written - self.samples.len() as u64
+    }
+
+    /// Copies the `len` samples that end at absolute position `end` into `out`.
+    /// Returns false if any of them have not arrived yet or were already overwritten.
+    pub fn copy_frame(&self, end: u64, len: usize, out: &mut Vec<f32>) -> bool {
+        let start = match end.checked_sub(len as u64) {
+            Some(start) if start >= self.oldest() && end <= self.written => start,
+            _ => return false,
+        };
+        let offset = (start - self.oldest()) as usize;
+        out.clear();
+        out.extend(self.samples.range(offset..offset + len));
+        true
+    }
+}
+
+/// Per-channel histories fed from an interleaved stream.
+pub struct MultiChannelBuffer {
+    channels: Vec<CaptureBuffer>,
+    next_channel: usize,
+    scratch: Vec<f32>,
+}
+
+impl MultiChannelBuffer {
+    /// Empty histories of `capacity` samples for at least one channel.
+    pub fn new(channel_count: usize, capacity: usize) -> Self {
+        MultiChannelBuffer {
+            channels: (0..channel_count.max(1))
+                .map(|_| CaptureBuffer::new(capacity))
+                .collect(),
+            next_channel: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Number of captured channels.
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Appends the next sample of an interleaved stream to its channel.
+    pub fn push_interleaved(&mut self, sample: f32) {
+        self.channels[self.next_channel].push(sample);
+        self.next_channel = (self.next_channel + 1) % self.channels.len();
+    }
+
+    /// Changes how much history each channel keeps.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        for channel in &mut self.channels {
+            channel.set_capacity(capacity);
+        }
+    }
+
+    /// Number of complete frames written so far.
+    pub fn written(&self) -> u64 {
+        self.channels.last().map_or(0, CaptureBuffer::written)
+    }
+
+    /// Absolute position of the oldest frame still held.
+    pub fn oldest(&self) -> u64 {
+        self.channels[0].oldest()
+    }
+
+    /// Like [`CaptureBuffer::copy_frame`], for a channel or mix-down.
+    pub fn copy_frame(
+        &mut self,
+        channel: Channel,
+        end: u64,
+        len: usize,
+        out: &mut Vec<f32>,
+    ) -> bool {
+        if end > self.written() {
+            return false;
+        }
+        match channel {
+            Channel::Single(index) => self.channels[index].copy_frame(end, len, out),
+            mix => {
+                if !self.channels[0].copy_frame(end, len, out)
+                    || !self.channels[1].copy_frame(end, len, &mut self.scratch)
+                {
+                    return false;
+                }
+                for (left, &right) in out.iter_mut().zip(&self.scratch) {
+                    *left = mix.mix(*left, right);
+                }
+                true
+            }
+        }
+    }
+
+    /// Copies up to the `len` most recent samples of a channel or mix-down into `out`.
+    pub fn copy_latest(&mut self, channel: Channel, len: usize, out: &mut Vec<f32>) {
+        let end = self.written();
+        let len = len.min((end - self.oldest()) as usize);
+        if !self.copy_frame(channel, end, len, out) {
+            out.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_frames_by_absolute_position() {
+        let mut buffer = CaptureBuffer::new(4);
+        for i in 0..6 {
+            buffer.push(i as f32);
+        }
+        assert_eq!((buffer.written(), buffer.oldest()), (6, 2));
+        let mut out = Vec::new();
+        assert!(buffer.copy_frame(5, 3, &mut out));
+        assert_eq!(out, [2.0, 3.0, 4.0]);
+        // Overwritten, and not yet written.
+        assert!(!buffer.copy_frame(4, 3, &mut out));
+        assert!(!buffer.copy_frame(7, 2, &mut out));
+    }
+
+    #[test]
+    fn shrinking_keeps_the_newest_samples() {
+        let mut buffer = CaptureBuffer::new(8);
+        for i in 0..8 {
+            buffer.push(i as f32);
+        }
+        buffer.set_capacity(2);
+        let mut out = Vec::new();
+        assert!(buffer.copy_frame(8, 2, &mut out));
+        assert_eq!(out, [6.0, 7.0]);
+        assert_eq!(buffer.oldest(), 6);
+    }
+
+    #[test]
+    fn deinterleaves_and_mixes() {
+        let mut buffer = MultiChannelBuffer::new(2, 16);
+        for frame in [[1.0, 0.5], [0.25, -0.25], [0.0, 1.0]] {
+            for sample in frame {
+                buffer.push_interleaved(sample);
+            }
+        }
+        assert_eq!(buffer.written(), 3);
+        let mut out = Vec::new();
+        assert!(buffer.copy_frame(Channel::Single(1), 3, 3, &mut out));
+        assert_eq!(out, [0.5, -0.25, 1.0]);
+        assert!(buffer.copy_frame(Channel::Sum, 2, 2, &mut out));
+        assert_eq!(out, [1.5, 0.0]);
+        buffer.copy_latest(Channel::Mid, 8, &mut out);
+        assert_eq!(out, [0.75, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn incomplete_frames_are_not_readable() {
+        let mut buffer = MultiChannelBuffer::new(2, 16);
+        buffer.push_interleaved(1.0);
+        assert_eq!(buffer.written(), 0);
+        let mut out = Vec::new();
+        assert!(!buffer.copy_frame(Channel::Single(0), 1, 1, &mut out));
+    }
+}
@@ -0,0 +1,339 @@
+//! Stream configurations a device supports, and the one the user picked.
+
+use cpal::traits::DeviceTrait;
+use cpal::{SampleFormat, SupportedBufferSize, SupportedStreamConfigRange};
+
+use crate::error::AudioError;
+
+/// Rates offered in the settings dialog, filtered by what the device supports.
+pub const STANDARD_SAMPLE_RATES: [u32; 13] = [
+    8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000, 352800, 384000,
+];
+
+/// A stream configuration chosen by the user for one device.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeviceSettings {
+    /// Frames per second.
+    pub sample_rate: u32,
+    /// Number of interleaved channels.
+    pub channels: u16,
+    /// Sample type delivered to the callback.
+    pub sample_format: SampleFormat,
+    /// Frames per callback, or `None` to let the backend decide.
+    pub buffer_size: Option<u32>,
+}
+
+impl DeviceSettings {
+    /// The device's default input configuration.
+    pub fn from_default(device: &cpal::Device) -> Result<Self, AudioError> {
+        let config = device.default_input_config()?;
+        Ok(DeviceSettings {
+            sample_rate: config.sample_rate().0,
+            channels: config.channels(),
+            sample_format: config.sample_format(),
+            buffer_size: None,
+        })
+    }
+
+    /// Checks the settings against what the device reports and builds the stream config.
+    pub fn validate(
+        &self,
+        supported: &[SupportedStreamConfigRange],
+    ) -> Result<cpal::StreamConfig, AudioError> {
+        supported
+            .iter()
+            .find(|range| self.matches(range) && self.fits_buffer(range))
+            .ok_or_else(|| match self.buffer_size {
+                Some(frames) if supported.iter().any(|range| self.matches(range)) => {
+                    let ranges: Vec<String> = buffer_size_ranges(supported, self)
+                        .iter()
+                        .map(|(min, max)| format!("{min}..={max}"))
+                        .collect();
+                    AudioError::UnsupportedConfig(format!(
+                        "buffer size {frames} outside {} frames",
+                        ranges.join(", ")
+                    ))
+                }
+                _ => AudioError::UnsupportedConfig(format!(
+                    "{} channel(s) of {} at {} Hz",
+                    self.channels, self.sample_format, self.sample_rate
+                )),
+            })?;
+
+        let buffer_size = match self.buffer_size {
+            None => cpal::BufferSize::Default,
+            Some(frames) => cpal::BufferSize::Fixed(frames),
+        };
+
+        Ok(cpal::StreamConfig {
+            channels: self.channels,
+            sample_rate: cpal::SampleRate(self.sample_rate),
+            buffer_size,
+        })
+    }
+
+    /// Whether `range` offers this channel count and sample format at this rate.
+    fn matches(&self, range: &SupportedStreamConfigRange) -> bool {
+        range.channels() == self.channels
+            && range.sample_format() == self.sample_format
+            && (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&self.sample_rate)
+    }
+
+    /// Whether `range` accepts the fixed buffer size, if one is set.
+    fn fits_buffer(&self, range: &SupportedStreamConfigRange) -> bool {
+        match (self.buffer_size, range.buffer_size()) {
+            (Some(frames), SupportedBufferSize::Range { min, max }) => {
+                (*min..=*max).contains(&frames)
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Every input configuration range the device reports.
+pub fn supported_configs(
+    device: &cpal::Device,
+) -> Result<Vec<SupportedStreamConfigRange>, AudioError> {
+    Ok(device.supported_input_configs()?.collect())
+}
+
+/// The distinct sample formats among `supported`, in the order reported.
+pub fn sample_formats(supported: &[SupportedStreamConfigRange]) -> Vec<SampleFormat> {
+    let mut formats: Vec<SampleFormat> = Vec::new();
+    for range in supported {
+        if !formats.contains(&range.sample_format()) {
+            formats.push(range.sample_format());
+        }
+    }
+    formats
+}
+
+/// The distinct channel counts offered for `format`, ascending.
+pub fn channel_counts(supported: &[SupportedStreamConfigRange], format: SampleFormat) -> Vec<u16> {
+    let mut channels: Vec<u16> = supported
+        .iter()
+        .filter(|range| range.sample_format() == format)
+        .map(|range| range.channels())
+        .collect();
+    channels.sort_unstable();
+    channels.dedup();
+    channels
+}
+
+/// Standard rates within any range matching `format` and `channels`, plus the
+/// range limits themselves so devices with unusual rates remain selectable.
+pub fn sample_rates(
+    supported: &[SupportedStreamConfigRange],
+    format: SampleFormat,
+    channels: u16,
+) -> Vec<u32> {
+    let mut rates = Vec::new();
+    for range in supported
+        .iter()
+        .filter(|range| range.sample_format() == format && range.channels() == channels)
+    {
+        let (min, max) = (range.min_sample_rate().0, range.max_sample_rate().0);
+        rates.push(min);
+        rates.push(max);
+        rates.extend(
+            STANDARD_SAMPLE_RATES
+                .iter()
+                .filter(|rate| (min..=max).contains(rate)),
+        );
+    }
+    rates.sort_unstable();
+    rates.dedup();
+    rates
+}
+
+/// The buffer size limits of each range matching `settings`, ascending. Empty when
+/// any of them leaves the size to the backend, since then every size is worth trying.
+pub fn buffer_size_ranges(
+    supported: &[SupportedStreamConfigRange],
+    settings: &DeviceSettings,
+) -> Vec<(u32, u32)> {
+    let ranges: Option<Vec<(u32, u32)>> = supported
+        .iter()
+        .filter(|range| settings.matches(range))
+        .map(|range| match range.buffer_size() {
+            SupportedBufferSize::Range { min, max } => Some((*min, *max)),
+            SupportedBufferSize::Unknown => None,
+        })
+        .collect();
+    let mut ranges = ranges.unwrap_or_default();
+    ranges.sort_unstable();
+    ranges.dedup();
+    ranges
+}
+
+/// The size within `ranges` closest to `frames`, or `frames` itself if there are none.
+pub fn nearest_buffer_size(ranges: &[(u32, u32)], frames: u32) -> u32 {
+    ranges
+        .iter()
+        .map(|&(min, max)| frames.clamp(min, max))
+        .min_by_key(|size| size.abs_diff(frames))
+        .unwrap_or(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::SampleRate;
+
+    fn range(
+        channels: u16,
+        rates: (u32, u32),
+        buffer: Option<(u32, u32)>,
+        format: SampleFormat,
+    ) -> SupportedStreamConfigRange {
+        let buffer_size = match buffer {
+            Some((min, max)) => SupportedBufferSize::Range { min, max },
+            None => SupportedBufferSize::Unknown,
+        };
+        SupportedStreamConfigRange::new(
+            channels,
+            SampleRate(rates.0),
+            SampleRate(rates.1),
+            buffer_size,
+            format,
+        )
+    }
+
+    fn settings(sample_rate: u32, buffer_size: Option<u32>) -> DeviceSettings {
+        DeviceSettings {
+            sample_rate,
+            channels: 2,
+            sample_format: SampleFormat::F32,
+            buffer_size,
+        }
+    }
+
+    fn device() -> Vec<SupportedStreamConfigRange> {
+        vec![
+            range(2, (8000, 48000), Some((64, 256)), SampleFormat::F32),
+            range(2, (44100, 96000), Some((128, 4096)), SampleFormat::F32),
+            range(1, (44100, 44100), None, SampleFormat::I16),
+            range(2, (44100, 48000), None, SampleFormat::I16),
+            range(1, (22050, 22050), None, SampleFormat::F32),
+        ]
+    }
+
+    #[test]
+    fn validate_accepts_supported_settings() {
+        let config = settings(48000, None).validate(&device()).unwrap();
+        assert_eq!(config.channels, 2);
+        assert_eq!(config.sample_rate, SampleRate(48000));
+        assert_eq!(config.buffer_size, cpal::BufferSize::Default);
+
+        let config = settings(44100, Some(64)).validate(&device()).unwrap();
+        assert_eq!(config.buffer_size, cpal::BufferSize::Fixed(64));
+    }
+
+    #[test]
+    fn validate_rejects_unsupported_rate_or_format() {
+        assert!(matches!(
+            settings(192000, None).validate(&device()),
+            Err(AudioError::UnsupportedConfig(_))
+        ));
+        let settings = DeviceSettings {
+            sample_format: SampleFormat::I32,
+            ..settings(48000, None)
+        };
+        assert!(matches!(
+            settings.validate(&device()),
+            Err(AudioError::UnsupportedConfig(_))
+        ));
+    }
+
+    #[test]
+    fn validate_checks_buffer_size_against_every_matching_range() {
+        // Only the second range allows 1024 frames at 48 kHz, though the first matches too.
+        let config = settings(48000, Some(1024)).validate(&device()).unwrap();
+        assert_eq!(config.buffer_size, cpal::BufferSize::Fixed(1024));
+
+        // At 32 kHz only the first range applies.
+        assert!(matches!(
+            settings(32000, Some(1024)).validate(&device()),
+            Err(AudioError::UnsupportedConfig(_))
+        ));
+        assert!(settings(32000, Some(256)).validate(&device()).is_ok());
+        assert!(settings(44100, Some(4097)).validate(&device()).is_err());
+        assert!(settings(44100, Some(32)).validate(&device()).is_err());
+    }
+
+    #[test]
+    fn validate_allows_any_buffer_size_when_unknown() {
+        let settings = DeviceSettings {
+            sample_format: SampleFormat::I16,
+            ..settings(44100, Some(100_000))
+        };
+        assert!(settings.validate(&device()).is_ok());
+    }
+
+    #[test]
+    fn sample_formats_are_distinct_in_reported_order() {
+        assert_eq!(
+            sample_formats(&device()),
+            [SampleFormat::F32, SampleFormat::I16]
+        );
+    }
+
+    #[test]
+    fn channel_counts_are_distinct_and_sorted() {
+        assert_eq!(channel_counts(&device(), SampleFormat::F32), [1, 2]);
+        assert_eq!(channel_counts(&device(), SampleFormat::I16), [1, 2]);
+        assert!(channel_counts(&device(), SampleFormat::U8).is_empty());
+    }
+
+    #[test]
+    fn sample_rates_include_range_limits() {
+        assert_eq!(
+            sample_rates(&device(), SampleFormat::F32, 2),
+            [8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000]
+        );
+        let supported = [range(1, (7000, 50000), None, SampleFormat::F32)];
+        assert_eq!(
+            sample_rates(&supported, SampleFormat::F32, 1),
+            [7000, 8000, 11025, 16000, 22050, 32000, 44100, 48000, 50000]
+        );
+        assert_eq!(sample_rates(&device(), SampleFormat::F32, 1), [22050]);
+    }
+
+    #[test]
+    fn buffer_size_ranges_honour_sample_rate() {
+        assert_eq!(
+            buffer_size_ranges(&device(), &settings(32000, None)),
+            [(64, 256)]
+        );
+        assert_eq!(
+            buffer_size_ranges(&device(), &settings(88200, None)),
+            [(128, 4096)]
+        );
+        assert_eq!(
+            buffer_size_ranges(&device(), &settings(48000, None)),
+            [(64, 256), (128, 4096)]
+        );
+        let settings = DeviceSettings {
+            sample_format: SampleFormat::I16,
+            ..settings(44100, None)
+        };
+        assert!(buffer_size_ranges(&device(), &settings).is_empty());
+    }
+
+    #[test]
+    fn gaps_between_buffer_size_ranges_are_rejected() {
+        let supported = [
+            range(2, (48000, 48000), Some((64, 256)), SampleFormat::F32),
+            range(2, (48000, 48000), Some((1024, 4096)), SampleFormat::F32),
+        ];
+        assert!(settings(48000, Some(512)).validate(&supported).is_err());
+        assert!(settings(48000, Some(2048)).validate(&supported).is_ok());
+
+        let ranges = buffer_size_ranges(&supported, &settings(48000, None));
+        assert_eq!(nearest_buffer_size(&ranges, 512), 256);
+        assert_eq!(nearest_buffer_size(&ranges, 800), 1024);
+        assert_eq!(nearest_buffer_size(&ranges, 2048), 2048);
+        assert_eq!(nearest_buffer_size(&ranges, 8192), 4096);
+        assert_eq!(nearest_buffer_size(&[], 8192), 8192);
+    }
+}
@@ -0,0 +1,54 @@
+//! Audio input: device enumeration, capture streams and the buffers between the
+//! audio callback and the analysis.
+
+mod buffer;
+pub mod device_settings;
+pub mod ring_buffer;
+mod stream;
+pub mod tap;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+
+use crate::error::AudioError;
+
+pub use buffer::{CaptureBuffer, MultiChannelBuffer};
+pub use stream::{open_input, stream_config, InputStream};
+
+/// Looks up a compiled-in host by name, ignoring case.
+pub fn host_from_name(name: &str) -> Result<cpal::Host, AudioError> {
+    let id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| AudioError::UnknownHost(name.to_string()))?;
+    Ok(cpal::host_from_id(id)?)
+}
+
+/// Every input device the host reports; an empty list is an error.
+pub fn input_devices(host: &cpal::Host) -> Result<Vec<cpal::Device>, AudioError> {
+    let devices: Vec<cpal::Device> = host.input_devices()?.collect();
+    if devices.is_empty() {
+        return Err(AudioError::NoDevice);
+    }
+    Ok(devices)
+}
+
+/// The first input device whose name contains `name`, ignoring case, or the host's
+/// default input device.
+pub fn find_input_device(
+    host: &cpal::Host,
+    name: Option<&str>,
+) -> Result<cpal::Device, AudioError> {
+    match name {
+        Some(wanted) => {
+            let wanted = wanted.to_lowercase();
+            host.input_devices()?
+                .find(|device| {
+                    device
+                        .name()
+                        .is_ok_and(|name| name.to_lowercase().contains(&wanted))
+                })
+                .ok_or(AudioError::NoDevice)
+        }
+        None => host.default_input_device().ok_or(AudioError::NoDevice),
+    }
+}
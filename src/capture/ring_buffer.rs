@@ -0,0 +1,123 @@
+//! Lock-free queue carrying samples from the audio callback to the analysis.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Samples are stored as their bit patterns in atomics; the head and tail
+/// indices provide the release/acquire ordering between the two ends.
+struct Shared {
+    slots: Box<[AtomicU32]>,
+    mask: usize,
+    /// Total samples written; only advanced by the producer.
+    head: AtomicUsize,
+    /// Total samples read; only advanced by the consumer.
+    tail: AtomicUsize,
+    /// Samples the producer had to discard because the consumer fell behind.
+    overflows: AtomicU64,
+}
+
+/// The writing end of a [`ring_buffer`], used from the audio callback.
+pub struct Producer {
+    shared: Arc<Shared>,
+}
+
+/// The reading end of a [`ring_buffer`].
+pub struct Consumer {
+    shared: Arc<Shared>,
+}
+
+/// Creates a fixed-capacity single-producer/single-consumer queue of samples holding
+/// at least `capacity` samples, rounded up to a power of two.
+///
+/// Both ends are wait-free and never allocate, so the producer can live on the
+/// real-time audio thread while the consumer is drained from the UI.
+pub fn ring_buffer(capacity: usize) -> (Producer, Consumer) {
+    let capacity = capacity.max(1).next_power_of_two();
+    let shared = Arc::new(Shared {
+        slots: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+        mask: capacity - 1,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        overflows: AtomicU64::new(0),
+    });
+    (
+        Producer {
+            shared: shared.clone(),
+        },
+        Consumer { shared },
+    )
+}
+
+impl Producer {
+    /// Queues all of `samples`, or none of them if they don't fit, in which case they
+    /// are counted as overflow. Keeping callbacks whole keeps interleaved frames aligned.
+    pub fn push_all(&mut self, samples: impl ExactSizeIterator<Item = f32>) -> bool {
+        let shared = &*self.shared;
+        let head = shared.head.load(Ordering::Relaxed);
+        let tail = shared.tail.load(Ordering::Acquire);
+        let free = shared.slots.len() - head.wrapping_sub(tail);
+
+        let len = samples.len();
+        if len > free {
+            shared.overflows.fetch_add(len as u64, Ordering::Relaxed);
+            return false;
+        }
+        for (i, sample) in samples.take(len).enumerate() {
+            shared.slots[head.wrapping_add(i) & shared.mask]
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+        shared.head.store(head.wrapping_add(len), Ordering::Release);
+        true
+    }
+}
+
+impl Consumer {
+    /// Hands every queued sample to `f`, oldest first, and frees their slots.
+    pub fn pop_each(&mut self, mut f: impl FnMut(f32)) {
+        let shared = &*self.shared;
+        let tail = shared.tail.load(Ordering::Relaxed);
+        let head = shared.head.load(Ordering::Acquire);
+        let available = head.wrapping_sub(tail);
+
+        for i in 0..available {
+            let bits = shared.slots[tail.wrapping_add(i) & shared.mask].load(Ordering::Relaxed);
+            f(f32::from_bits(bits));
+        }
+
+        shared.tail.store(head, Ordering::Release);
+    }
+
+    /// Total samples dropped by the producer so far.
+    pub fn overflows(&self) -> u64 {
+        self.shared.overflows.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delivers_samples_in_order() {
+        let (mut producer, mut consumer) = ring_buffer(5);
+        assert!(producer.push_all([1.0, 2.0, 3.0].into_iter()));
+        let mut out = Vec::new();
+        consumer.pop_each(|sample| out.push(sample));
+        // Wraps around the eight slots the capacity was rounded up to.
+        assert!(producer.push_all((4..11).map(|i| i as f32)));
+        consumer.pop_each(|sample| out.push(sample));
+        assert_eq!(out, (1..=10).map(|i| i as f32).collect::<Vec<_>>());
+        assert_eq!(consumer.overflows(), 0);
+    }
+
+    #[test]
+    fn overflow_drops_the_whole_callback() {
+        let (mut producer, mut consumer) = ring_buffer(4);
+        assert!(producer.push_all([1.0, 2.0, 3.0].into_iter()));
+        assert!(!producer.push_all([4.0, 5.0].into_iter()));
+        assert_eq!(consumer.overflows(), 2);
+        let mut out = Vec::new();
+        consumer.pop_each(|sample| out.push(sample));
+        assert_eq!(out, [1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::mpsc;
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{SampleFormat, Stream};
+
+use super::device_settings::{self, DeviceSettings};
+use super::ring_buffer::Producer;
+use super::tap::TapInput;
+use crate::error::AudioError;
+
+/// A running capture stream and the format it was opened with.
+pub struct InputStream {
+    /// Capture stops when this is dropped.
+    pub stream: Stream,
+    /// Rate, channel count and buffer size the stream runs with.
+    pub config: cpal::StreamConfig,
+    /// Sample type the device delivers before conversion to f32.
+    pub sample_format: SampleFormat,
+}
+
+/// Opens and starts `device` with the given settings, or its default configuration,
+/// queueing every callback's samples as f32 into `producer`, and into `tap` while it
+/// has a reader. Errors raised while the stream runs are sent to `errors`.
+pub fn open_input(
+    device: &cpal::Device,
+    settings: Option<&DeviceSettings>,
+    producer: Producer,
+    tap: TapInput,
+    errors: mpsc::Sender<AudioError>,
+) -> Result<InputStream, AudioError> {
+    let (config, sample_format) = stream_config(device, settings)?;
+
+    let err_fn = move |err: cpal::StreamError| {
+        let _ = errors.send(err.into());
+    };
+    let queues = (producer, tap);
+    let stream = match sample_format {
+        SampleFormat::I8 => build_input_stream::<i8>(device, &config, err_fn, queues),
+        SampleFormat::I16 => build_input_stream::<i16>(device, &config, err_fn, queues),
+        SampleFormat::I32 => build_input_stream::<i32>(device, &config, err_fn, queues),
+        SampleFormat::I64 => build_input_stream::<i64>(device, &config, err_fn, queues),
+        SampleFormat::U8 => build_input_stream::<u8>(device, &config, err_fn, queues),
+        SampleFormat::U16 => build_input_stream::<u16>(device, &config, err_fn, queues),
+        SampleFormat::U32 => build_input_stream::<u32>(device, &config, err_fn, queues),
+        SampleFormat::U64 => build_input_stream::<u64>(device, &config, err_fn, queues),
+        SampleFormat::F32 => build_input_stream::<f32>(device, &config, err_fn, queues),
+        SampleFormat::F64 => build_input_stream::<f64>(device, &config, err_fn, queues),
+        _ => return Err(AudioError::UnsupportedSampleFormat(sample_format)),
+    }?;
+
+    stream.play()?;
+    Ok(InputStream {
+        stream,
+        config,
+        sample_format,
+    })
+}
+
+/// The configuration [`open_input`] would open `device` with.
+pub fn stream_config(
+    device: &cpal::Device,
+    settings: Option<&DeviceSettings>,
+) -> Result<(cpal::StreamConfig, SampleFormat), AudioError> {
+    match settings {
+        Some(settings) => {
+            let supported = device_settings::supported_configs(device)?;
+            Ok((settings.validate(&supported)?, settings.sample_format))
+        }
+        None => {
+            let config = device.default_input_config()?;
+            Ok((config.config(), config.sample_format()))
+        }
+    }
+}
+
+fn build_input_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    err_fn: impl Fn(cpal::StreamError) + Send + 'static,
+    (mut producer, mut tap): (Producer, TapInput),
+) -> Result<Stream, AudioError>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _| {
+            // Unsigned formats are offset-binary, so this also removes their DC offset.
+            producer.push_all(data.iter().map(|&sample| sample.to_sample::<f32>()));
+            tap.push_all(data.iter().map(|&sample| sample.to_sample::<f32>()));
+        },
+        err_fn,
+        None,
+    )?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use cpal::Sample;
+
+    #[test]
+    fn every_format_maps_onto_the_same_full_scale() {
+        // (format, its minimum, midpoint and maximum as f32, the step of one count)
+        let cases: [(&str, [f32; 3], f32); 10] = [
+            (
+                "i8",
+                [i8::MIN, 0, i8::MAX].map(|s| s.to_sample()),
+                1.0 / 128.0,
+            ),
+            (
+                "i16",
+                [i16::MIN, 0, i16::MAX].map(|s| s.to_sample()),
+                1.0 / 32768.0,
+            ),
+            (
+                "i32",
+                [i32::MIN, 0, i32::MAX].map(|s| s.to_sample()),
+                1.0 / 2147483648.0,
+            ),
+            ("i64", [i64::MIN, 0, i64::MAX].map(|s| s.to_sample()), 0.0),
+            (
+                "u8",
+                [u8::MIN, 1 << 7, u8::MAX].map(|s| s.to_sample()),
+                1.0 / 128.0,
+            ),
+            (
+                "u16",
+                [u16::MIN, 1 << 15, u16::MAX].map(|s| s.to_sample()),
+                1.0 / 32768.0,
+            ),
+            (
+                "u32",
+                [u32::MIN, 1 << 31, u32::MAX].map(|s| s.to_sample()),
+                1.0 / 2147483648.0,
+            ),
+            (
+                "u64",
+                [u64::MIN, 1 << 63, u64::MAX].map(|s| s.to_sample()),
+                0.0,
+            ),
+            ("f32", [-1.0f32, 0.0, 1.0].map(|s| s.to_sample()), 0.0),
+            ("f64", [-1.0f64, 0.0, 1.0].map(|s| s.to_sample()), 0.0),
+        ];
+        for (format, [min, mid, max], step) in cases {
+            assert_eq!(min, -1.0, "{format}");
+            assert_eq!(mid, 0.0, "{format}");
+            assert!(
+                (max - (1.0 - step)).abs() <= f32::EPSILON,
+                "{format}: {max}"
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! A second copy of a capture stream that can be switched on and drained from any
+//! thread, so a recording keeps up with the audio callback however long the UI
+//! takes between reads.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::ring_buffer::{self, Consumer, Producer};
+
+/// The audio-callback end of a [`tap`], queueing samples only while a reader is
+/// attached.
+pub struct TapInput {
+    attached: Arc<AtomicBool>,
+    producer: Producer,
+}
+
+/// Hands out readers of a [`tap`], one at a time.
+#[derive(Clone)]
+pub struct Tap {
+    attached: Arc<AtomicBool>,
+    consumer: Arc<Mutex<Consumer>>,
+}
+
+/// Receives every sample queued from the moment it was attached until it is dropped.
+pub struct TapReader {
+    tap: Tap,
+    /// Overflows counted before this reader was attached.
+    overflows_before: u64,
+}
+
+/// Creates a tap holding up to `capacity` samples between reads, like
+/// [`ring_buffer`](ring_buffer::ring_buffer).
+pub fn tap(capacity: usize) -> (TapInput, Tap) {
+    let (producer, consumer) = ring_buffer::ring_buffer(capacity);
+    let attached = Arc::new(AtomicBool::new(false));
+    (
+        TapInput {
+            attached: attached.clone(),
+            producer,
+        },
+        Tap {
+            attached,
+            consumer: Arc::new(Mutex::new(consumer)),
+        },
+    )
+}
+
+impl TapInput {
+    /// Queues all of `samples` if a reader is attached, like [`Producer::push_all`].
+    pub fn push_all(&mut self, samples: impl ExactSizeIterator<Item = f32>) {
+        if self.attached.load(Ordering::Acquire) {
+            self.producer.push_all(samples);
+        }
+    }
+}
+
+impl Tap {
+    /// Starts queueing samples for a new reader, discarding any an earlier one left.
+    pub fn attach(&self) -> TapReader {
+        let mut consumer = self.consumer.lock().unwrap();
+        consumer.pop_each(|_| {});
+        let overflows_before = consumer.overflows();
+        self.attached.store(true, Ordering::Release);
+        TapReader {
+            tap: self.clone(),
+            overflows_before,
+        }
+    }
+}
+
+impl TapReader {
+    /// Appends the samples queued since the previous call to `out`.
+    pub fn read(&mut self, out: &mut Vec<f32>) {
+        let mut consumer = self.tap.consumer.lock().unwrap();
+        consumer.pop_each(|sample| out.push(sample));
+    }
+
+    /// Samples dropped since attaching because the reader fell behind.
+    pub fn overflows(&self) -> u64 {
+        self.tap.consumer.lock().unwrap().overflows() - self.overflows_before
+    }
+}
+
+impl Drop for TapReader {
+    fn drop(&mut self) {
+        self.tap.attached.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queues_only_while_attached() {
+        let (mut input, tap) = tap(4);
+        input.push_all([1.0, 2.0].into_iter());
+        let mut reader = tap.attach();
+        input.push_all([3.0, 4.0, 5.0].into_iter());
+        input.push_all([6.0, 7.0].into_iter());
+        let mut out = Vec::new();
+        reader.read(&mut out);
+        assert_eq!(out, [3.0, 4.0, 5.0]);
+        assert_eq!(reader.overflows(), 2);
+        drop(reader);
+
+        input.push_all([8.0].into_iter());
+        let mut reader = tap.attach();
+        input.push_all([9.0].into_iter());
+        out.clear();
+        reader.read(&mut out);
+        assert_eq!(out, [9.0]);
+        assert_eq!(reader.overflows(), 0);
+    }
+}
@@ -0,0 +1,768 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use fft_analyzer::analysis::FileAnalysis;
+use fft_analyzer::audio_file::AudioFile;
+use fft_analyzer::capture;
+use fft_analyzer::capture::device_settings;
+use fft_analyzer::dsp::bands::{self, BandMethod, OctaveFraction};
+use fft_analyzer::dsp::metrics::dbfs;
+use fft_analyzer::dsp::peaks::{Interpolation, PeakEstimator};
+use fft_analyzer::dsp::spectrum::{Scaling, SpectrumAnalyzer};
+use fft_analyzer::dsp::weighting::Weighting;
+use fft_analyzer::dsp::window::WindowFunction;
+use fft_analyzer::error::AudioError;
+use fft_analyzer::export::{ExportFormat, Metadata, Table, Values};
+use fft_analyzer::recorder::{RecordFormat, RecordSettings, Recorder};
+use fft_analyzer::source::{AudioSource, DeviceSource, Generator, Signal};
+use fft_analyzer::types::Channel;
+
+pub const USAGE: &str = "usage: fft-analyzer [--host <name>] [<command> [options]]
+
+Without a command the graphical analyzer starts.
+
+commands:
+  devices                 list input devices and their configurations
+  capture                 record live input or a test signal to a WAV or FLAC file
+  analyze <file>          print levels and the averaged spectrum of an audio file
+  spectrum                print levels and the averaged spectrum of live input or a
+                          test signal
+
+options:
+  --host <name>           audio host to capture from, e.g. ALSA or JACK; PulseAudio
+                          and PipeWire are reached through their ALSA devices
+  -h, --help              show this message
+
+capture and spectrum options:
+  --device <name>         input device, matched by part of its name (default device)
+  --duration <seconds>    how long to capture (default 5)
+  --signal <signal>       generate a mono 48 kHz test signal instead of capturing:
+                          sine[:hz], multitone[:hz,hz,...], white, pink,
+                          sweep[:from-to[:seconds]] or silence
+  --level <dBFS>          peak level of the test signal (default -6)
+
+capture options:
+  --output <path>         file to write (default capture-{date}-{time}.wav); takes the
+                          placeholders {date} {time} {device} {rate} {channels}
+  --format <format>       wav16, wav24, wav32f, flac16 or flac24 (default from the
+                          extension, otherwise wav24)
+
+analyze and spectrum options:
+  --fft <size>            FFT size, a power of two from 256 to 1048576 (default 2048)
+  --window <name>         rectangular, hann, hamming, blackman-harris, flat-top,
+                          kaiser[:beta] or gaussian[:sigma] (default hann)
+  --overlap <percent>     overlap between frames, 0 to 99 (default 50)
+  --scaling <mode>        amplitude, noise or psd in dBFS/Hz (default amplitude)
+  --channel <channel>     1, 2, ..., sum, difference, mid or side; may be repeated
+                          (default every channel)
+  --bands <fraction>      write 1/1, 1/3, 1/6, 1/12 or 1/24-octave band levels in
+                          dBFS instead of the spectrum; give the denominator
+  --band-method <method>  fft to sum spectrum bins or filter for a filter bank
+                          (default fft)
+  --weighting <curve>     a, c or z frequency weighting of the spectrum, bands and
+                          RMS level (default z)
+  --output <path>         write the spectrum to a .csv, .json or .npy file instead of
+                          printing it as CSV";
+
+const DEFAULT_DURATION: f64 = 5.0;
+const MIN_FFT_SIZE: usize = 1 << 8;
+const MAX_FFT_SIZE: usize = 1 << 20;
+/// How often live input is drained; well inside what the capture ring buffer holds.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+const GENERATOR_SAMPLE_RATE: u32 = 48000;
+
+#[derive(Default)]
+pub struct Options {
+    pub host: Option<String>,
+    /// Runs headless when set; otherwise the GUI starts.
+    pub command: Option<Command>,
+}
+
+pub enum Command {
+    Devices,
+    Capture {
+        live: LiveOptions,
+        output: Option<PathBuf>,
+        format: Option<RecordFormat>,
+    },
+    Analyze {
+        path: PathBuf,
+        analysis: AnalysisOptions,
+    },
+    Spectrum {
+        live: LiveOptions,
+        analysis: AnalysisOptions,
+    },
+}
+
+pub struct LiveOptions {
+    device: Option<String>,
+    duration: f64,
+    /// Generated in place of the device when set.
+    signal: Option<Signal>,
+    level_db: f32,
+}
+
+impl Default for LiveOptions {
+    fn default() -> Self {
+        LiveOptions {
+            device: None,
+            duration: DEFAULT_DURATION,
+            signal: None,
+            level_db: -6.0,
+        }
+    }
+}
+
+pub struct AnalysisOptions {
+    fft_size: usize,
+    window: WindowFunction,
+    overlap_percent: f64,
+    scaling: Scaling,
+    channels: Vec<Channel>,
+    /// Band levels are written in place of the spectrum when set.
+    bands: Option<OctaveFraction>,
+    band_method: BandMethod,
+    weighting: Weighting,
+    output: Option<PathBuf>,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            fft_size: 2048,
+            window: WindowFunction::Hann,
+            overlap_percent: 50.0,
+            scaling: Scaling::Amplitude,
+            channels: Vec::new(),
+            bands: None,
+            band_method: BandMethod::FftBinning,
+            weighting: Weighting::Z,
+            output: None,
+        }
+    }
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--host" => {
+                options.host = Some(args.next().ok_or("--host requires a value")?);
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            command if !command.starts_with('-') => {
+                options.command = Some(parse_command(command, &mut args)?);
+            }
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_command(name: &str, args: &mut impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut live = LiveOptions::default();
+    let mut analysis = AnalysisOptions::default();
+    let mut output = None;
+    let mut format = None;
+    let mut positional = Vec::new();
+
+    let takes_live = matches!(name, "capture" | "spectrum");
+    let takes_analysis = matches!(name, "analyze" | "spectrum");
+    if !matches!(name, "devices" | "capture" | "analyze" | "spectrum") {
+        return Err(format!("unknown command '{name}'"));
+    }
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("{USAGE}");
+            std::process::exit(0);
+        }
+        if !arg.starts_with("--") {
+            positional.push(arg);
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{arg} requires a value"))?;
+        match arg.as_str() {
+            "--host" => return Err("--host must come before the command".into()),
+            "--device" if takes_live => live.device = Some(value),
+            "--duration" if takes_live => {
+                live.duration = parse_number(&arg, &value)?;
+                if live.duration <= 0.0 {
+                    return Err("--duration must be positive".into());
+                }
+            }
+            "--signal" if takes_live => live.signal = Some(parse_signal(&value)?),
+            "--level" if takes_live => {
+                live.level_db = parse_number(&arg, &value)?;
+                if live.level_db > 0.0 {
+                    return Err("--level must be at most 0 dBFS".into());
+                }
+            }
+            "--output" if name != "devices" => output = Some(PathBuf::from(value)),
+            "--format" if name == "capture" => format = Some(parse_record_format(&value)?),
+            "--fft" if takes_analysis => {
+                let size: usize = parse_number(&arg, &value)?;
+                if !size.is_power_of_two() || !(MIN_FFT_SIZE..=MAX_FFT_SIZE).contains(&size) {
+                    return Err(format!(
+                        "--fft must be a power of two from {MIN_FFT_SIZE} to {MAX_FFT_SIZE}"
+                    ));
+                }
+                analysis.fft_size = size;
+            }
+            "--window" if takes_analysis => analysis.window = parse_window(&value)?,
+            "--overlap" if takes_analysis => {
+                analysis.overlap_percent = parse_number(&arg, &value)?;
+                if !(0.0..=99.0).contains(&analysis.overlap_percent) {
+                    return Err("--overlap must be between 0 and 99".into());
+                }
+            }
+            "--scaling" if takes_analysis => {
+                analysis.scaling = match value.to_ascii_lowercase().as_str() {
+                    "amplitude" => Scaling::Amplitude,
+                    "noise" => Scaling::Noise,
+                    "psd" => Scaling::Psd,
+                    _ => return Err(format!("unknown scaling '{value}'")),
+                };
+            }
+            "--channel" if takes_analysis => analysis.channels.push(parse_channel(&value)?),
+            "--bands" if takes_analysis => {
+                let denominator = value.strip_prefix("1/").unwrap_or(&value);
+                analysis.bands = Some(
+                    OctaveFraction::ALL
+                        .into_iter()
+                        .find(|fraction| fraction.bands_per_octave().to_string() == denominator)
+                        .ok_or_else(|| format!("unknown band fraction '{value}'"))?,
+                );
+            }
+            "--band-method" if takes_analysis => {
+                analysis.band_method = match value.to_ascii_lowercase().as_str() {
+                    "fft" => BandMethod::FftBinning,
+                    "filter" => BandMethod::FilterBank,
+                    _ => return Err(format!("unknown band method '{value}'")),
+                };
+            }
+            "--weighting" if takes_analysis => {
+                analysis.weighting = Weighting::ALL
+                    .into_iter()
+                    .find(|weighting| weighting.to_string().eq_ignore_ascii_case(&value))
+                    .ok_or_else(|| format!("unknown weighting '{value}'"))?;
+            }
+            _ => return Err(format!("'{name}' does not take {arg}")),
+        }
+    }
+
+    let command = match name {
+        "devices" => Command::Devices,
+        "capture" => Command::Capture {
+            live,
+            output,
+            format,
+        },
+        "analyze" => {
+            let path = positional.pop().ok_or("analyze requires a file")?;
+            analysis.output = output;
+            Command::Analyze {
+                path: PathBuf::from(path),
+                analysis,
+            }
+        }
+        _ => {
+            analysis.output = output;
+            Command::Spectrum { live, analysis }
+        }
+    };
+    if let Some(extra) = positional.first() {
+        return Err(format!("unexpected argument '{extra}'"));
+    }
+    Ok(command)
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}' for {flag}"))
+}
+
+fn parse_window(value: &str) -> Result<WindowFunction, String> {
+    let (name, parameter) = match value.split_once(':') {
+        Some((name, parameter)) => (name, Some(parse_number::<f32>("--window", parameter)?)),
+        None => (value, None),
+    };
+    let window = match name.to_ascii_lowercase().as_str() {
+        "rectangular" => WindowFunction::Rectangular,
+        "hann" => WindowFunction::Hann,
+        "hamming" => WindowFunction::Hamming,
+        "blackman-harris" => WindowFunction::BlackmanHarris,
+        "flat-top" => WindowFunction::FlatTop,
+        "kaiser" => WindowFunction::Kaiser {
+            beta: parameter.unwrap_or(8.6),
+        },
+        "gaussian" => WindowFunction::Gaussian {
+            sigma: parameter.unwrap_or(0.4),
+        },
+        _ => return Err(format!("unknown window '{value}'")),
+    };
+    Ok(window)
+}
+
+fn parse_signal(value: &str) -> Result<Signal, String> {
+    let (name, parameters) = match value.split_once(':') {
+        Some((name, parameters)) => (name, Some(parameters)),
+        None => (value, None),
+    };
+    let frequency = |value: &str| -> Result<f64, String> {
+        match parse_number::<f64>("--signal", value)? {
+            hz if hz > 0.0 && hz < GENERATOR_SAMPLE_RATE as f64 / 2.0 => Ok(hz),
+            _ => Err(format!(
+                "--signal frequencies must be between 0 and {} Hz",
+                GENERATOR_SAMPLE_RATE / 2
+            )),
+        }
+    };
+    let signal = match (name.to_ascii_lowercase().as_str(), parameters) {
+        ("sine", None) => Signal::defaults()[0].clone(),
+        ("sine", Some(hz)) => Signal::Sine {
+            frequency: frequency(hz)?,
+        },
+        ("multitone", None) => Signal::defaults()[1].clone(),
+        ("multitone", Some(list)) => Signal::Multitone {
+            frequencies: list.split(',').map(frequency).collect::<Result<_, _>>()?,
+        },
+        ("white", None) => Signal::WhiteNoise,
+        ("pink", None) => Signal::PinkNoise,
+        ("sweep", None) => Signal::defaults()[4].clone(),
+        ("sweep", Some(parameters)) => {
+            let (range, seconds) = match parameters.split_once(':') {
+                Some((range, seconds)) => (range, parse_number("--signal", seconds)?),
+                None => (parameters, 5.0),
+            };
+            let (start, end) = range
+                .split_once('-')
+                .ok_or_else(|| format!("invalid sweep range '{range}'"))?;
+            if seconds <= 0.0 {
+                return Err("the sweep duration must be positive".into());
+            }
+            Signal::Sweep {
+                start: frequency(start)?,
+                end: frequency(end)?,
+                duration: seconds,
+            }
+        }
+        ("silence", None) => Signal::Silence,
+        _ => return Err(format!("unknown signal '{value}'")),
+    };
+    Ok(signal)
+}
+
+fn parse_channel(value: &str) -> Result<Channel, String> {
+    let channel = match value.to_ascii_lowercase().as_str() {
+        "sum" => Channel::Sum,
+        "difference" => Channel::Difference,
+        "mid" => Channel::Mid,
+        "side" => Channel::Side,
+        number => match number.parse::<usize>() {
+            Ok(n) if n >= 1 => Channel::Single(n - 1),
+            _ => return Err(format!("unknown channel '{value}'")),
+        },
+    };
+    Ok(channel)
+}
+
+fn parse_record_format(value: &str) -> Result<RecordFormat, String> {
+    let format = match value.to_ascii_lowercase().as_str() {
+        "wav16" => RecordFormat::Wav16,
+        "wav24" => RecordFormat::Wav24,
+        "wav32f" => RecordFormat::Wav32Float,
+        "flac16" => RecordFormat::Flac16,
+        "flac24" => RecordFormat::Flac24,
+        _ => return Err(format!("unknown format '{value}'")),
+    };
+    Ok(format)
+}
+
+pub fn run(command: Command, host: &cpal::Host) -> Result<(), AudioError> {
+    match command {
+        Command::Devices => list_devices(host),
+        Command::Capture {
+            live,
+            output,
+            format,
+        } => capture(host, &live, output, format),
+        Command::Analyze { path, analysis } => {
+            let file = AudioFile::open(&path)?;
+            let signal = Samples {
+                source: path.display().to_string(),
+                sample_rate: file.sample_rate,
+                channels: file.channels,
+                samples: file.samples,
+            };
+            analyze(&signal, &analysis)
+        }
+        Command::Spectrum { live, analysis } => {
+            let mut input = LiveInput::open(host, &live)?;
+            let mut samples = Vec::new();
+            input.run(live.duration, |chunk| {
+                samples.extend_from_slice(chunk);
+                Ok(())
+            })?;
+            let signal = Samples {
+                source: input.source.name(),
+                sample_rate: input.source.sample_rate(),
+                channels: input.source.channels(),
+                samples,
+            };
+            analyze(&signal, &analysis)
+        }
+    }
+}
+
+fn list_devices(host: &cpal::Host) -> Result<(), AudioError> {
+    println!("Host: {}", host.id().name());
+    let default_name = host
+        .default_input_device()
+        .and_then(|device| device.name().ok());
+    for device in host.input_devices()? {
+        let name = device.name().unwrap_or_else(|_| "Unknown Device".into());
+        let marker = if Some(&name) == default_name.as_ref() {
+            "*"
+        } else {
+            " "
+        };
+        println!("{marker} {name}");
+        if let Ok(config) = device.default_input_config() {
+            println!(
+                "    default: {} ch, {} Hz, {}",
+                config.channels(),
+                config.sample_rate().0,
+                config.sample_format()
+            );
+        }
+        for range in device_settings::supported_configs(&device).unwrap_or_default() {
+            println!(
+                "    supports: {} ch, {}-{} Hz, {}",
+                range.channels(),
+                range.min_sample_rate().0,
+                range.max_sample_rate().0,
+                range.sample_format()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn capture(
+    host: &cpal::Host,
+    live: &LiveOptions,
+    output: Option<PathBuf>,
+    format: Option<RecordFormat>,
+) -> Result<(), AudioError> {
+    let output = output.unwrap_or_else(|| PathBuf::from("capture-{date}-{time}.wav"));
+    let extension = output.extension().and_then(|ext| ext.to_str());
+    let format = format.unwrap_or(match extension {
+        Some(ext) if ext.eq_ignore_ascii_case("flac") => RecordFormat::Flac24,
+        _ => RecordFormat::Wav24,
+    });
+    // The recorder adds the extension for the format itself.
+    let template = match extension {
+        Some(ext) if ext.eq_ignore_ascii_case(format.extension()) => output.with_extension(""),
+        _ => output,
+    };
+    let settings = RecordSettings {
+        format,
+        template: template.display().to_string(),
+        max_file_bytes: None,
+    };
+
+    let mut input = LiveInput::open(host, live)?;
+    let (errors, writer_errors) = mpsc::channel();
+    let mut recorder = Recorder::start(
+        &settings,
+        &input.source.name(),
+        input.source.sample_rate(),
+        input.source.channels(),
+        None,
+        errors,
+    )?;
+    let path = recorder.path();
+    eprintln!(
+        "Recording {:.1} s from {} to {}",
+        live.duration,
+        input.source.name(),
+        path.display()
+    );
+    input.run(live.duration, |chunk| {
+        if recorder.write(chunk.to_vec()) {
+            return Ok(());
+        }
+        // The writer thread stopped and is about to report why.
+        Err(writer_errors
+            .recv()
+            .unwrap_or_else(|_| AudioError::Record(std::io::ErrorKind::BrokenPipe.into())))
+    })?;
+    drop(recorder);
+    if let Ok(err) = writer_errors.try_recv() {
+        return Err(err);
+    }
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// Interleaved samples from a file or a capture.
+struct Samples {
+    source: String,
+    sample_rate: u32,
+    channels: usize,
+    samples: Vec<f32>,
+}
+
+/// Prints the levels, peaks, clips and strongest frequency of each channel, and writes
+/// the averaged spectra to the output file, or as CSV to stdout.
+fn analyze(signal: &Samples, options: &AnalysisOptions) -> Result<(), AudioError> {
+    let channels = if options.channels.is_empty() {
+        (0..signal.channels).map(Channel::Single).collect()
+    } else {
+        options.channels.clone()
+    };
+    if let Some(channel) = channels.iter().find(|c| !c.is_available(signal.channels)) {
+        return Err(AudioError::UnsupportedConfig(format!(
+            "{channel} with {} channel(s)",
+            signal.channels
+        )));
+    }
+
+    let mut analyzer = SpectrumAnalyzer::new(options.fft_size, options.window);
+    analyzer.set_scaling(options.scaling);
+    let hop = fft_analyzer::dsp::hop_size(options.fft_size, options.overlap_percent);
+    let filter_bank = match options.band_method {
+        BandMethod::FilterBank => options.bands,
+        BandMethod::FftBinning => None,
+    };
+    let mut job = FileAnalysis {
+        analyzer,
+        hop,
+        traces: Vec::new(),
+        meter_channels: Vec::new(),
+        filter_bank,
+        max_rows: 0,
+        max_waveform_points: 0,
+    };
+    let estimator = PeakEstimator::new(options.window);
+
+    let mut metadata = Metadata::new();
+    metadata.insert("source".into(), signal.source.clone().into());
+    metadata.insert("sample_rate_hz".into(), signal.sample_rate.into());
+    let duration = (signal.samples.len() / signal.channels) as f64 / signal.sample_rate as f64;
+    metadata.insert("duration_s".into(), duration.into());
+    let window = job.analyzer.window();
+    metadata.insert("fft_size".into(), options.fft_size.into());
+    metadata.insert("window".into(), window.function.to_string().into());
+    metadata.insert("window_coherent_gain".into(), window.coherent_gain.into());
+    metadata.insert("window_enbw_bins".into(), window.enbw.into());
+    metadata.insert("scaling".into(), options.scaling.to_string().into());
+    metadata.insert("overlap_percent".into(), options.overlap_percent.into());
+    metadata.insert("hop_size".into(), hop.into());
+    metadata.insert("averaging".into(), "power mean".into());
+    let weighting = options.weighting;
+    metadata.insert("weighting".into(), weighting.to_string().into());
+    let (column, unit) = match options.scaling {
+        Scaling::Psd => ("psd_db", weighting.label("dBFS/Hz")),
+        Scaling::Amplitude | Scaling::Noise => ("magnitude_db", weighting.label("dBFS")),
+    };
+    let rms_unit = weighting.label("dBFS");
+    if let Some(fraction) = options.bands {
+        metadata.insert("bands".into(), fraction.to_string().into());
+        metadata.insert("band_method".into(), options.band_method.to_string().into());
+        metadata.insert("unit".into(), rms_unit.clone().into());
+    } else {
+        metadata.insert("unit".into(), unit.clone().into());
+    }
+
+    let mut summary = Vec::new();
+    let mut columns = Vec::new();
+    for &channel in &channels {
+        job.traces = vec![(channel, weighting)];
+        job.meter_channels = vec![channel];
+        let mut results = job.run(&signal.samples, signal.channels, signal.sample_rate);
+        let trace = results.traces.remove(0);
+        let meter = &results.meters[0];
+        let peak = meter.peak();
+        let rms = trace.meter.map_or(0.0, |level| level.level());
+        let true_peak = dbfs(meter.true_peak());
+        metadata.insert(format!("{channel} peak_dbfs"), dbfs(peak).into());
+        metadata.insert(format!("{channel} true_peak_dbtp"), true_peak.into());
+        metadata.insert(format!("{channel} rms_dbfs"), dbfs(rms).into());
+        let crest_factor = meter.crest_factor_db();
+        metadata.insert(format!("{channel} crest_factor_db"), crest_factor.into());
+        metadata.insert(format!("{channel} dc_offset"), meter.dc_offset().into());
+        metadata.insert(format!("{channel} clips"), meter.clips().into());
+        let mut line = format!(
+            "{channel}: peak {:.2} dBFS, true peak {true_peak:.2} dBTP, RMS {:.2} {rms_unit}",
+            dbfs(peak),
+            dbfs(rms)
+        );
+        if meter.clips() > 0 {
+            line += &format!(", {} clips", meter.clips());
+        }
+
+        let Some(spectrum) = trace.spectrum else {
+            summary.push(format!(
+                "{line}, too short for a {}-point FFT",
+                options.fft_size
+            ));
+            continue;
+        };
+        if let Some(peak) = estimator
+            .strongest_weighted(&spectrum, weighting, 1, 0, Interpolation::Jacobsen)
+            .first()
+        {
+            line += &format!(
+                ", strongest {:.2} Hz at {:.2} {unit}",
+                peak.frequency, peak.level_db
+            );
+        }
+        summary.push(line);
+        if let Some(fraction) = options.bands {
+            let bands = fraction.audio_bands(signal.sample_rate);
+            let levels = match options.band_method {
+                BandMethod::FftBinning => {
+                    let enbw = job.analyzer.window().enbw;
+                    bands::spectrum_band_levels(&spectrum, options.scaling, enbw, &bands)
+                }
+                BandMethod::FilterBank => match &results.filter_bank {
+                    Some(bank) => bank.weighted_levels_db(weighting),
+                    None => continue,
+                },
+            };
+            if columns.is_empty() {
+                let band_values =
+                    |value: fn(&bands::Band) -> f64| Values::F64(bands.iter().map(value).collect());
+                columns.push(("nominal_hz".to_string(), band_values(|band| band.nominal)));
+                columns.push(("lower_hz".to_string(), band_values(|band| band.lower)));
+                columns.push(("upper_hz".to_string(), band_values(|band| band.upper)));
+                metadata.insert("frames_averaged".into(), spectrum.averages.into());
+            }
+            columns.push((format!("{channel} band_level_db"), Values::F32(levels)));
+            continue;
+        }
+        if columns.is_empty() {
+            let frequencies = (0..spectrum.magnitudes_db.len())
+                .map(|bin| spectrum.frequency(bin))
+                .collect();
+            columns.push(("frequency_hz".to_string(), Values::F64(frequencies)));
+            metadata.insert("frames_averaged".into(), spectrum.averages.into());
+        }
+        columns.push((
+            format!("{channel} {column}"),
+            Values::F32(spectrum.magnitudes_db),
+        ));
+    }
+
+    let table = Table { metadata, columns };
+    match &options.output {
+        Some(path) => {
+            table
+                .write(export_format(path)?, path)
+                .map_err(AudioError::Export)?;
+            for line in &summary {
+                println!("{line}");
+            }
+            println!("Wrote {}", path.display());
+        }
+        None => {
+            for line in &summary {
+                eprintln!("{line}");
+            }
+            let mut stdout = std::io::stdout().lock();
+            table.write_csv(&mut stdout).map_err(AudioError::Export)?;
+            stdout.flush().map_err(AudioError::Export)?;
+        }
+    }
+    Ok(())
+}
+
+fn export_format(path: &Path) -> Result<ExportFormat, AudioError> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    ExportFormat::ALL
+        .into_iter()
+        .find(|format| format.extension().eq_ignore_ascii_case(extension))
+        .ok_or_else(|| {
+            AudioError::UnsupportedConfig(format!(
+                "output '{}' is not a .csv, .json or .npy file",
+                path.display()
+            ))
+        })
+}
+
+/// A device or generator captured without the GUI.
+struct LiveInput {
+    source: Box<dyn AudioSource>,
+    errors: mpsc::Receiver<AudioError>,
+}
+
+impl LiveInput {
+    /// Starts the test signal if one was given, otherwise the first device whose
+    /// name contains the requested one, ignoring case, or the default device.
+    fn open(host: &cpal::Host, live: &LiveOptions) -> Result<Self, AudioError> {
+        let (sender, errors) = mpsc::channel();
+        let mut source: Box<dyn AudioSource> = match &live.signal {
+            Some(signal) => Box::new(Generator::new(
+                signal.clone(),
+                10f32.powf(live.level_db / 20.0),
+                GENERATOR_SAMPLE_RATE,
+                1,
+            )),
+            None => {
+                let device = capture::find_input_device(host, live.device.as_deref())?;
+                Box::new(DeviceSource::new(
+                    device,
+                    None,
+                    crate::RING_CAPACITY,
+                    sender,
+                )?)
+            }
+        };
+        source.start()?;
+        Ok(LiveInput { source, errors })
+    }
+
+    /// Hands captured interleaved samples to `f` until `duration` seconds have passed
+    /// through it, stopping early if the device goes away. Other stream errors are
+    /// printed as warnings.
+    fn run(
+        &mut self,
+        duration: f64,
+        mut f: impl FnMut(&[f32]) -> Result<(), AudioError>,
+    ) -> Result<(), AudioError> {
+        let channels = self.source.channels();
+        let mut remaining = (duration * self.source.sample_rate() as f64) as usize * channels;
+        let mut chunk = Vec::new();
+        while remaining > 0 {
+            std::thread::sleep(POLL_INTERVAL);
+            while let Ok(err) = self.errors.try_recv() {
+                if let AudioError::Stream(cpal::StreamError::DeviceNotAvailable) = err {
+                    return Err(err);
+                }
+                eprintln!("warning: {err}");
+            }
+            chunk.clear();
+            self.source.read(&mut chunk);
+            chunk.truncate(remaining);
+            remaining -= chunk.len();
+            f(&chunk)?;
+        }
+        let overflows = self.source.overflows();
+        if overflows > 0 {
+            eprintln!("warning: {overflows} samples were dropped");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use std::path::Path;
+
+use symphonia::core::audio::{SampleBuffer, SignalSpec};
+use symphonia::core::codecs::{CodecParameters, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision};
+use symphonia::core::probe::Hint;
+
+use crate::audio_file::AudioFile;
+use crate::error::AudioError;
+
+/// Decodes the first audio track of a compressed file (FLAC, MP3, Ogg Vorbis, AAC
+/// in MP4, and Ogg Opus when built with the `opus` feature) into memory.
+pub fn decode(path: &Path) -> Result<AudioFile, AudioError> {
+    let file = std::fs::File::open(path).map_err(Error::IoError)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    // Tags can live in the container (e.g. Vorbis comments) or ahead of it (e.g. ID3v2).
+    let mut tags = Vec::new();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        tags.extend(tag_pairs(revision));
+    }
+    let mut format = probed.format;
+    if let Some(revision) = format.metadata().current() {
+        tags.extend(tag_pairs(revision));
+    }
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| AudioError::UnsupportedCodec("no audio track".into()))?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    let channel_layout = params.channels.map(|channels| format!("{channels}"));
+
+    let (sample_rate, channels, samples) = if params.codec == CODEC_TYPE_OPUS {
+        decode_opus(format.as_mut(), track_id, &params)?
+    } else {
+        decode_packets(format.as_mut(), track_id, &params)?
+    };
+
+    let codec = match symphonia::default::get_codecs().get_codec(params.codec) {
+        Some(descriptor) => descriptor.long_name,
+        None if params.codec == CODEC_TYPE_OPUS => "Opus",
+        None => "unknown codec",
+    };
+    let encoding = match params.bits_per_sample {
+        Some(bits) => format!("{codec}, {bits}-bit"),
+        None => codec.to_string(),
+    };
+
+    Ok(AudioFile {
+        path: path.to_path_buf(),
+        sample_rate,
+        channels,
+        encoding,
+        channel_layout,
+        tags,
+        samples,
+    })
+}
+
+fn tag_pairs(revision: &MetadataRevision) -> impl Iterator<Item = (String, String)> + '_ {
+    revision.tags().iter().map(|tag| {
+        let key = match tag.std_key {
+            Some(std_key) => format!("{std_key:?}"),
+            None => tag.key.clone(),
+        };
+        (key, tag.value.to_string())
+    })
+}
+
+/// Whether reading stopped because the stream ended rather than because it failed.
+fn is_end_of_stream(err: &Error) -> bool {
+    matches!(err, Error::IoError(err) if err.kind() == std::io::ErrorKind::UnexpectedEof)
+}
+
+fn decode_packets(
+    format: &mut dyn FormatReader,
+    mut track_id: u32,
+    params: &CodecParameters,
+) -> Result<(u32, usize, Vec<f32>), AudioError> {
+    let mut decoder = symphonia::default::get_codecs().make(params, &DecoderOptions::default())?;
+    let mut sample_rate = params.sample_rate.unwrap_or(0);
+    let mut channels = params.channels.map_or(0, |channels| channels.count());
+    let mut samples = Vec::new();
+    let mut buffer: Option<(SignalSpec, SampleBuffer<f32>)> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(err) if is_end_of_stream(&err) => break,
+            // The tracks changed (e.g. the next stream of a chained Ogg file), so
+            // carry on with a decoder for whichever audio track comes first now.
+            Err(Error::ResetRequired) => {
+                let Some(track) = format
+                    .tracks()
+                    .iter()
+                    .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+                else {
+                    break;
+                };
+                track_id = track.id;
+                decoder = symphonia::default::get_codecs()
+                    .make(&track.codec_params, &DecoderOptions::default())?;
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet only costs its own samples.
+            Err(Error::DecodeError(_)) => continue,
+            // The stream's parameters changed mid-way; start afresh from the next packet.
+            Err(Error::ResetRequired) => {
+                decoder.reset();
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let spec = *decoded.spec();
+        sample_rate = spec.rate;
+        channels = spec.channels.count();
+        let buffer = match &mut buffer {
+            Some((buffer_spec, buffer))
+                if *buffer_spec == spec && buffer.capacity() >= decoded.capacity() * channels =>
+            {
+                buffer
+            }
+            _ => {
+                let new = SampleBuffer::new(decoded.capacity() as u64, spec);
+                &mut buffer.insert((spec, new)).1
+            }
+        };
+        buffer.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buffer.samples());
+    }
+
+    if channels == 0 || sample_rate == 0 {
+        return Err(AudioError::UnsupportedCodec(
+            "stream contains no audio".into(),
+        ));
+    }
+    Ok((sample_rate, channels, samples))
+}
+
+#[cfg(feature = "opus")]
+fn decode_opus(
+    format: &mut dyn FormatReader,
+    track_id: u32,
+    params: &CodecParameters,
+) -> Result<(u32, usize, Vec<f32>), AudioError> {
+    // Opus always decodes at 48 kHz; the rate in the header is informational only.
+    const SAMPLE_RATE: u32 = 48000;
+    // The longest packet Opus allows is 120 ms.
+    const MAX_FRAME: usize = 5760;
+
+    let header = params.extra_data.as_deref().unwrap_or_default();
+    let channels = match header.get(9) {
+        Some(1) => opus::Channels::Mono,
+        Some(2) => opus::Channels::Stereo,
+        _ => return Err(AudioError::UnsupportedCodec("multichannel Opus".into())),
+    };
+    let channel_count = channels as usize;
+    let pre_skip = header
+        .get(10..12)
+        .map_or(0, |bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize);
+
+    let mut decoder = opus::Decoder::new(SAMPLE_RATE, channels)?;
+    let mut frame = vec![0.0; MAX_FRAME * channel_count];
+    let mut samples = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(err) if is_end_of_stream(&err) => break,
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = decoder.decode_float(&packet.data, &mut frame, false)?;
+        samples.extend_from_slice(&frame[..decoded * channel_count]);
+    }
+
+    let skip = (pre_skip * channel_count).min(samples.len());
+    samples.drain(..skip);
+    Ok((SAMPLE_RATE, channel_count, samples))
+}
+
+#[cfg(not(feature = "opus"))]
+fn decode_opus(
+    _format: &mut dyn FormatReader,
+    _track_id: u32,
+    _params: &CodecParameters,
+) -> Result<(u32, usize, Vec<f32>), AudioError> {
+    Err(AudioError::UnsupportedCodec(
+        "Opus (rebuild with the `opus` feature)".into(),
+    ))
+}
@@ -0,0 +1,466 @@
+//! Combining spectra of successive frames.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::spectrum::SpectrumAnalyzer;
+use super::MIN_DB;
+use crate::types::Spectrum;
+
+/// Mean of several spectra, taken in the power domain.
+#[derive(Default)]
+pub struct PowerAverage {
+    sum: Vec<f64>,
+    count: usize,
+    sample_rate: u32,
+    fft_size: usize,
+}
+
+impl PowerAverage {
+    /// Adds a spectrum, starting over if its bin count differs from the previous ones.
+    pub fn add(&mut self, spectrum: &Spectrum) {
+        if self.sum.len() != spectrum.magnitudes_db.len() {
+            self.sum = vec![0.0; spectrum.magnitudes_db.len()];
+            self.count = 0;
+        }
+        for (sum, &db) in self.sum.iter_mut().zip(&spectrum.magnitudes_db) {
+            *sum += 10f64.powf(db as f64 / 10.0);
+        }
+        self.count += 1;
+        self.sample_rate = spectrum.sample_rate;
+        self.fft_size = spectrum.fft_size;
+    }
+
+    /// The mean so far, or `None` before the first spectrum.
+    pub fn spectrum(&self) -> Option<Spectrum> {
+        if self.count == 0 {
+            return None;
+        }
+        let magnitudes_db: Vec<f32> = self
+            .sum
+            .iter()
+            .map(|&sum| ((10.0 * (sum / self.count as f64).log10()) as f32).max(MIN_DB))
+            .collect();
+        Some(Spectrum {
+            sample_rate: self.sample_rate,
+            fft_size: self.fft_size,
+            phases: vec![f32::NAN; magnitudes_db.len()],
+            magnitudes_db,
+            averages: self.count,
+        })
+    }
+}
+
+/// How an [`Averager`] combines successive spectra. Every mode averages power, so
+/// noise settles to its mean level rather than the mean of its decibels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AveragingMode {
+    /// Each frame on its own.
+    Off,
+    /// Exponentially weighted mean, where a frame's weight decays by 1/e over
+    /// `time_constant` seconds.
+    Exponential {
+        /// Time constant in seconds.
+        time_constant: f32,
+    },
+    /// Mean of the most recent `frames` frames.
+    Linear {
+        /// Number of frames in the moving window.
+        frames: usize,
+    },
+    /// Mean of every frame since the last reset.
+    Infinite,
+}
+
+impl AveragingMode {
+    /// Every mode with its default parameter, in display order.
+    pub const ALL: [AveragingMode; 4] = [
+        AveragingMode::Off,
+        AveragingMode::Exponential { time_constant: 1.0 },
+        AveragingMode::Linear { frames: 16 },
+        AveragingMode::Infinite,
+    ];
+
+    /// Whether `other` is the same kind of mode, ignoring its parameter.
+    pub fn same_kind(&self, other: &AveragingMode) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl fmt::Display for AveragingMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AveragingMode::Off => write!(f, "Off"),
+            AveragingMode::Exponential { .. } => write!(f, "Exponential"),
+            AveragingMode::Linear { .. } => write!(f, "Linear"),
+            AveragingMode::Infinite => write!(f, "Infinite"),
+        }
+    }
+}
+
+/// Averages spectra as they arrive, and keeps max- and min-hold traces of the
+/// individual frames alongside.
+pub struct Averager {
+    mode: AveragingMode,
+    /// Mean power per bin; for linear averaging, the sum over `history`.
+    power: Vec<f64>,
+    /// Power of each frame in the linear window, oldest first.
+    history: VecDeque<Vec<f64>>,
+    /// Frames added since the last reset.
+    count: usize,
+    max_hold: Vec<f32>,
+    min_hold: Vec<f32>,
+    /// The most recent frame, whose sample rate, size and phases the output reuses.
+    latest: Option<Spectrum>,
+}
+
+impl Averager {
+    /// An empty averager in the given mode.
+    pub fn new(mode: AveragingMode) -> Self {
+        Averager {
+            mode,
+            power: Vec::new(),
+            history: VecDeque::new(),
+            count: 0,
+            max_hold: Vec::new(),
+            min_hold: Vec::new(),
+            latest: None,
+        }
+    }
+
+    /// The current mode.
+    pub fn mode(&self) -> AveragingMode {
+        self.mode
+    }
+
+    /// Switches mode, starting over for a different kind. A new time constant
+    /// carries the average on, and a shorter linear window drops the oldest frames.
+    pub fn set_mode(&mut self, mode: AveragingMode) {
+        let same_kind = mode.same_kind(&self.mode);
+        self.mode = mode;
+        if !same_kind {
+            self.reset();
+        } else if let AveragingMode::Linear { frames } = mode {
+            self.trim_history(frames);
+        }
+    }
+
+    /// Discards the average and both holds.
+    pub fn reset(&mut self) {
+        self.power.clear();
+        self.history.clear();
+        self.count = 0;
+        self.max_hold.clear();
+        self.min_hold.clear();
+        self.latest = None;
+    }
+
+    /// Number of frames the current average is made of.
+    pub fn count(&self) -> usize {
+        match self.mode {
+            AveragingMode::Off => self.count.min(1),
+            AveragingMode::Linear { .. } => self.history.len(),
+            AveragingMode::Exponential { .. } | AveragingMode::Infinite => self.count,
+        }
+    }
+
+    /// Adds the next frame, which started `frame_interval` seconds after the previous
+    /// one. Starts over if its bin count differs from the previous frames.
+    pub fn add(&mut self, spectrum: &Spectrum, frame_interval: f64) {
+        let bins = spectrum.magnitudes_db.len();
+        if self.latest.as_ref().map(|s| s.magnitudes_db.len()) != Some(bins) {
+            self.reset();
+            self.power = vec![0.0; bins];
+            self.max_hold = vec![MIN_DB; bins];
+            self.min_hold = vec![f32::MAX; bins];
+        }
+        self.count += 1;
+        for ((max, min), &db) in self
+            .max_hold
+            .iter_mut()
+            .zip(&mut self.min_hold)
+            .zip(&spectrum.magnitudes_db)
+        {
+            *max = max.max(db);
+            *min = min.min(db);
+        }
+
+        let power = spectrum
+            .magnitudes_db
+            .iter()
+            .map(|&db| 10f64.powf(db as f64 / 10.0));
+        match self.mode {
+            AveragingMode::Off => {}
+            AveragingMode::Exponential { time_constant } => {
+                // Until enough frames have arrived this is a plain mean, so the
+                // first frame does not linger for a whole time constant.
+                let decay = (-frame_interval / time_constant as f64).exp();
+                let weight = (1.0 - decay).max(1.0 / self.count as f64);
+                for (mean, power) in self.power.iter_mut().zip(power) {
+                    *mean += weight * (power - *mean);
+                }
+            }
+            AveragingMode::Linear { frames } => {
+                let power: Vec<f64> = power.collect();
+                for (sum, &power) in self.power.iter_mut().zip(&power) {
+                    *sum += power;
+                }
+                self.history.push_back(power);
+                self.trim_history(frames);
+            }
+            AveragingMode::Infinite => {
+                let weight = 1.0 / self.count as f64;
+                for (mean, power) in self.power.iter_mut().zip(power) {
+                    *mean += weight * (power - *mean);
+                }
+            }
+        }
+        self.latest = Some(spectrum.clone());
+    }
+
+    /// Drops the oldest frames of the linear window beyond `frames`.
+    fn trim_history(&mut self, frames: usize) {
+        while self.history.len() > frames.max(1) {
+            let oldest = self.history.pop_front().unwrap_or_default();
+            for (sum, power) in self.power.iter_mut().zip(oldest) {
+                *sum -= power;
+            }
+        }
+    }
+
+    /// The averaged spectrum, or `None` before the first frame. Phases are only
+    /// kept with averaging off.
+    pub fn spectrum(&self) -> Option<Spectrum> {
+        let latest = self.latest.as_ref()?;
+        let mean = match self.mode {
+            AveragingMode::Off => return Some(latest.clone()),
+            AveragingMode::Linear { .. } => {
+                let frames = self.history.len() as f64;
+                self.power.iter().map(|&sum| sum / frames).collect()
+            }
+            AveragingMode::Exponential { .. } | AveragingMode::Infinite => self.power.clone(),
+        };
+        Some(self.with_levels(latest, from_power(mean)))
+    }
+
+    /// The highest level each bin has reached since the last reset.
+    pub fn max_hold(&self) -> Option<Spectrum> {
+        let latest = self.latest.as_ref()?;
+        Some(self.with_levels(latest, self.max_hold.clone()))
+    }
+
+    /// The lowest level each bin has reached since the last reset.
+    pub fn min_hold(&self) -> Option<Spectrum> {
+        let latest = self.latest.as_ref()?;
+        Some(self.with_levels(latest, self.min_hold.clone()))
+    }
+
+    fn with_levels(&self, latest: &Spectrum, magnitudes_db: Vec<f32>) -> Spectrum {
+        Spectrum {
+            sample_rate: latest.sample_rate,
+            fft_size: latest.fft_size,
+            phases: vec![f32::NAN; magnitudes_db.len()],
+            magnitudes_db,
+            averages: self.count(),
+        }
+    }
+}
+
+fn from_power(power: Vec<f64>) -> Vec<f32> {
+    power
+        .into_iter()
+        // A linear sum can end up a hair below zero after subtracting old frames.
+        .map(|power| ((10.0 * power.max(0.0).log10()) as f32).max(MIN_DB))
+        .collect()
+}
+
+/// Power-averages the spectra of every complete frame, `hop` samples apart. With
+/// [`Scaling::Psd`](super::spectrum::Scaling::Psd) this is Welch's estimate.
+pub fn average_spectrum(
+    analyzer: &mut SpectrumAnalyzer,
+    samples: &[f32],
+    hop: usize,
+    sample_rate: u32,
+) -> Option<Spectrum> {
+    average_spectrum_with_progress(analyzer, samples, hop, sample_rate, |_| true)
+}
+
+/// Like [`average_spectrum`], telling `progress` how many samples it has analyzed
+/// before each frame and giving up with `None` as soon as it returns `false`.
+pub fn average_spectrum_with_progress(
+    analyzer: &mut SpectrumAnalyzer,
+    samples: &[f32],
+    hop: usize,
+    sample_rate: u32,
+    mut progress: impl FnMut(usize) -> bool,
+) -> Option<Spectrum> {
+    let fft_size = analyzer.fft_size();
+    let mut average = PowerAverage::default();
+    let mut end = fft_size;
+    while end <= samples.len() {
+        if !progress(end - fft_size) {
+            return None;
+        }
+        if let Some(spectrum) = analyzer.process(&samples[end - fft_size..end], sample_rate) {
+            average.add(&spectrum);
+        }
+        end += hop;
+    }
+    average.spectrum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::window::WindowFunction;
+
+    fn spectrum(magnitudes_db: Vec<f32>) -> Spectrum {
+        Spectrum {
+            sample_rate: 48000,
+            fft_size: 2 * (magnitudes_db.len() - 1),
+            phases: vec![0.0; magnitudes_db.len()],
+            magnitudes_db,
+            averages: 1,
+        }
+    }
+
+    #[test]
+    fn averages_power_not_decibels() {
+        let mut average = PowerAverage::default();
+        assert!(average.spectrum().is_none());
+        average.add(&spectrum(vec![0.0, -10.0]));
+        average.add(&spectrum(vec![-200.0, -10.0]));
+        let mean = average.spectrum().unwrap();
+        assert_eq!(mean.averages, 2);
+        assert!((mean.magnitudes_db[0] + 3.01).abs() < 0.01);
+        assert!((mean.magnitudes_db[1] + 10.0).abs() < 1e-4);
+        assert!(mean.phases.iter().all(|phase| phase.is_nan()));
+    }
+
+    #[test]
+    fn restarts_when_the_size_changes() {
+        let mut average = PowerAverage::default();
+        average.add(&spectrum(vec![0.0, 0.0]));
+        average.add(&spectrum(vec![-20.0, -20.0, -20.0]));
+        let mean = average.spectrum().unwrap();
+        assert_eq!(mean.averages, 1);
+        assert_eq!(mean.magnitudes_db.len(), 3);
+    }
+
+    fn levels(averager: &Averager) -> Vec<f32> {
+        averager.spectrum().unwrap().magnitudes_db
+    }
+
+    fn assert_db(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 0.01, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn off_passes_frames_through() {
+        let mut averager = Averager::new(AveragingMode::Off);
+        assert!(averager.spectrum().is_none());
+        averager.add(&spectrum(vec![0.0, -10.0]), 0.1);
+        averager.add(&spectrum(vec![-20.0, -30.0]), 0.1);
+        assert_eq!(levels(&averager), [-20.0, -30.0]);
+        assert_eq!(averager.count(), 1);
+        assert!(!averager.spectrum().unwrap().phases[0].is_nan());
+    }
+
+    #[test]
+    fn linear_averages_the_last_frames() {
+        let mut averager = Averager::new(AveragingMode::Linear { frames: 2 });
+        averager.add(&spectrum(vec![-200.0, 0.0]), 0.1);
+        averager.add(&spectrum(vec![0.0, 0.0]), 0.1);
+        assert_db(levels(&averager)[0], -3.01);
+        averager.add(&spectrum(vec![0.0, -200.0]), 0.1);
+        assert_eq!(averager.count(), 2);
+        // The first frame has left the window.
+        assert_db(levels(&averager)[0], 0.0);
+        assert_db(levels(&averager)[1], -3.01);
+    }
+
+    #[test]
+    fn infinite_averages_everything_until_reset() {
+        let mut averager = Averager::new(AveragingMode::Infinite);
+        for _ in 0..3 {
+            averager.add(&spectrum(vec![0.0, 0.0]), 0.1);
+        }
+        averager.add(&spectrum(vec![-200.0, 0.0]), 0.1);
+        assert_eq!(averager.count(), 4);
+        assert_db(levels(&averager)[0], -1.25);
+        averager.reset();
+        assert_eq!(averager.count(), 0);
+        assert!(averager.spectrum().is_none());
+    }
+
+    #[test]
+    fn exponential_decays_with_its_time_constant() {
+        let mut averager = Averager::new(AveragingMode::Exponential { time_constant: 1.0 });
+        // Long enough to be past the plain-mean start.
+        for _ in 0..1000 {
+            averager.add(&spectrum(vec![0.0]), 0.01);
+        }
+        assert_db(levels(&averager)[0], 0.0);
+        // One time constant of silence leaves 1/e of the power.
+        for _ in 0..100 {
+            averager.add(&spectrum(vec![-200.0]), 0.01);
+        }
+        assert_db(levels(&averager)[0], -4.34);
+        assert_eq!(averager.count(), 1100);
+    }
+
+    #[test]
+    fn exponential_starts_as_a_plain_mean() {
+        let mut averager = Averager::new(AveragingMode::Exponential {
+            time_constant: 10.0,
+        });
+        averager.add(&spectrum(vec![0.0]), 0.01);
+        averager.add(&spectrum(vec![-200.0]), 0.01);
+        assert_db(levels(&averager)[0], -3.01);
+    }
+
+    #[test]
+    fn parameter_changes_keep_the_average() {
+        let mut averager = Averager::new(AveragingMode::Linear { frames: 3 });
+        averager.add(&spectrum(vec![-200.0]), 0.1);
+        averager.add(&spectrum(vec![0.0]), 0.1);
+        averager.add(&spectrum(vec![0.0]), 0.1);
+        assert_db(levels(&averager)[0], -1.76);
+        averager.set_mode(AveragingMode::Linear { frames: 2 });
+        assert_eq!(averager.count(), 2);
+        assert_db(levels(&averager)[0], 0.0);
+        averager.set_mode(AveragingMode::Linear { frames: 4 });
+        assert_eq!(averager.count(), 2);
+
+        let mut averager = Averager::new(AveragingMode::Exponential { time_constant: 1.0 });
+        averager.add(&spectrum(vec![0.0]), 0.1);
+        averager.add(&spectrum(vec![-200.0]), 0.1);
+        averager.set_mode(AveragingMode::Exponential { time_constant: 2.0 });
+        assert_eq!(averager.count(), 2);
+        assert_db(levels(&averager)[0], -3.01);
+
+        averager.set_mode(AveragingMode::Infinite);
+        assert_eq!(averager.count(), 0);
+    }
+
+    #[test]
+    fn holds_track_individual_frames() {
+        let mut averager = Averager::new(AveragingMode::Infinite);
+        averager.add(&spectrum(vec![-10.0, -40.0]), 0.1);
+        averager.add(&spectrum(vec![-30.0, -20.0]), 0.1);
+        assert_eq!(averager.max_hold().unwrap().magnitudes_db, [-10.0, -20.0]);
+        assert_eq!(averager.min_hold().unwrap().magnitudes_db, [-30.0, -40.0]);
+        averager.set_mode(AveragingMode::Off);
+        assert!(averager.max_hold().is_none());
+    }
+
+    #[test]
+    fn counts_overlapping_frames() {
+        let mut analyzer = SpectrumAnalyzer::new(256, WindowFunction::Hann);
+        let samples = vec![0.25; 1024];
+        let mean = average_spectrum(&mut analyzer, &samples, 128, 48000).unwrap();
+        // Frames end at 256, 384, ..., 1024.
+        assert_eq!(mean.averages, 7);
+        assert!(average_spectrum(&mut analyzer, &samples[..255], 128, 48000).is_none());
+    }
+}
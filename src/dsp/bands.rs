@@ -0,0 +1,367 @@
+//! Fractional-octave band levels after IEC 61260-1, either by grouping the bins of a
+//! spectrum or from a bank of band-pass filters.
+
+use std::fmt;
+
+use super::filter::{self, Biquad};
+use super::metrics::RunningMean;
+use super::spectrum::Scaling;
+use super::weighting::Weighting;
+use super::MIN_DB;
+use crate::types::Spectrum;
+
+/// The base-ten octave ratio, 10^(3/10).
+const OCTAVE_RATIO: f64 = 1.995_262_314_968_88;
+/// Centre frequency of the band every series is aligned to.
+const REFERENCE_HZ: f64 = 1000.0;
+/// Poles of the low-pass prototype of each band filter. Three give the usual
+/// sixth-order band-pass, which meets class 1 of IEC 61260-1.
+const FILTER_ORDER: usize = 3;
+/// The range band analyses cover: the audio band.
+const AUDIO_RANGE_HZ: (f64, f64) = (20.0, 20_000.0);
+/// Preferred numbers of IEC 61260-1 for nominal centre frequencies, one decade.
+const R10: [f64; 10] = [1.0, 1.25, 1.6, 2.0, 2.5, 3.15, 4.0, 5.0, 6.3, 8.0];
+
+/// How wide each band is, as a fraction of an octave.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OctaveFraction {
+    /// 1/1 octave.
+    Full,
+    /// 1/3 octave.
+    Third,
+    /// 1/6 octave.
+    Sixth,
+    /// 1/12 octave.
+    Twelfth,
+    /// 1/24 octave.
+    TwentyFourth,
+}
+
+impl OctaveFraction {
+    /// Every fraction, from widest to narrowest.
+    pub const ALL: [OctaveFraction; 5] = [
+        OctaveFraction::Full,
+        OctaveFraction::Third,
+        OctaveFraction::Sixth,
+        OctaveFraction::Twelfth,
+        OctaveFraction::TwentyFourth,
+    ];
+
+    /// Bands per octave.
+    pub fn bands_per_octave(self) -> u32 {
+        match self {
+            OctaveFraction::Full => 1,
+            OctaveFraction::Third => 3,
+            OctaveFraction::Sixth => 6,
+            OctaveFraction::Twelfth => 12,
+            OctaveFraction::TwentyFourth => 24,
+        }
+    }
+
+    /// The bands that overlap the range from `low` to `high` Hz, in ascending order.
+    pub fn bands(self, low: f64, high: f64) -> Vec<Band> {
+        let b = self.bands_per_octave() as f64;
+        // Octaves from the reference of band `x`'s centre. With an even number of
+        // bands per octave, the reference falls on a band edge instead.
+        let octaves = |x: i32| {
+            if self.bands_per_octave() % 2 == 1 {
+                x as f64 / b
+            } else {
+                (2 * x + 1) as f64 / (2.0 * b)
+            }
+        };
+        let to_index = |hz: f64| (hz / REFERENCE_HZ).log(OCTAVE_RATIO) * b;
+        if !(low > 0.0 && high >= low) {
+            return Vec::new();
+        }
+        let first = to_index(low).floor() as i32 - 1;
+        let last = to_index(high).ceil() as i32 + 1;
+        (first..=last)
+            .map(|x| {
+                let centre = REFERENCE_HZ * OCTAVE_RATIO.powf(octaves(x));
+                let half_width = OCTAVE_RATIO.powf(1.0 / (2.0 * b));
+                Band {
+                    centre,
+                    lower: centre / half_width,
+                    upper: centre * half_width,
+                    nominal: self.nominal(centre),
+                }
+            })
+            .filter(|band| band.upper > low && band.lower < high)
+            .collect()
+    }
+
+    /// The bands that overlap 20 Hz to 20 kHz and lie wholly below Nyquist at
+    /// `sample_rate`.
+    pub fn audio_bands(self, sample_rate: u32) -> Vec<Band> {
+        let nyquist = sample_rate as f64 / 2.0;
+        let mut bands = self.bands(AUDIO_RANGE_HZ.0, AUDIO_RANGE_HZ.1);
+        bands.retain(|band| band.upper < nyquist);
+        bands
+    }
+
+    /// The name of a band: the preferred number nearest its centre for octave and
+    /// third-octave bands, and otherwise its centre to three significant digits.
+    fn nominal(self, centre: f64) -> f64 {
+        match self {
+            OctaveFraction::Full | OctaveFraction::Third => {
+                let tenths = (10.0 * centre.log10()).round() as i32;
+                R10[tenths.rem_euclid(10) as usize] * 10f64.powi(tenths.div_euclid(10))
+            }
+            _ => {
+                let scale = 10f64.powi(centre.log10().floor() as i32 - 2);
+                (centre / scale).round() * scale
+            }
+        }
+    }
+}
+
+impl fmt::Display for OctaveFraction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "1/{} octave", self.bands_per_octave())
+    }
+}
+
+/// One fractional-octave band.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Band {
+    /// Exact mid-band frequency, in Hz.
+    pub centre: f64,
+    /// Lower edge, in Hz.
+    pub lower: f64,
+    /// Upper edge, in Hz.
+    pub upper: f64,
+    /// Nominal mid-band frequency the band is labelled with, e.g. 31.5 Hz.
+    pub nominal: f64,
+}
+
+/// How band levels are computed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BandMethod {
+    /// Summing the power of the spectrum's bins in each band. Bands narrower than a
+    /// bin only get a share of the bins they overlap, so at low frequencies the
+    /// levels are no finer than the FFT's resolution.
+    FftBinning,
+    /// Measuring the output of a Butterworth band-pass per band, as IEC 61260-1
+    /// describes.
+    FilterBank,
+}
+
+impl BandMethod {
+    /// Both methods, in display order.
+    pub const ALL: [BandMethod; 2] = [BandMethod::FftBinning, BandMethod::FilterBank];
+}
+
+impl fmt::Display for BandMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BandMethod::FftBinning => write!(f, "FFT binning"),
+            BandMethod::FilterBank => write!(f, "Filter bank"),
+        }
+    }
+}
+
+/// The power in each band in dB relative to a full-scale sine, from a spectrum
+/// computed with `scaling` and a window with an ENBW of `enbw` bins.
+pub fn spectrum_band_levels(
+    spectrum: &Spectrum,
+    scaling: Scaling,
+    enbw: f32,
+    bands: &[Band],
+) -> Vec<f32> {
+    let bin_width = spectrum.bin_width();
+    // Turns each bin's level into the power it holds.
+    let scale = match scaling {
+        Scaling::Amplitude => 1.0 / enbw as f64,
+        Scaling::Noise => 1.0,
+        Scaling::Psd => bin_width,
+    };
+    let last = spectrum.magnitudes_db.len().saturating_sub(1);
+    bands
+        .iter()
+        .map(|band| {
+            let first_bin = ((band.lower / bin_width - 0.5).floor().max(0.0) as usize).min(last);
+            let last_bin = ((band.upper / bin_width + 0.5).ceil() as usize).min(last);
+            let power: f64 = (first_bin..=last_bin)
+                .map(|k| {
+                    let centre = spectrum.frequency(k);
+                    let overlap = (band.upper.min(centre + bin_width / 2.0)
+                        - band.lower.max(centre - bin_width / 2.0))
+                    .max(0.0);
+                    let db = spectrum.magnitudes_db[k] as f64;
+                    10f64.powf(db / 10.0) * scale * overlap / bin_width
+                })
+                .sum();
+            ((10.0 * power.log10()) as f32).max(MIN_DB)
+        })
+        .collect()
+}
+
+/// A band-pass filter per band with a running mean square of each output.
+pub struct FilterBank {
+    bands: Vec<Band>,
+    filters: Vec<Vec<Biquad>>,
+    sample_rate: u32,
+    mean_squares: Vec<RunningMean>,
+}
+
+impl FilterBank {
+    /// Designs filters for the `bands` that lie wholly below Nyquist. With a
+    /// `time_constant` in seconds the levels are exponentially time-weighted, like a
+    /// sound level meter's Fast or Slow; without one they average everything.
+    pub fn new(bands: &[Band], sample_rate: u32, time_constant: Option<f64>) -> Self {
+        let rate = sample_rate as f64;
+        let bands: Vec<Band> = bands
+            .iter()
+            .copied()
+            .filter(|band| band.upper < rate / 2.0)
+            .collect();
+        let filters = bands
+            .iter()
+            .map(|band| filter::butterworth_band_pass(FILTER_ORDER, band.lower, band.upper, rate))
+            .collect();
+        FilterBank {
+            mean_squares: vec![RunningMean::new(sample_rate, time_constant); bands.len()],
+            bands,
+            filters,
+            sample_rate,
+        }
+    }
+
+    /// The bands the bank has filters for.
+    pub fn bands(&self) -> &[Band] {
+        &self.bands
+    }
+
+    /// Sample rate the filters were designed for.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Filters `samples` through every band.
+    pub fn process(&mut self, samples: &[f32]) {
+        for (sections, mean_square) in self.filters.iter_mut().zip(&mut self.mean_squares) {
+            for &x in samples {
+                let y = filter::process_cascade(sections, x as f64);
+                mean_square.add(y * y);
+            }
+        }
+    }
+
+    /// The power in each band in dB relative to a full-scale sine.
+    pub fn levels_db(&self) -> Vec<f32> {
+        self.mean_squares
+            .iter()
+            .map(|mean_square| ((10.0 * (2.0 * mean_square.mean()).log10()) as f32).max(MIN_DB))
+            .collect()
+    }
+
+    /// The band levels with `weighting` added at each band's exact mid-band frequency.
+    pub fn weighted_levels_db(&self, weighting: Weighting) -> Vec<f32> {
+        self.levels_db()
+            .into_iter()
+            .zip(&self.bands)
+            .map(|(db, band)| ((db as f64 + weighting.gain_db(band.centre)) as f32).max(MIN_DB))
+            .collect()
+    }
+
+    /// Clears the filters and levels.
+    pub fn reset(&mut self) {
+        self.filters.iter_mut().flatten().for_each(Biquad::reset);
+        self.mean_squares.iter_mut().for_each(RunningMean::reset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::spectrum::SpectrumAnalyzer;
+    use crate::dsp::window::WindowFunction;
+    use crate::source::sine;
+
+    #[test]
+    fn bands_follow_the_base_ten_series() {
+        let octaves = OctaveFraction::Full.bands(20.0, 20000.0);
+        let nominal: Vec<f64> = octaves.iter().map(|band| band.nominal).collect();
+        assert_eq!(
+            nominal,
+            [16.0, 31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0]
+        );
+        let thirds = OctaveFraction::Third.bands(20.0, 20000.0);
+        assert_eq!(thirds.len(), 31);
+        assert_eq!(thirds[0].nominal, 20.0);
+        assert_eq!(thirds[30].nominal, 20000.0);
+        // Adjacent bands share their edges.
+        for pair in thirds.windows(2) {
+            assert!((pair[0].upper - pair[1].lower).abs() < 1e-9);
+        }
+        // With an even number per octave, 1 kHz is an edge rather than a centre.
+        let sixths = OctaveFraction::Sixth.bands(900.0, 1100.0);
+        assert!(sixths.iter().any(|band| (band.upper - 1000.0).abs() < 1e-9));
+        assert_eq!(OctaveFraction::TwentyFourth.bands(20.0, 20000.0).len(), 241);
+    }
+
+    #[test]
+    fn a_tone_lands_in_its_band_either_way() {
+        let bands = OctaveFraction::Third.bands(20.0, 20000.0);
+        let samples = sine(1000.0, 0.5, 48000, 48000);
+        let in_band = |levels: &[f32]| {
+            let band = bands
+                .iter()
+                .position(|band| band.nominal == 1000.0)
+                .unwrap();
+            (levels[band], levels[band - 1], levels[band + 1])
+        };
+
+        let mut analyzer = SpectrumAnalyzer::new(8192, WindowFunction::Hann);
+        let spectrum = analyzer.process(&samples, 48000).unwrap();
+        let enbw = analyzer.window().enbw;
+        let levels = spectrum_band_levels(&spectrum, Scaling::Amplitude, enbw, &bands);
+        let (level, below, above) = in_band(&levels);
+        assert!((level + 6.02).abs() < 0.1, "{level}");
+        assert!(below < -40.0 && above < -40.0);
+
+        let mut bank = FilterBank::new(&bands, 48000, None);
+        bank.process(&samples[..24000]);
+        bank.reset();
+        bank.process(&samples[24000..]);
+        let (level, below, above) = in_band(&bank.levels_db());
+        assert!((level + 6.02).abs() < 0.1, "{level}");
+        // Class 1 filters need only be 10 dB or so down a band away.
+        assert!(
+            below < level - 12.0 && above < level - 12.0,
+            "{below} {above}"
+        );
+    }
+
+    #[test]
+    fn band_levels_of_white_noise_sum_to_its_power() {
+        // A deterministic noise with a mean square of 1/12 of full scale.
+        let mut state = 1u32;
+        let samples: Vec<f32> = (0..16384)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                state as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect();
+        let bands = OctaveFraction::Full.bands(1.0, 24000.0);
+        let mut total = 0.0;
+        for scaling in Scaling::ALL {
+            let mut analyzer = SpectrumAnalyzer::new(16384, WindowFunction::Hann);
+            analyzer.set_scaling(scaling);
+            let spectrum = analyzer.process(&samples, 48000).unwrap();
+            let enbw = analyzer.window().enbw;
+            let levels = spectrum_band_levels(&spectrum, scaling, enbw, &bands);
+            let power: f64 = levels.iter().map(|&db| 10f64.powf(db as f64 / 10.0)).sum();
+            if scaling == Scaling::Amplitude {
+                total = power;
+            }
+            // The same under every scaling.
+            assert!((10.0 * (power / total).log10()).abs() < 0.01, "{scaling}");
+        }
+        // Relative to a full-scale sine's 1/2, give or take the band above 22.4 kHz
+        // and the window's loss of power at the frame edges.
+        let expected = 2.0 / 12.0;
+        assert!((10.0 * (total / expected).log10()).abs() < 0.5, "{total}");
+    }
+}
@@ -0,0 +1,69 @@
+//! Reducing long signals and curves to about as many points as a plot has pixels,
+//! keeping the extremes so narrow peaks stay visible.
+
+/// Reduces a polyline to roughly `max_points` points by keeping the extremes of
+/// each bucket, so narrow peaks survive when there are far more points than pixels.
+pub fn decimate(points: Vec<[f64; 2]>, max_points: usize) -> Vec<[f64; 2]> {
+    let bucket_len = points.len().div_ceil(max_points / 2);
+    if bucket_len <= 1 {
+        return points;
+    }
+    points
+        .chunks(bucket_len)
+        .flat_map(|bucket| {
+            let cmp = |a: &&[f64; 2], b: &&[f64; 2]| a[1].total_cmp(&b[1]);
+            let min = bucket.iter().min_by(cmp).unwrap();
+            let max = bucket.iter().max_by(cmp).unwrap();
+            if min[0] <= max[0] {
+                [*min, *max]
+            } else {
+                [*max, *min]
+            }
+        })
+        .collect()
+}
+
+/// Writes the minimum and maximum of each of about `max_points / 2` buckets of
+/// `samples` to `out`, returning the spacing in samples between output points.
+pub fn envelope(samples: &[f32], max_points: usize, out: &mut Vec<f32>) -> f64 {
+    out.clear();
+    let bucket_len = samples.len().div_ceil(max_points / 2).max(1);
+    if bucket_len <= 2 {
+        out.extend_from_slice(samples);
+        return 1.0;
+    }
+    for bucket in samples.chunks(bucket_len) {
+        out.push(bucket.iter().copied().fold(f32::MAX, f32::min));
+        out.push(bucket.iter().copied().fold(f32::MIN, f32::max));
+    }
+    bucket_len as f64 / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimation_keeps_extremes_in_order() {
+        let points: Vec<[f64; 2]> = (0..8).map(|i| [i as f64, (i % 4) as f64]).collect();
+        assert_eq!(decimate(points.clone(), 16), points);
+        assert_eq!(
+            decimate(points, 4),
+            [[0.0, 0.0], [3.0, 3.0], [4.0, 0.0], [7.0, 3.0]]
+        );
+        let falling = vec![[0.0, 5.0], [1.0, 1.0], [2.0, 3.0]];
+        assert_eq!(decimate(falling, 2), [[0.0, 5.0], [1.0, 1.0]]);
+    }
+
+    #[test]
+    fn envelope_of_buckets() {
+        let samples: Vec<f32> = (0..12)
+            .map(|i| if i % 2 == 0 { -1.0 } else { 0.5 })
+            .collect();
+        let mut out = Vec::new();
+        assert_eq!(envelope(&samples, 24, &mut out), 1.0);
+        assert_eq!(out, samples);
+        assert_eq!(envelope(&samples, 4, &mut out), 3.0);
+        assert_eq!(out, [-1.0, 0.5, -1.0, 0.5]);
+    }
+}
@@ -0,0 +1,162 @@
+//! Second-order IIR sections and the filters built from them.
+
+use rustfft::num_complex::Complex;
+use std::f64::consts::PI;
+
+/// A second-order IIR section in transposed direct form II. State is kept in f64 so
+/// that poles close to the unit circle, as in narrow low-frequency bands, stay
+/// accurate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Biquad {
+    /// Feed-forward coefficients b0, b1 and b2.
+    pub b: [f64; 3],
+    /// Feedback coefficients a1 and a2, with a0 normalized to 1.
+    pub a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    /// A section with the given coefficients and cleared state.
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Biquad {
+            b,
+            a,
+            state: [0.0; 2],
+        }
+    }
+
+    /// Filters one sample.
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+
+    /// Clears the state, as if the input had always been silent.
+    pub fn reset(&mut self) {
+        self.state = [0.0; 2];
+    }
+
+    /// Complex gain at `frequency` Hz.
+    pub fn response(&self, frequency: f64, sample_rate: f64) -> Complex<f64> {
+        let z1 = Complex::from_polar(1.0, -2.0 * PI * frequency / sample_rate);
+        let z2 = z1 * z1;
+        (self.b[0] + self.b[1] * z1 + self.b[2] * z2) / (1.0 + self.a[0] * z1 + self.a[1] * z2)
+    }
+}
+
+/// The digital section for an analogue one, (b0·s² + b1·s + b2) / (a0·s² + a1·s + a2),
+/// through the bilinear transform without prewarping.
+pub fn bilinear(b: [f64; 3], a: [f64; 3], sample_rate: f64) -> Biquad {
+    let k = 2.0 * sample_rate;
+    let digital = |c: [f64; 3]| {
+        [
+            c[0] * k * k + c[1] * k + c[2],
+            2.0 * (c[2] - c[0] * k * k),
+            c[0] * k * k - c[1] * k + c[2],
+        ]
+    };
+    let (b, a) = (digital(b), digital(a));
+    Biquad::new(b.map(|b| b / a[0]), [a[1] / a[0], a[2] / a[0]])
+}
+
+/// Filters a sample through a cascade of sections.
+pub fn process_cascade(sections: &mut [Biquad], x: f64) -> f64 {
+    sections.iter_mut().fold(x, |x, section| section.process(x))
+}
+
+/// Complex gain of a cascade of sections at `frequency` Hz.
+pub fn cascade_response(sections: &[Biquad], frequency: f64, sample_rate: f64) -> Complex<f64> {
+    sections
+        .iter()
+        .map(|section| section.response(frequency, sample_rate))
+        .product()
+}
+
+/// A Butterworth band-pass from `lower` to `upper` Hz, where it is 3 dB down, as
+/// `order` sections: the band-pass transform of an `order`-pole low-pass, through the
+/// bilinear transform with both edges prewarped. Unity gain at the geometric centre.
+pub fn butterworth_band_pass(
+    order: usize,
+    lower: f64,
+    upper: f64,
+    sample_rate: f64,
+) -> Vec<Biquad> {
+    let prewarp = |hz: f64| 2.0 * sample_rate * (PI * hz / sample_rate).tan();
+    let (lower, upper) = (prewarp(lower), prewarp(upper));
+    let centre = (lower * upper).sqrt();
+    let bandwidth = upper - lower;
+
+    let mut sections = Vec::with_capacity(order);
+    for k in 0..order {
+        // Each low-pass pole p becomes the roots of s² - pBs + ω0² = 0; those in the
+        // upper half-plane and their conjugates make up the sections.
+        let angle = PI * (2 * k + order + 1) as f64 / (2 * order) as f64;
+        let pole = Complex::from_polar(1.0, angle);
+        let root = (pole * pole * bandwidth * bandwidth - 4.0 * centre * centre).sqrt();
+        for s in [
+            (pole * bandwidth + root) / 2.0,
+            (pole * bandwidth - root) / 2.0,
+        ] {
+            if s.im <= 0.0 {
+                continue;
+            }
+            let z = (1.0 + s / (2.0 * sample_rate)) / (1.0 - s / (2.0 * sample_rate));
+            // Zeros at DC and Nyquist.
+            sections.push(Biquad::new([1.0, 0.0, -1.0], [-2.0 * z.re, z.norm_sqr()]));
+        }
+    }
+
+    let digital_centre = sample_rate / PI * (centre / (2.0 * sample_rate)).atan();
+    for section in &mut sections {
+        let gain = section.response(digital_centre, sample_rate).norm();
+        for b in &mut section.b {
+            *b /= gain;
+        }
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::sine;
+
+    fn gain_db(sections: &[Biquad], frequency: f64) -> f64 {
+        20.0 * cascade_response(sections, frequency, 48000.0)
+            .norm()
+            .log10()
+    }
+
+    #[test]
+    fn band_pass_is_3_db_down_at_its_edges() {
+        for (lower, upper) in [(891.0, 1122.0), (17.8, 22.4), (4467.0, 5623.0)] {
+            let sections = butterworth_band_pass(3, lower, upper, 48000.0);
+            assert_eq!(sections.len(), 3);
+            let centre = (lower * upper).sqrt();
+            assert!(gain_db(&sections, centre).abs() < 0.01);
+            assert!((gain_db(&sections, lower) + 3.01).abs() < 0.05, "{lower}");
+            assert!((gain_db(&sections, upper) + 3.01).abs() < 0.05, "{upper}");
+            // 60 dB per decade on either side, so an octave out is well down.
+            assert!(gain_db(&sections, lower / 2.0) < -40.0);
+            assert!(gain_db(&sections, upper * 2.0) < -40.0);
+        }
+    }
+
+    #[test]
+    fn filtering_matches_the_response() {
+        let mut sections = butterworth_band_pass(3, 891.0, 1122.0, 48000.0);
+        let expected = cascade_response(&sections, 1200.0, 48000.0).norm();
+        let mut sum_squares = 0.0;
+        let (settle, len) = (48000, 48000);
+        for (n, &x) in sine(1200.0, 1.0, 48000, settle + len).iter().enumerate() {
+            let y = process_cascade(&mut sections, x as f64);
+            if n >= settle {
+                sum_squares += y * y;
+            }
+        }
+        let gain = (2.0 * sum_squares / len as f64).sqrt();
+        assert!((gain - expected).abs() < 1e-3, "{gain} vs {expected}");
+    }
+}
@@ -0,0 +1,432 @@
+//! Level measurements of a block of samples, and of a running stream.
+
+use std::f64::consts::PI;
+
+use super::filter::{self, Biquad};
+use super::weighting::Weighting;
+use super::MIN_DB;
+
+/// Samples at or above this magnitude count as clipped: the largest positive 8-bit
+/// value, so that the largest value of every integer format counts.
+pub const CLIP_LEVEL: f32 = 127.0 / 128.0;
+/// How fast the peak readings fall once the signal drops, 20 dB in 1.7 s as in
+/// IEC 60268-18.
+const PEAK_RELEASE_DB_PER_S: f64 = 20.0 / 1.7;
+/// Averaging time of the DC offset of a time-weighted meter, long enough that low
+/// frequencies hardly ripple it.
+const DC_TIME_CONSTANT: f64 = 1.0;
+/// Oversampling factor of the true-peak interpolator, as in ITU-R BS.1770-4.
+const OVERSAMPLING: usize = 4;
+/// Input samples each phase of the interpolator spans.
+const TAPS_PER_PHASE: usize = 12;
+
+/// Level in dB relative to full scale, flooring silence at -200 dB.
+pub fn dbfs(level: f64) -> f64 {
+    (20.0 * level.log10()).max(MIN_DB as f64)
+}
+
+/// Largest absolute sample value.
+pub fn peak(samples: &[f32]) -> f64 {
+    samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs())) as f64
+}
+
+/// RMS level relative to a full-scale sine, like the spectrum, so a full-scale sine
+/// reads 1 and a full-scale square wave reads √2.
+pub fn rms(samples: &[f32]) -> f64 {
+    let mean_square =
+        samples.iter().map(|&s| s as f64 * s as f64).sum::<f64>() / samples.len().max(1) as f64;
+    (mean_square * 2.0).sqrt()
+}
+
+/// The mean of a stream of values, exponentially time-weighted like a sound level
+/// meter's Fast or Slow, or taken over everything since the last reset.
+#[derive(Clone, Copy, Debug)]
+pub struct RunningMean {
+    /// Weight of each new value in the exponential average, or `None` for the plain
+    /// mean.
+    smoothing: Option<f64>,
+    mean: f64,
+    count: u64,
+}
+
+impl RunningMean {
+    /// Time-weighted with a `time_constant` in seconds, for values arriving at
+    /// `sample_rate`; without one, the plain mean.
+    pub fn new(sample_rate: u32, time_constant: Option<f64>) -> Self {
+        let rate = sample_rate as f64;
+        RunningMean {
+            smoothing: time_constant.map(|tau| 1.0 - (-1.0 / (tau * rate)).exp()),
+            mean: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Adds the next value.
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        let weight = self.smoothing.unwrap_or(1.0 / self.count as f64);
+        self.mean += (value - self.mean) * weight;
+    }
+
+    /// The mean so far, 0 before the first value.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Starts over from no values.
+    pub fn reset(&mut self) {
+        self.mean = 0.0;
+        self.count = 0;
+    }
+}
+
+/// The frequency-weighted RMS level of a stream, as on a sound level meter.
+pub struct WeightedLevel {
+    weighting: Weighting,
+    filter: Vec<Biquad>,
+    sample_rate: u32,
+    mean_square: RunningMean,
+}
+
+impl WeightedLevel {
+    /// With a `time_constant` in seconds the level is exponentially time-weighted,
+    /// 0.125 for Fast and 1 for Slow; without one it averages everything.
+    pub fn new(weighting: Weighting, sample_rate: u32, time_constant: Option<f64>) -> Self {
+        WeightedLevel {
+            weighting,
+            filter: weighting.filter(sample_rate),
+            sample_rate,
+            mean_square: RunningMean::new(sample_rate, time_constant),
+        }
+    }
+
+    /// The weighting the filter was designed for.
+    pub fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+
+    /// Sample rate the filter was designed for.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Weights `samples` and adds them to the level.
+    pub fn process(&mut self, samples: &[f32]) {
+        for &x in samples {
+            let y = filter::process_cascade(&mut self.filter, x as f64);
+            self.mean_square.add(y * y);
+        }
+    }
+
+    /// RMS level relative to a full-scale sine, like [`rms`].
+    pub fn level(&self) -> f64 {
+        (self.mean_square.mean() * 2.0).sqrt()
+    }
+
+    /// Clears the filter and level.
+    pub fn reset(&mut self) {
+        self.filter.iter_mut().for_each(Biquad::reset);
+        self.mean_square.reset();
+    }
+}
+
+/// Running sample and true peaks, RMS level, crest factor, DC offset and clipping of
+/// one channel, with the highest peaks held until [`LevelMeter::reset_holds`].
+pub struct LevelMeter {
+    sample_rate: u32,
+    time_constant: Option<f64>,
+    /// Per-sample factor the peaks fall by.
+    release: f64,
+    mean_square: RunningMean,
+    mean: RunningMean,
+    peak: f64,
+    true_peak: f64,
+    peak_hold: f64,
+    true_peak_hold: f64,
+    clips: u64,
+    clipping: bool,
+    /// Interpolation filter taps, phase by phase.
+    interpolator: Vec<[f64; TAPS_PER_PHASE]>,
+    /// The latest inputs, newest first.
+    history: [f64; TAPS_PER_PHASE],
+}
+
+impl LevelMeter {
+    /// With a `time_constant` in seconds the RMS level is exponentially
+    /// time-weighted and the peaks fall back; without one every reading covers
+    /// everything processed, as for a whole file.
+    pub fn new(sample_rate: u32, time_constant: Option<f64>) -> Self {
+        let rate = sample_rate as f64;
+        let release = match time_constant {
+            Some(_) => 10f64.powf(-PEAK_RELEASE_DB_PER_S / 20.0 / rate),
+            None => 1.0,
+        };
+        LevelMeter {
+            sample_rate,
+            time_constant,
+            release,
+            mean_square: RunningMean::new(sample_rate, time_constant),
+            mean: RunningMean::new(sample_rate, time_constant.map(|_| DC_TIME_CONSTANT)),
+            peak: 0.0,
+            true_peak: 0.0,
+            peak_hold: 0.0,
+            true_peak_hold: 0.0,
+            clips: 0,
+            clipping: false,
+            interpolator: interpolator(),
+            history: [0.0; TAPS_PER_PHASE],
+        }
+    }
+
+    /// Sample rate the meter was made for.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The time constant the meter was made with.
+    pub fn time_constant(&self) -> Option<f64> {
+        self.time_constant
+    }
+
+    /// Adds `samples` to every reading.
+    pub fn process(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            let x = sample as f64;
+            self.mean_square.add(x * x);
+            self.mean.add(x);
+
+            self.history.rotate_right(1);
+            self.history[0] = x;
+            let interpolated = self
+                .interpolator
+                .iter()
+                .map(|taps| {
+                    let y: f64 = taps.iter().zip(&self.history).map(|(h, x)| h * x).sum();
+                    y.abs()
+                })
+                .fold(x.abs(), f64::max);
+            self.peak = (self.peak * self.release).max(x.abs());
+            self.true_peak = (self.true_peak * self.release).max(interpolated);
+            self.peak_hold = self.peak_hold.max(self.peak);
+            self.true_peak_hold = self.true_peak_hold.max(self.true_peak);
+
+            let clipping = sample.abs() >= CLIP_LEVEL;
+            if clipping && !self.clipping {
+                self.clips += 1;
+            }
+            self.clipping = clipping;
+        }
+    }
+
+    /// RMS level relative to a full-scale sine, like [`rms`].
+    pub fn rms(&self) -> f64 {
+        (self.mean_square.mean() * 2.0).sqrt()
+    }
+
+    /// Largest sample magnitude, falling back at the release rate.
+    pub fn peak(&self) -> f64 {
+        self.peak
+    }
+
+    /// Largest magnitude of the signal between samples as well, from 4x
+    /// oversampling; reads above full scale when a conversion would clip.
+    pub fn true_peak(&self) -> f64 {
+        self.true_peak
+    }
+
+    /// Highest [`peak`](Self::peak) since the holds were reset.
+    pub fn peak_hold(&self) -> f64 {
+        self.peak_hold
+    }
+
+    /// Highest [`true_peak`](Self::true_peak) since the holds were reset.
+    pub fn true_peak_hold(&self) -> f64 {
+        self.true_peak_hold
+    }
+
+    /// Ratio of the sample peak to the true RMS in dB, 3.01 dB for a sine.
+    pub fn crest_factor_db(&self) -> f64 {
+        dbfs(self.peak) - dbfs(self.mean_square.mean().sqrt())
+    }
+
+    /// Mean sample value, as a fraction of full scale.
+    pub fn dc_offset(&self) -> f64 {
+        self.mean.mean()
+    }
+
+    /// Runs of consecutive samples at or above [`CLIP_LEVEL`] since the holds were
+    /// reset.
+    pub fn clips(&self) -> u64 {
+        self.clips
+    }
+
+    /// Lets the held peaks and clip count start over from the current readings.
+    pub fn reset_holds(&mut self) {
+        self.peak_hold = self.peak;
+        self.true_peak_hold = self.true_peak;
+        self.clips = 0;
+    }
+}
+
+/// A 48-tap windowed-sinc interpolator split into its four phases. They fall 1/8,
+/// 3/8, 5/8 and 7/8 of the way between two inputs, so none repeats one.
+fn interpolator() -> Vec<[f64; TAPS_PER_PHASE]> {
+    let len = OVERSAMPLING * TAPS_PER_PHASE;
+    let centre = (len - 1) as f64 / 2.0;
+    (0..OVERSAMPLING)
+        .map(|phase| {
+            let mut taps = [0.0; TAPS_PER_PHASE];
+            for (k, tap) in taps.iter_mut().enumerate() {
+                let n = k * OVERSAMPLING + phase;
+                let t = (n as f64 - centre) / OVERSAMPLING as f64;
+                let sinc = if t == 0.0 {
+                    1.0
+                } else {
+                    (PI * t).sin() / (PI * t)
+                };
+                let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / len as f64).cos();
+                *tap = sinc * window;
+            }
+            // Unity gain at DC for every phase.
+            let sum: f64 = taps.iter().sum();
+            taps.map(|tap| tap / sum)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::sine;
+
+    #[test]
+    fn sine_levels() {
+        let sine = sine(1000.0, 0.5, 48000, 4800);
+        assert!((peak(&sine) - 0.5).abs() < 1e-6);
+        assert!((rms(&sine) - 0.5).abs() < 1e-6);
+        assert!((dbfs(rms(&sine)) + 6.02).abs() < 0.01);
+    }
+
+    #[test]
+    fn running_means() {
+        let mut plain = RunningMean::new(10, None);
+        let mut weighted = RunningMean::new(10, Some(1.0));
+        for _ in 0..10 {
+            plain.add(1.0);
+            weighted.add(1.0);
+        }
+        plain.add(0.0);
+        assert!((plain.mean() - 10.0 / 11.0).abs() < 1e-12);
+        // One time constant of a step.
+        assert!((weighted.mean() - (1.0 - (-1.0f64).exp())).abs() < 1e-12);
+        weighted.reset();
+        assert_eq!(weighted.mean(), 0.0);
+    }
+
+    #[test]
+    fn square_wave_reads_above_full_scale() {
+        let square = [1.0, -1.0, 1.0, -1.0];
+        assert_eq!(peak(&square), 1.0);
+        assert!((dbfs(rms(&square)) - 3.01).abs() < 0.01);
+    }
+
+    #[test]
+    fn weighted_levels() {
+        for (weighting, frequency) in [
+            (Weighting::Z, 50.0),
+            (Weighting::A, 1000.0),
+            (Weighting::A, 100.0),
+            (Weighting::C, 31.5),
+        ] {
+            let mut meter = WeightedLevel::new(weighting, 48000, None);
+            meter.process(&sine(frequency, 1.0, 48000, 48000));
+            let expected = weighting.gain_db(frequency);
+            assert!(
+                (dbfs(meter.level()) - expected).abs() < 0.1,
+                "{weighting} {frequency}"
+            );
+        }
+
+        // Fast time weighting follows a step within about a second.
+        let mut meter = WeightedLevel::new(Weighting::A, 48000, Some(0.125));
+        meter.process(&sine(1000.0, 1.0, 48000, 48000));
+        assert!(dbfs(meter.level()).abs() < 0.05);
+        meter.reset();
+        assert_eq!(meter.level(), 0.0);
+    }
+
+    #[test]
+    fn true_peak_finds_the_peaks_between_samples() {
+        // A quarter-rate sine whose samples all fall 45° off its peaks, at ±0.707.
+        let quarter_rate: Vec<f32> = (0..4800)
+            .map(|i| (PI / 2.0 * i as f64 + PI / 4.0).sin() as f32)
+            .collect();
+        let mut meter = LevelMeter::new(48000, None);
+        meter.process(&quarter_rate);
+        assert!((dbfs(meter.peak()) + 3.01).abs() < 0.01);
+        assert!(
+            dbfs(meter.true_peak()).abs() < 0.5,
+            "{}",
+            dbfs(meter.true_peak())
+        );
+        assert!((meter.crest_factor_db() - 0.0).abs() < 0.01);
+        assert_eq!(meter.clips(), 0);
+
+        // A low tone reads the same either way.
+        let mut meter = LevelMeter::new(48000, Some(0.125));
+        meter.process(&sine(100.0, 0.5, 48000, 48000));
+        assert!((meter.true_peak() / meter.peak() - 1.0).abs() < 0.01);
+        assert!((dbfs(meter.rms()) + 6.02).abs() < 0.05);
+        assert!((meter.crest_factor_db() - 3.01).abs() < 0.05);
+    }
+
+    #[test]
+    fn meters_hold_peaks_and_count_clips() {
+        let mut meter = LevelMeter::new(48000, Some(0.125));
+        let mut burst = vec![0.25f32; 5 * 48000];
+        burst[100..103].fill(1.0);
+        burst[200] = -1.0;
+        meter.process(&burst);
+        assert_eq!(meter.clips(), 2);
+        assert!((meter.dc_offset() - 0.25).abs() < 0.005);
+        assert_eq!(meter.peak_hold(), 1.0);
+
+        // The peak falls back over a second of quiet while the hold stays.
+        let before = dbfs(meter.peak());
+        meter.process(&vec![0.0; 48000]);
+        assert!((dbfs(meter.peak()) - before + 20.0 / 1.7).abs() < 0.01);
+        assert_eq!(meter.peak_hold(), 1.0);
+
+        meter.reset_holds();
+        assert_eq!(meter.clips(), 0);
+        assert_eq!(meter.peak_hold(), meter.peak());
+    }
+
+    #[test]
+    fn full_scale_counts_as_clipping_in_every_format() {
+        use cpal::Sample;
+        let full_scale = [
+            (i8::MAX.to_sample::<f32>(), i8::MIN.to_sample::<f32>()),
+            (u8::MAX.to_sample(), u8::MIN.to_sample()),
+            (i16::MAX.to_sample(), i16::MIN.to_sample()),
+            (u16::MAX.to_sample(), u16::MIN.to_sample()),
+            (i32::MAX.to_sample(), i32::MIN.to_sample()),
+            (1.0, -1.0),
+        ];
+        for (max, min) in full_scale {
+            let mut meter = LevelMeter::new(48000, None);
+            meter.process(&[0.0, max, 0.0, min, 0.0]);
+            assert_eq!(meter.clips(), 2, "{max} {min}");
+        }
+        // Just below the largest 8-bit value is not clipping yet.
+        let mut meter = LevelMeter::new(48000, None);
+        meter.process(&[126.0 / 128.0, -126.0 / 128.0]);
+        assert_eq!(meter.clips(), 0);
+    }
+
+    #[test]
+    fn silence_is_floored() {
+        assert_eq!(rms(&[]), 0.0);
+        assert_eq!(dbfs(peak(&[0.0; 16])), -200.0);
+    }
+}
@@ -0,0 +1,34 @@
+//! Windowing, FFT analysis, averaging, peak search, fractional-octave bands,
+//! frequency weighting, level metrics, calibration and decimation for plotting.
+
+pub mod averaging;
+pub mod bands;
+pub mod decimate;
+pub mod filter;
+pub mod metrics;
+pub mod peaks;
+pub mod spectrum;
+pub mod units;
+pub mod weighting;
+pub mod window;
+
+/// Floor applied before taking the logarithm so silent bins don't produce -inf.
+pub const MIN_DB: f32 = -200.0;
+
+/// Samples between the starts of consecutive frames for a given overlap, at least one.
+pub fn hop_size(fft_size: usize, overlap_percent: f64) -> usize {
+    ((fft_size as f64 * (1.0 - overlap_percent / 100.0)).round() as usize).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hop_sizes() {
+        assert_eq!(hop_size(2048, 0.0), 2048);
+        assert_eq!(hop_size(2048, 50.0), 1024);
+        assert_eq!(hop_size(2048, 75.0), 512);
+        assert_eq!(hop_size(256, 99.9), 1);
+    }
+}
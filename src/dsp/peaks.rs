@@ -0,0 +1,492 @@
+//! Finding and stepping between peaks of a spectrum or any other sampled curve, and
+//! locating tones between the bins of a spectrum.
+
+use rustfft::num_complex::Complex;
+use std::fmt;
+
+use super::weighting::Weighting;
+use super::window::{Window, WindowFunction};
+use crate::types::Spectrum;
+
+/// Window size [`PeakEstimator`] evaluates responses at; the main lobe's shape in bins
+/// hardly changes beyond it.
+const RESPONSE_SIZE: usize = 1024;
+/// Response table entries per bin of offset.
+const RESPONSE_STEPS: usize = 128;
+/// Offsets the response table covers, in bins: the neighbours of a tone up to half a
+/// bin from the peak bin, with room to spare.
+const RESPONSE_SPAN: usize = 2;
+/// Pitch of A4, which note names are relative to.
+const A4_HZ: f64 = 440.0;
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C♯", "D", "D♯", "E", "F", "F♯", "G", "G♯", "A", "A♯", "B",
+];
+
+/// Indices of the local maxima of `values`, in ascending order. A flat top counts
+/// once, at its first index; the two ends never count.
+pub fn local_maxima(values: &[f32]) -> Vec<usize> {
+    let mut maxima = Vec::new();
+    let mut i = 1;
+    while i + 1 < values.len() {
+        if values[i] > values[i - 1] {
+            let top = i;
+            while i + 1 < values.len() && values[i + 1] == values[top] {
+                i += 1;
+            }
+            if i + 1 < values.len() && values[i + 1] < values[top] {
+                maxima.push(top);
+            }
+        }
+        i += 1;
+    }
+    maxima
+}
+
+/// Up to `count` of the highest local maxima, highest first, skipping any within
+/// `min_separation` indices of a higher one already chosen.
+pub fn strongest_peaks(values: &[f32], count: usize, min_separation: usize) -> Vec<usize> {
+    let mut maxima = local_maxima(values);
+    maxima.sort_by(|&a, &b| values[b].total_cmp(&values[a]));
+    let mut peaks: Vec<usize> = Vec::with_capacity(count);
+    for index in maxima {
+        if peaks.len() == count {
+            break;
+        }
+        if peaks
+            .iter()
+            .all(|&peak| peak.abs_diff(index) > min_separation)
+        {
+            peaks.push(index);
+        }
+    }
+    peaks
+}
+
+/// Which peak [`find_peak`] moves to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeakSearch {
+    /// The highest peak.
+    Highest,
+    /// The highest peak below the current level.
+    NextLower,
+    /// The nearest peak at a lower index.
+    Left,
+    /// The nearest peak at a higher index.
+    Right,
+}
+
+impl PeakSearch {
+    /// Every search, in button order.
+    pub const ALL: [PeakSearch; 4] = [
+        PeakSearch::Highest,
+        PeakSearch::NextLower,
+        PeakSearch::Left,
+        PeakSearch::Right,
+    ];
+}
+
+impl fmt::Display for PeakSearch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PeakSearch::Highest => write!(f, "Peak"),
+            PeakSearch::NextLower => write!(f, "Next peak"),
+            PeakSearch::Left => write!(f, "Peak left"),
+            PeakSearch::Right => write!(f, "Peak right"),
+        }
+    }
+}
+
+/// The local maximum that `search` leads to from index `from`, if there is one.
+/// Peaks of equal level are stepped through from left to right.
+pub fn find_peak(values: &[f32], from: usize, search: PeakSearch) -> Option<usize> {
+    let maxima = local_maxima(values);
+    // By level, with the leftmost of equal levels counting as the higher.
+    let cmp = |a: usize, b: usize| values[a].total_cmp(&values[b]).then(b.cmp(&a));
+    match search {
+        PeakSearch::Highest => maxima.into_iter().max_by(|&a, &b| cmp(a, b)),
+        PeakSearch::NextLower => {
+            let from = from.min(values.len().checked_sub(1)?);
+            maxima
+                .into_iter()
+                .filter(|&i| cmp(i, from).is_lt())
+                .max_by(|&a, &b| cmp(a, b))
+        }
+        PeakSearch::Left => maxima.into_iter().rev().find(|&i| i < from),
+        PeakSearch::Right => maxima.into_iter().find(|&i| i > from),
+    }
+}
+
+/// How a peak's position between bins is estimated from the bins around it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The centre of the peak bin.
+    None,
+    /// The vertex of a parabola through the linear magnitudes of the peak bin and its
+    /// neighbours.
+    Parabolic,
+    /// The vertex of a parabola through their levels in dB, which is exact for a
+    /// Gaussian main lobe and close for most windows.
+    Gaussian,
+    /// Jacobsen's estimator on the complex bins, which is Quinn's for the rectangular
+    /// window, with its bias for the window in use removed. Averaged spectra have no
+    /// phases, so for those a ratio of the magnitudes is matched to the window instead.
+    Jacobsen,
+}
+
+impl Interpolation {
+    /// Every estimator, roughly from coarsest to finest.
+    pub const ALL: [Interpolation; 4] = [
+        Interpolation::None,
+        Interpolation::Parabolic,
+        Interpolation::Gaussian,
+        Interpolation::Jacobsen,
+    ];
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Interpolation::None => write!(f, "None (bin centre)"),
+            Interpolation::Parabolic => write!(f, "Parabolic"),
+            Interpolation::Gaussian => write!(f, "Gaussian"),
+            Interpolation::Jacobsen => write!(f, "Quinn/Jacobsen"),
+        }
+    }
+}
+
+/// A tone located between the bins of a spectrum.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    /// The local maximum the tone was found at.
+    pub bin: usize,
+    /// Position of the tone relative to `bin`, within half a bin either way.
+    pub offset: f64,
+    /// Frequency of the tone, in Hz.
+    pub frequency: f64,
+    /// Level of `bin` corrected for the window's response at `offset`, which is the
+    /// tone's level under [`Scaling::Amplitude`](super::spectrum::Scaling::Amplitude).
+    pub level_db: f32,
+}
+
+/// Estimates tone frequencies and levels from spectra taken with one window, using a
+/// table of the window's response built once.
+pub struct PeakEstimator {
+    window: WindowFunction,
+    /// [`Window::response`] every `1 / RESPONSE_STEPS` bins from 0 to `RESPONSE_SPAN`.
+    response: Vec<f64>,
+}
+
+impl PeakEstimator {
+    /// Tabulates the response of `window`.
+    pub fn new(window: WindowFunction) -> Self {
+        let evaluated = Window::new(window, RESPONSE_SIZE);
+        let response = (0..=RESPONSE_SPAN * RESPONSE_STEPS)
+            .map(|i| evaluated.response(i as f64 / RESPONSE_STEPS as f64))
+            .collect();
+        PeakEstimator { window, response }
+    }
+
+    /// The window the spectra are expected to be taken with.
+    pub fn window(&self) -> WindowFunction {
+        self.window
+    }
+
+    /// The window's amplitude response `offset` bins from a bin centre, relative to 1
+    /// on it.
+    pub fn response(&self, offset: f64) -> f64 {
+        let last = self.response.len() - 1;
+        let x = (offset.abs() * RESPONSE_STEPS as f64).min(last as f64);
+        let i = (x as usize).min(last - 1);
+        let t = x - i as f64;
+        self.response[i] * (1.0 - t) + self.response[i + 1] * t
+    }
+
+    /// Locates the tone at local maximum `bin` of `spectrum`. Bins at either end of
+    /// the spectrum are taken as they are.
+    pub fn estimate(&self, spectrum: &Spectrum, bin: usize, interpolation: Interpolation) -> Peak {
+        let levels = &spectrum.magnitudes_db;
+        let offset = if bin == 0 || bin + 1 >= levels.len() {
+            0.0
+        } else {
+            let around = [bin - 1, bin, bin + 1];
+            let db = around.map(|k| levels[k] as f64);
+            let bins = around.map(|k| {
+                Complex::from_polar(amplitude(levels[k] as f64), spectrum.phases[k] as f64)
+            });
+            match interpolation {
+                Interpolation::None => 0.0,
+                Interpolation::Parabolic => parabola_vertex(db.map(amplitude)),
+                Interpolation::Gaussian => parabola_vertex(db),
+                Interpolation::Jacobsen if bins.iter().all(|c| c.is_finite()) => {
+                    self.jacobsen(bins)
+                }
+                Interpolation::Jacobsen => self.magnitude_ratio(db.map(amplitude)),
+            }
+        };
+        let correction_db = -20.0 * self.response(offset).log10();
+        Peak {
+            bin,
+            offset,
+            frequency: (bin as f64 + offset) * spectrum.bin_width(),
+            level_db: (levels[bin] as f64 + correction_db) as f32,
+        }
+    }
+
+    /// Up to `count` of the highest peaks of `spectrum` above DC, highest first and at
+    /// least `min_separation` bins apart, located with `interpolation`.
+    pub fn strongest(
+        &self,
+        spectrum: &Spectrum,
+        count: usize,
+        min_separation: usize,
+        interpolation: Interpolation,
+    ) -> Vec<Peak> {
+        let Some(levels) = spectrum.magnitudes_db.get(1..) else {
+            return Vec::new();
+        };
+        strongest_peaks(levels, count, min_separation)
+            .into_iter()
+            .map(|i| self.estimate(spectrum, i + 1, interpolation))
+            .collect()
+    }
+
+    /// The same for a spectrum `weighting` has been applied to. The weighting tilts
+    /// the bins around a tone and shifts its estimate, so each peak of the weighted
+    /// spectrum is located in the unweighted one and then given its weighted level.
+    pub fn strongest_weighted(
+        &self,
+        spectrum: &Spectrum,
+        weighting: Weighting,
+        count: usize,
+        min_separation: usize,
+        interpolation: Interpolation,
+    ) -> Vec<Peak> {
+        if weighting == Weighting::Z {
+            return self.strongest(spectrum, count, min_separation, interpolation);
+        }
+        let Some(levels) = spectrum.magnitudes_db.get(1..) else {
+            return Vec::new();
+        };
+        let mut plain = spectrum.clone();
+        weighting.remove(&mut plain);
+        let plain_levels = &plain.magnitudes_db;
+        strongest_peaks(levels, count, min_separation)
+            .into_iter()
+            .map(|i| {
+                // Climb to the tone's own maximum, which may be a bin off.
+                let mut bin = i + 1;
+                while bin > 1 && plain_levels[bin - 1] > plain_levels[bin] {
+                    bin -= 1;
+                }
+                while bin + 1 < plain_levels.len() && plain_levels[bin + 1] > plain_levels[bin] {
+                    bin += 1;
+                }
+                let mut peak = self.estimate(&plain, bin, interpolation);
+                peak.level_db = (peak.level_db as f64 + weighting.gain_db(peak.frequency)) as f32;
+                peak
+            })
+            .collect()
+    }
+
+    /// Jacobsen's ratio is the offset itself for the rectangular window; for others,
+    /// the offset is found whose expected ratio under this window matches it.
+    fn jacobsen(&self, [left, centre, right]: [Complex<f64>; 3]) -> f64 {
+        let ratio = ((left - right) / (2.0 * centre - left - right)).re;
+        // Neighbouring bins alternate in sign around a tone, so these add up.
+        self.offset_for(ratio, |left, centre, right| {
+            (right - left) / (2.0 * centre + left + right)
+        })
+    }
+
+    /// The same from magnitudes alone, for spectra without phases.
+    fn magnitude_ratio(&self, [left, centre, right]: [f64; 3]) -> f64 {
+        let ratio = (right - left) / (left + centre + right);
+        self.offset_for(ratio, |left, centre, right| {
+            (right.abs() - left.abs()) / (left.abs() + centre.abs() + right.abs())
+        })
+    }
+
+    /// The offset at which `expected`, given the window's response at the left,
+    /// centre and right bins, equals `ratio`. The expected ratio rises monotonically
+    /// across the bin, so this is a bisection.
+    fn offset_for(&self, ratio: f64, expected: impl Fn(f64, f64, f64) -> f64) -> f64 {
+        if !ratio.is_finite() {
+            return 0.0;
+        }
+        let (mut low, mut high) = (-0.5, 0.5);
+        for _ in 0..32 {
+            let mid = 0.5 * (low + high);
+            let at_mid = expected(
+                self.response(1.0 + mid),
+                self.response(mid),
+                self.response(1.0 - mid),
+            );
+            if at_mid < ratio {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        0.5 * (low + high)
+    }
+}
+
+/// The nearest equal-tempered note to a frequency, with A4 at 440 Hz, and how many
+/// cents it is off, e.g. "A4 +3¢".
+pub fn note_name(frequency: f64) -> Option<String> {
+    if !(frequency > 0.0 && frequency.is_finite()) {
+        return None;
+    }
+    // MIDI note numbers, where A4 is 69.
+    let semitones = 69.0 + 12.0 * (frequency / A4_HZ).log2();
+    let note = semitones.round();
+    let cents = ((semitones - note) * 100.0).round() as i64;
+    let note = note as i64;
+    let name = NOTE_NAMES[note.rem_euclid(12) as usize];
+    Some(format!("{name}{} {cents:+}¢", note.div_euclid(12) - 1))
+}
+
+fn amplitude(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// Offset of the vertex of the parabola through three evenly spaced values from the
+/// middle one, which is the highest; zero if they are flat.
+fn parabola_vertex([left, centre, right]: [f64; 3]) -> f64 {
+    let curvature = left - 2.0 * centre + right;
+    if curvature < 0.0 {
+        (0.5 * (left - right) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::spectrum::SpectrumAnalyzer;
+    use crate::source::sine;
+
+    const VALUES: [f32; 10] = [0.0, 3.0, 1.0, 5.0, 5.0, 2.0, 4.0, 0.0, 3.0, 9.0];
+
+    #[test]
+    fn finds_local_maxima() {
+        assert_eq!(local_maxima(&VALUES), vec![1, 3, 6]);
+        assert_eq!(local_maxima(&[1.0, 2.0]), Vec::<usize>::new());
+        assert_eq!(local_maxima(&[0.0, 1.0, 1.0, 2.0, 0.0]), vec![3]);
+    }
+
+    #[test]
+    fn strongest_peaks_keep_their_distance() {
+        assert_eq!(strongest_peaks(&VALUES, 3, 0), vec![3, 6, 1]);
+        assert_eq!(strongest_peaks(&VALUES, 3, 2), vec![3, 6]);
+        assert_eq!(strongest_peaks(&VALUES, 1, 0), vec![3]);
+    }
+
+    #[test]
+    fn steps_between_peaks() {
+        let find = |from, search| find_peak(&VALUES, from, search);
+        assert_eq!(find(0, PeakSearch::Highest), Some(3));
+        assert_eq!(find(3, PeakSearch::NextLower), Some(6));
+        assert_eq!(find(6, PeakSearch::NextLower), Some(1));
+        assert_eq!(find(1, PeakSearch::NextLower), None);
+        assert_eq!(find(6, PeakSearch::Left), Some(3));
+        assert_eq!(find(3, PeakSearch::Right), Some(6));
+        assert_eq!(find(6, PeakSearch::Right), None);
+        assert_eq!(find(0, PeakSearch::NextLower), None);
+    }
+
+    /// A half-scale tone `offset` bins from bin 100 of a 4096-point transform.
+    fn tone_spectrum(window: WindowFunction, offset: f64) -> Spectrum {
+        let samples = sine((100.0 + offset) * 48000.0 / 4096.0, 0.5, 48000, 4096);
+        SpectrumAnalyzer::new(4096, window)
+            .process(&samples, 48000)
+            .unwrap()
+    }
+
+    #[test]
+    fn estimators_locate_a_tone_between_bins() {
+        // Worst offset error in bins and level error in dB that each should stay within.
+        let tolerances = [
+            (Interpolation::None, 0.5, 1.5),
+            (Interpolation::Parabolic, 0.06, 0.2),
+            (Interpolation::Gaussian, 0.02, 0.06),
+            (Interpolation::Jacobsen, 0.001, 0.01),
+        ];
+        for window in [
+            WindowFunction::Hann,
+            WindowFunction::BlackmanHarris,
+            WindowFunction::Kaiser { beta: 8.6 },
+        ] {
+            let estimator = PeakEstimator::new(window);
+            for offset in [-0.45, -0.2, 0.0, 0.3] {
+                let spectrum = tone_spectrum(window, offset);
+                for (interpolation, max_offset_error, max_level_error) in tolerances {
+                    let peaks = estimator.strongest(&spectrum, 1, 0, interpolation);
+                    let peak = peaks[0];
+                    let offset_error = (peak.bin as f64 + peak.offset - 100.0 - offset).abs();
+                    let level_error = (peak.level_db + 6.02).abs();
+                    assert!(
+                        offset_error <= max_offset_error,
+                        "{window} {interpolation} at {offset}: off by {offset_error} bins"
+                    );
+                    assert!(
+                        level_error <= max_level_error,
+                        "{window} {interpolation} at {offset}: off by {level_error} dB"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn jacobsen_is_exact_for_the_rectangular_window() {
+        let estimator = PeakEstimator::new(WindowFunction::Rectangular);
+        for offset in [-0.45, -0.2, 0.3] {
+            let spectrum = tone_spectrum(WindowFunction::Rectangular, offset);
+            let peak = estimator.strongest(&spectrum, 1, 0, Interpolation::Jacobsen)[0];
+            assert!((peak.bin as f64 + peak.offset - 100.0 - offset).abs() < 1e-4);
+            assert!((peak.level_db + 6.02).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn jacobsen_falls_back_to_magnitudes_without_phases() {
+        for window in [WindowFunction::Rectangular, WindowFunction::FlatTop] {
+            let estimator = PeakEstimator::new(window);
+            for offset in [-0.45, 0.3] {
+                let mut spectrum = tone_spectrum(window, offset);
+                spectrum.phases.fill(f32::NAN);
+                let peak = estimator.estimate(&spectrum, 100, Interpolation::Jacobsen);
+                assert!((peak.offset - offset).abs() < 0.005, "{window} at {offset}");
+                assert!((peak.level_db + 6.02).abs() < 0.05, "{window} at {offset}");
+            }
+        }
+    }
+
+    #[test]
+    fn weighted_peaks_are_located_unweighted() {
+        // A tone where A-weighting rises by about 2 dB a bin.
+        let samples = sine(9.4 * 48000.0 / 4096.0, 0.5, 48000, 4096);
+        let mut spectrum = SpectrumAnalyzer::new(4096, WindowFunction::Hann)
+            .process(&samples, 48000)
+            .unwrap();
+        Weighting::A.apply(&mut spectrum);
+        let estimator = PeakEstimator::new(WindowFunction::Hann);
+        let peak =
+            estimator.strongest_weighted(&spectrum, Weighting::A, 1, 0, Interpolation::Jacobsen)[0];
+        assert!((peak.bin as f64 + peak.offset - 9.4).abs() < 0.01);
+        let expected = -6.02 + Weighting::A.gain_db(peak.frequency);
+        assert!((peak.level_db as f64 - expected).abs() < 0.05);
+    }
+
+    #[test]
+    fn note_names() {
+        assert_eq!(note_name(440.0).unwrap(), "A4 +0¢");
+        assert_eq!(note_name(261.63).unwrap(), "C4 +0¢");
+        assert_eq!(note_name(27.5).unwrap(), "A0 +0¢");
+        assert_eq!(note_name(450.0).unwrap(), "A4 +39¢");
+        assert_eq!(note_name(1000.0).unwrap(), "B5 +21¢");
+        assert_eq!(note_name(0.0), None);
+    }
+}
@@ -0,0 +1,262 @@
+//! Magnitude spectra of single frames.
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+use super::window::{Window, WindowFunction};
+use super::MIN_DB;
+use crate::types::Spectrum;
+
+/// How spectrum levels are normalized for the window in use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scaling {
+    /// Coherent-gain corrected, so a sinusoid reads its true amplitude under any window.
+    Amplitude,
+    /// Additionally corrected for the window's ENBW, so broadband noise reads the same
+    /// level under any window.
+    Noise,
+    /// One-sided power spectral density in dBFS/Hz, relative to the power of a
+    /// full-scale sine. Noise floors read the same under any window and FFT size;
+    /// averaged over overlapping frames this is Welch's method.
+    Psd,
+}
+
+impl Scaling {
+    /// Every scaling, in display order.
+    pub const ALL: [Scaling; 3] = [Scaling::Amplitude, Scaling::Noise, Scaling::Psd];
+}
+
+impl std::fmt::Display for Scaling {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Scaling::Amplitude => write!(f, "Amplitude"),
+            Scaling::Noise => write!(f, "Noise (ENBW)"),
+            Scaling::Psd => write!(f, "PSD (Welch)"),
+        }
+    }
+}
+
+/// Computes windowed FFT spectra, reusing its plan and buffers between frames.
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Window,
+    scaling: Scaling,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl SpectrumAnalyzer {
+    /// Plans an `fft_size`-point transform with amplitude scaling.
+    pub fn new(fft_size: usize, window: WindowFunction) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+        SpectrumAnalyzer {
+            fft,
+            window: Window::new(window, fft_size),
+            scaling: Scaling::Amplitude,
+            buffer: vec![Complex::default(); fft_size],
+            scratch,
+        }
+    }
+
+    /// Re-plans the transform for a new size, keeping the window and scaling.
+    pub fn set_fft_size(&mut self, fft_size: usize) {
+        if fft_size != self.fft_size() {
+            let scaling = self.scaling;
+            *self = SpectrumAnalyzer::new(fft_size, self.window.function);
+            self.scaling = scaling;
+        }
+    }
+
+    /// Transform length in samples.
+    pub fn fft_size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The window applied before each transform.
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    /// Switches to another window function at the current size.
+    pub fn set_window(&mut self, window: WindowFunction) {
+        if self.window.function != window {
+            self.window = Window::new(window, self.fft_size());
+        }
+    }
+
+    /// How levels are normalized for the window.
+    pub fn scaling(&self) -> Scaling {
+        self.scaling
+    }
+
+    /// Changes the normalization for subsequent spectra.
+    pub fn set_scaling(&mut self, scaling: Scaling) {
+        self.scaling = scaling;
+    }
+
+    /// Windows and transforms the most recent `fft_size` samples into a one-sided
+    /// magnitude spectrum in dBFS, where a full-scale sine reads 0 dB, or a density
+    /// in dBFS/Hz with [`Scaling::Psd`].
+    pub fn process(&mut self, samples: &[f32], sample_rate: u32) -> Option<Spectrum> {
+        let n = self.fft_size();
+        if samples.len() < n {
+            return None;
+        }
+
+        let block = &samples[samples.len() - n..];
+        for ((dst, &src), &w) in self
+            .buffer
+            .iter_mut()
+            .zip(block)
+            .zip(&self.window.coefficients)
+        {
+            *dst = Complex::new(src * w, 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        let bins = n / 2 + 1;
+        let norm = n as f32 * self.window.coherent_gain;
+        let bin_width = sample_rate as f32 / n as f32;
+        let offset_db = match self.scaling {
+            Scaling::Amplitude => 0.0,
+            Scaling::Noise => -10.0 * self.window.enbw.log10(),
+            // A sine's power spread over the window's noise bandwidth in Hz.
+            Scaling::Psd => -10.0 * (self.window.enbw * bin_width).log10(),
+        };
+        let phases = self.buffer[..bins].iter().map(|c| c.arg()).collect();
+        let magnitudes_db = self.buffer[..bins]
+            .iter()
+            .enumerate()
+            .map(|(k, c)| {
+                // DC and Nyquist have no mirrored negative-frequency bin. As power
+                // relative to a full-scale sine's 1/2, a constant d counts as 2d².
+                let edge = k == 0 || (n.is_multiple_of(2) && k == n / 2);
+                let scale = match (edge, self.scaling) {
+                    (false, _) => 2.0,
+                    (true, Scaling::Psd) => std::f32::consts::SQRT_2,
+                    (true, _) => 1.0,
+                };
+                let amplitude = c.norm() * scale / norm;
+                (20.0 * amplitude.log10() + offset_db).max(MIN_DB)
+            })
+            .collect();
+
+        Some(Spectrum {
+            sample_rate,
+            fft_size: n,
+            magnitudes_db,
+            phases,
+            averages: 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::sine;
+
+    #[test]
+    fn full_scale_sine_reads_zero_db_under_any_window() {
+        // Bin 64 of a 1024-point transform at 48 kHz.
+        let samples = sine(3000.0, 1.0, 48000, 1024);
+        for window in WindowFunction::ALL {
+            let mut analyzer = SpectrumAnalyzer::new(1024, window);
+            let spectrum = analyzer.process(&samples, 48000).unwrap();
+            assert_eq!(spectrum.magnitudes_db.len(), 513);
+            assert!(spectrum.magnitudes_db[64].abs() < 0.01, "{window}");
+        }
+    }
+
+    #[test]
+    fn half_scale_sine_reads_minus_six_db() {
+        let samples = sine(3000.0, 0.5, 48000, 1024);
+        let mut analyzer = SpectrumAnalyzer::new(1024, WindowFunction::Hann);
+        let spectrum = analyzer.process(&samples, 48000).unwrap();
+        assert!((spectrum.magnitudes_db[64] + 6.02).abs() < 0.01);
+    }
+
+    #[test]
+    fn analyzes_the_most_recent_samples() {
+        let mut samples = vec![0.0; 4096];
+        samples.extend(sine(3000.0, 1.0, 48000, 1024));
+        let mut analyzer = SpectrumAnalyzer::new(1024, WindowFunction::Hann);
+        let spectrum = analyzer.process(&samples, 48000).unwrap();
+        assert!(spectrum.magnitudes_db[64].abs() < 0.01);
+        assert!(analyzer.process(&samples[..1000], 48000).is_none());
+    }
+
+    #[test]
+    fn silence_is_floored() {
+        let mut analyzer = SpectrumAnalyzer::new(256, WindowFunction::Hann);
+        let spectrum = analyzer.process(&[0.0; 256], 48000).unwrap();
+        assert!(spectrum.magnitudes_db.iter().all(|&db| db == MIN_DB));
+    }
+
+    /// Mean power of bins 16 and up, in dB.
+    fn mean_level(spectrum: &Spectrum) -> f64 {
+        let bins = &spectrum.magnitudes_db[16..];
+        let power: f64 = bins.iter().map(|&db| 10f64.powf(db as f64 / 10.0)).sum();
+        10.0 * (power / bins.len() as f64).log10()
+    }
+
+    #[test]
+    fn psd_of_white_noise_is_independent_of_fft_size_and_window() {
+        let mut generator =
+            crate::source::Generator::new(crate::source::Signal::WhiteNoise, 1.0, 48000, 1);
+        let mut samples = Vec::new();
+        generator.generate(1 << 18, &mut samples);
+        // Uniform noise in [-1, 1) has a mean square of 1/3, twice that relative to a
+        // full-scale sine, spread over 24 kHz.
+        let expected = 10.0 * (2.0 / 3.0 / 24000.0f64).log10();
+        for (fft_size, window) in [
+            (1024, WindowFunction::Hann),
+            (8192, WindowFunction::Hann),
+            (4096, WindowFunction::BlackmanHarris),
+            (4096, WindowFunction::Rectangular),
+        ] {
+            let mut analyzer = SpectrumAnalyzer::new(fft_size, window);
+            analyzer.set_scaling(Scaling::Psd);
+            let spectrum = crate::dsp::averaging::average_spectrum(
+                &mut analyzer,
+                &samples,
+                fft_size / 2,
+                48000,
+            )
+            .unwrap();
+            let level = mean_level(&spectrum);
+            assert!(
+                (level - expected).abs() < 0.1,
+                "{fft_size} {window}: {level}"
+            );
+        }
+    }
+
+    #[test]
+    fn psd_of_a_sine_spreads_over_the_noise_bandwidth() {
+        let mut analyzer = SpectrumAnalyzer::new(1024, WindowFunction::Hann);
+        analyzer.set_scaling(Scaling::Psd);
+        let spectrum = analyzer
+            .process(&sine(3000.0, 1.0, 48000, 1024), 48000)
+            .unwrap();
+        // 1.5 bins of 46.875 Hz.
+        let expected = -10.0 * (1.5f32 * 46.875).log10();
+        assert!((spectrum.magnitudes_db[64] - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn noise_scaling_removes_the_enbw() {
+        let mut analyzer = SpectrumAnalyzer::new(1024, WindowFunction::Hann);
+        analyzer.set_scaling(Scaling::Noise);
+        analyzer.set_fft_size(2048);
+        assert_eq!(analyzer.scaling(), Scaling::Noise);
+        let spectrum = analyzer
+            .process(&sine(3000.0, 1.0, 48000, 2048), 48000)
+            .unwrap();
+        // Hann has an ENBW of 1.5 bins.
+        assert!((spectrum.magnitudes_db[128] + 1.76).abs() < 0.01);
+    }
+}
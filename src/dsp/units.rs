@@ -0,0 +1,214 @@
+//! Calibration from digital full scale to volts and physical units.
+
+use std::fmt;
+
+/// RMS voltage of 0 dBu, the voltage that dissipates 1 mW in 600 Ω.
+pub const DBU_REFERENCE_VOLTS: f64 = 0.774_596_7;
+/// Sound pressure of 0 dB SPL, in pascals.
+pub const SPL_REFERENCE_PASCALS: f64 = 20e-6;
+
+/// How samples relate to the voltage at the converter and to the quantity a sensor
+/// measures.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    /// RMS voltage of a full-scale sine, e.g. 1.228 V where 0 dBFS is +4 dBu.
+    pub full_scale_volts: f64,
+    /// Sensor output per unit, e.g. 0.012 V/Pa for a microphone.
+    pub volts_per_unit: f64,
+    /// Name of the measured unit, e.g. "Pa" or "g".
+    pub unit: String,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            full_scale_volts: 1.0,
+            volts_per_unit: 1.0,
+            unit: "Pa".into(),
+        }
+    }
+}
+
+impl Calibration {
+    /// Decibels that turn a level relative to a full-scale sine into one relative to
+    /// 1 V RMS.
+    pub fn volts_offset_db(&self) -> f64 {
+        20.0 * self.full_scale_volts.log10()
+    }
+
+    /// Decibels that turn a level relative to a full-scale sine into one relative to
+    /// one unit RMS.
+    pub fn unit_offset_db(&self) -> f64 {
+        self.volts_offset_db() - 20.0 * self.volts_per_unit.log10()
+    }
+}
+
+/// How a magnitude spectrum's levels are shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MagnitudeScale {
+    /// Amplitude as a fraction of a full-scale sine.
+    Linear,
+    /// Decibels relative to a full-scale sine.
+    Dbfs,
+    /// Decibels relative to 1 V RMS.
+    Dbv,
+    /// Decibels relative to 0.775 V RMS.
+    Dbu,
+    /// Decibels relative to 20 µPa, taking the calibrated unit to be pascals.
+    DbSpl,
+}
+
+impl MagnitudeScale {
+    /// Every scale, in display order.
+    pub const ALL: [MagnitudeScale; 5] = [
+        MagnitudeScale::Linear,
+        MagnitudeScale::Dbfs,
+        MagnitudeScale::Dbv,
+        MagnitudeScale::Dbu,
+        MagnitudeScale::DbSpl,
+    ];
+
+    /// Decibels to add to a level in dBFS; for [`MagnitudeScale::Linear`], before
+    /// converting to an amplitude.
+    pub fn offset_db(self, calibration: &Calibration) -> f64 {
+        match self {
+            MagnitudeScale::Linear | MagnitudeScale::Dbfs => 0.0,
+            MagnitudeScale::Dbv => calibration.volts_offset_db(),
+            MagnitudeScale::Dbu => {
+                calibration.volts_offset_db() - 20.0 * DBU_REFERENCE_VOLTS.log10()
+            }
+            MagnitudeScale::DbSpl => {
+                calibration.unit_offset_db() - 20.0 * SPL_REFERENCE_PASCALS.log10()
+            }
+        }
+    }
+
+    /// Whether levels are in decibels rather than a plain ratio.
+    pub fn is_db(self) -> bool {
+        self != MagnitudeScale::Linear
+    }
+
+    /// Whether the scale depends on [`Calibration::full_scale_volts`].
+    pub fn needs_calibration(self) -> bool {
+        matches!(
+            self,
+            MagnitudeScale::Dbv | MagnitudeScale::Dbu | MagnitudeScale::DbSpl
+        )
+    }
+
+    /// The unit of the levels, e.g. "dBu".
+    pub fn unit(self) -> &'static str {
+        match self {
+            MagnitudeScale::Linear => "FS",
+            MagnitudeScale::Dbfs => "dBFS",
+            MagnitudeScale::Dbv => "dBV",
+            MagnitudeScale::Dbu => "dBu",
+            MagnitudeScale::DbSpl => "dB SPL",
+        }
+    }
+}
+
+impl fmt::Display for MagnitudeScale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MagnitudeScale::Linear => write!(f, "Linear"),
+            scale => write!(f, "{}", scale.unit()),
+        }
+    }
+}
+
+/// What a power spectral density is reported relative to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DensityUnit {
+    /// Power of a full-scale sine per hertz.
+    DbfsPerHz,
+    /// V²/Hz at the converter input, through [`Calibration::full_scale_volts`].
+    VoltsSquaredPerHz,
+    /// Units²/Hz at the sensor, through the whole [`Calibration`].
+    UnitsSquaredPerHz,
+}
+
+impl DensityUnit {
+    /// Every unit, in display order.
+    pub const ALL: [DensityUnit; 3] = [
+        DensityUnit::DbfsPerHz,
+        DensityUnit::VoltsSquaredPerHz,
+        DensityUnit::UnitsSquaredPerHz,
+    ];
+
+    /// Decibels to add to a density in dBFS/Hz.
+    pub fn offset_db(self, calibration: &Calibration) -> f64 {
+        match self {
+            DensityUnit::DbfsPerHz => 0.0,
+            DensityUnit::VoltsSquaredPerHz => calibration.volts_offset_db(),
+            DensityUnit::UnitsSquaredPerHz => calibration.unit_offset_db(),
+        }
+    }
+
+    /// The linear unit, e.g. "V²/Hz".
+    pub fn linear_label(self, calibration: &Calibration) -> String {
+        match self {
+            DensityUnit::DbfsPerHz => "FS²/Hz".into(),
+            DensityUnit::VoltsSquaredPerHz => "V²/Hz".into(),
+            DensityUnit::UnitsSquaredPerHz => format!("{}²/Hz", calibration.unit),
+        }
+    }
+
+    /// The unit of the decibel values, e.g. "dB re 1 V²/Hz".
+    pub fn db_label(self, calibration: &Calibration) -> String {
+        match self {
+            DensityUnit::DbfsPerHz => "dBFS/Hz".into(),
+            unit => format!("dB re 1 {}", unit.linear_label(calibration)),
+        }
+    }
+}
+
+impl fmt::Display for DensityUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DensityUnit::DbfsPerHz => write!(f, "dBFS/Hz"),
+            DensityUnit::VoltsSquaredPerHz => write!(f, "V²/Hz"),
+            DensityUnit::UnitsSquaredPerHz => write!(f, "Units²/Hz"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magnitude_scales() {
+        // 0 dBFS = +4 dBu, and a 12 mV/Pa microphone.
+        let calibration = Calibration {
+            full_scale_volts: 1.227_8,
+            volts_per_unit: 0.012,
+            unit: "Pa".into(),
+        };
+        let level = |scale: MagnitudeScale, dbfs| dbfs + scale.offset_db(&calibration);
+        assert_eq!(level(MagnitudeScale::Linear, -20.0), -20.0);
+        assert_eq!(level(MagnitudeScale::Dbfs, -20.0), -20.0);
+        assert!((level(MagnitudeScale::Dbu, 0.0) - 4.0).abs() < 0.01);
+        assert!((level(MagnitudeScale::Dbv, 0.0) - 1.78).abs() < 0.01);
+        // 1 Pa is 94 dB SPL, and gives 12 mV or -40.2 dBFS here.
+        let one_pascal = 20.0 * (0.012f64 / 1.227_8).log10();
+        assert!((level(MagnitudeScale::DbSpl, one_pascal) - 93.98).abs() < 0.01);
+    }
+
+    #[test]
+    fn offsets_follow_the_calibration() {
+        let calibration = Calibration {
+            full_scale_volts: 10.0,
+            volts_per_unit: 0.01,
+            unit: "Pa".into(),
+        };
+        let offset = |unit: DensityUnit| unit.offset_db(&calibration);
+        assert_eq!(offset(DensityUnit::DbfsPerHz), 0.0);
+        assert!((offset(DensityUnit::VoltsSquaredPerHz) - 20.0).abs() < 1e-9);
+        assert!((offset(DensityUnit::UnitsSquaredPerHz) - 60.0).abs() < 1e-9);
+        assert_eq!(
+            DensityUnit::UnitsSquaredPerHz.db_label(&calibration),
+            "dB re 1 Pa²/Hz"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Window functions and their correction factors.
+
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Taper applied to each frame before the FFT to limit spectral leakage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowFunction {
+    /// No taper: the narrowest main lobe, but the worst leakage.
+    Rectangular,
+    /// Raised cosine; a good general-purpose default.
+    Hann,
+    /// Raised cosine with a lower first sidelobe than Hann.
+    Hamming,
+    /// 4-term Blackman-Harris, with sidelobes below -92 dB.
+    BlackmanHarris,
+    /// Nearly flat main lobe, for reading tone amplitudes between bins.
+    FlatTop,
+    /// Kaiser-Bessel; larger `beta` trades a wider main lobe for lower sidelobes.
+    Kaiser {
+        /// Shape parameter.
+        beta: f32,
+    },
+    /// Gaussian with standard deviation `sigma` relative to half the frame.
+    Gaussian {
+        /// Width parameter.
+        sigma: f32,
+    },
+}
+
+impl WindowFunction {
+    /// Every window with its default shape parameter, in display order.
+    pub const ALL: [WindowFunction; 7] = [
+        WindowFunction::Rectangular,
+        WindowFunction::Hann,
+        WindowFunction::Hamming,
+        WindowFunction::BlackmanHarris,
+        WindowFunction::FlatTop,
+        WindowFunction::Kaiser { beta: 8.6 },
+        WindowFunction::Gaussian { sigma: 0.4 },
+    ];
+
+    /// Whether `other` is the same kind of window, ignoring any shape parameter.
+    pub fn same_kind(&self, other: &WindowFunction) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    /// Periodic (DFT-even) coefficients, which is what spectral analysis wants:
+    /// the window repeats seamlessly and the coherent gain is exact for bin-centred tones.
+    pub fn coefficients(&self, size: usize) -> Vec<f32> {
+        let n = size as f64;
+        (0..size)
+            .map(|i| {
+                let x = i as f64 / n;
+                let w = match *self {
+                    WindowFunction::Rectangular => 1.0,
+                    WindowFunction::Hann => cosine_sum(x, &[0.5, 0.5]),
+                    WindowFunction::Hamming => cosine_sum(x, &[0.54, 0.46]),
+                    WindowFunction::BlackmanHarris => {
+                        cosine_sum(x, &[0.35875, 0.48829, 0.14128, 0.01168])
+                    }
+                    WindowFunction::FlatTop => cosine_sum(
+                        x,
+                        &[
+                            0.21557895,
+                            0.41663158,
+                            0.277263158,
+                            0.083578947,
+                            0.006947368,
+                        ],
+                    ),
+                    WindowFunction::Kaiser { beta } => {
+                        let beta = beta as f64;
+                        let r = 2.0 * x - 1.0;
+                        bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / bessel_i0(beta)
+                    }
+                    WindowFunction::Gaussian { sigma } => {
+                        let r = (2.0 * x - 1.0) / sigma as f64;
+                        (-0.5 * r * r).exp()
+                    }
+                };
+                w as f32
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for WindowFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WindowFunction::Rectangular => write!(f, "Rectangular"),
+            WindowFunction::Hann => write!(f, "Hann"),
+            WindowFunction::Hamming => write!(f, "Hamming"),
+            WindowFunction::BlackmanHarris => write!(f, "Blackman-Harris"),
+            WindowFunction::FlatTop => write!(f, "Flat-top"),
+            WindowFunction::Kaiser { .. } => write!(f, "Kaiser"),
+            WindowFunction::Gaussian { .. } => write!(f, "Gaussian"),
+        }
+    }
+}
+
+/// A window evaluated at a given size together with the gains needed to undo its effect.
+pub struct Window {
+    /// The function the coefficients were evaluated from.
+    pub function: WindowFunction,
+    /// One coefficient per sample of the frame.
+    pub coefficients: Vec<f32>,
+    /// Mean of the coefficients; dividing by it restores the amplitude of a tone.
+    pub coherent_gain: f32,
+    /// Equivalent noise bandwidth in bins; dividing power by it restores noise density.
+    pub enbw: f32,
+}
+
+impl Window {
+    /// Evaluates `function` over `size` samples.
+    pub fn new(function: WindowFunction, size: usize) -> Self {
+        let coefficients = function.coefficients(size);
+        let sum: f64 = coefficients.iter().map(|&w| w as f64).sum();
+        let sum_sq: f64 = coefficients.iter().map(|&w| (w as f64).powi(2)).sum();
+        Window {
+            function,
+            coherent_gain: (sum / size as f64) as f32,
+            enbw: (size as f64 * sum_sq / (sum * sum)) as f32,
+            coefficients,
+        }
+    }
+
+    /// Amplitude response to a tone `offset` bins from a bin centre, relative to one
+    /// on it. Below 1 within the main lobe, the scalloping loss, and negative on
+    /// alternate sidelobes.
+    pub fn response(&self, offset: f64) -> f64 {
+        let n = self.coefficients.len() as f64;
+        // Taken about the centre sample, where the window is symmetric, so it is real.
+        let sum: f64 = self
+            .coefficients
+            .iter()
+            .enumerate()
+            .map(|(i, &w)| w as f64 * (2.0 * PI * offset * (i as f64 - n / 2.0) / n).cos())
+            .sum();
+        sum / (n * self.coherent_gain as f64)
+    }
+}
+
+fn cosine_sum(x: f64, terms: &[f64]) -> f64 {
+    terms
+        .iter()
+        .enumerate()
+        .map(|(k, &a)| {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            sign * a * (2.0 * PI * k as f64 * x).cos()
+        })
+        .sum()
+}
+
+/// Zeroth-order modified Bessel function of the first kind, by its power series.
+fn bessel_i0(x: f64) -> f64 {
+    let quarter_x_sq = x * x / 4.0;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= quarter_x_sq / (k * k);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rectangular_has_unit_gains() {
+        let window = Window::new(WindowFunction::Rectangular, 64);
+        assert!(window.coefficients.iter().all(|&w| w == 1.0));
+        assert_eq!(window.coherent_gain, 1.0);
+        assert_eq!(window.enbw, 1.0);
+    }
+
+    #[test]
+    fn hann_gains() {
+        let window = Window::new(WindowFunction::Hann, 1024);
+        assert!((window.coherent_gain - 0.5).abs() < 1e-6);
+        assert!((window.enbw - 1.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn windows_are_periodic() {
+        for function in WindowFunction::ALL {
+            let coefficients = function.coefficients(16);
+            // Symmetric about the centre sample, which is the peak.
+            for i in 1..8 {
+                assert!(
+                    (coefficients[i] - coefficients[16 - i]).abs() < 1e-6,
+                    "{function}"
+                );
+            }
+            assert!(coefficients.iter().all(|&w| w <= coefficients[8] + 1e-6));
+        }
+    }
+
+    #[test]
+    fn scalloping_loss() {
+        let loss_db =
+            |function, offset| 20.0 * Window::new(function, 1024).response(offset).abs().log10();
+        assert!((loss_db(WindowFunction::Rectangular, 0.5) + 3.92).abs() < 0.01);
+        assert!((loss_db(WindowFunction::Hann, 0.5) + 1.42).abs() < 0.01);
+        assert!(loss_db(WindowFunction::FlatTop, 0.5).abs() < 0.02);
+        assert!(Window::new(WindowFunction::Hann, 1024).response(2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn shape_parameters_do_not_change_the_kind() {
+        let narrow = WindowFunction::Kaiser { beta: 2.0 };
+        assert!(narrow.same_kind(&WindowFunction::Kaiser { beta: 8.6 }));
+        assert!(!narrow.same_kind(&WindowFunction::Gaussian { sigma: 2.0 }));
+    }
+}
@@ -0,0 +1,178 @@
+//! The error type shared by capture, decoding, recording and export.
+
+use cpal::SampleFormat;
+use std::fmt;
+
+/// Everything that can go wrong opening an audio source: from enumerating devices
+/// to a running stream, decoding a file, or recording.
+#[derive(Debug)]
+pub enum AudioError {
+    /// No host with this name was compiled in.
+    UnknownHost(String),
+    /// The host is compiled in but cannot be used on this system.
+    HostUnavailable(cpal::HostUnavailable),
+    /// Listing the input devices failed.
+    EnumerateDevices(cpal::DevicesError),
+    /// There is no input device, or none matching the requested name.
+    NoDevice,
+    /// The device could not report its default configuration.
+    DefaultConfig(cpal::DefaultStreamConfigError),
+    /// The device could not report the configurations it supports.
+    SupportedConfigs(cpal::SupportedStreamConfigsError),
+    /// The requested stream settings are not among those the device reports.
+    UnsupportedConfig(String),
+    /// The device delivers a sample type that cannot be converted to f32.
+    UnsupportedSampleFormat(SampleFormat),
+    /// Opening the stream failed.
+    BuildStream(cpal::BuildStreamError),
+    /// Starting the stream failed.
+    PlayStream(cpal::PlayStreamError),
+    /// Reported asynchronously by a running stream.
+    Stream(cpal::StreamError),
+    /// Reading a WAV file failed.
+    Wav(hound::Error),
+    /// Reading or decoding a compressed file failed.
+    Decode(symphonia::core::errors::Error),
+    /// libopus rejected a packet.
+    #[cfg(feature = "opus")]
+    Opus(opus::Error),
+    /// The file decoded but holds nothing this build can analyze.
+    UnsupportedCodec(String),
+    /// Creating or writing a recording failed.
+    Record(std::io::Error),
+    /// Encoding a WAV recording failed.
+    WriteWav(hound::Error),
+    /// Encoding a FLAC recording failed.
+    EncodeFlac(String),
+    /// Writing an exported file failed.
+    Export(std::io::Error),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioError::UnknownHost(name) => {
+                let available: Vec<&str> =
+                    cpal::available_hosts().iter().map(|id| id.name()).collect();
+                write!(
+                    f,
+                    "unknown audio host '{name}' (available: {})",
+                    available.join(", ")
+                )
+            }
+            AudioError::HostUnavailable(err) => write!(f, "audio host unavailable: {err}"),
+            AudioError::EnumerateDevices(err) => write!(f, "failed to list input devices: {err}"),
+            AudioError::NoDevice => write!(f, "no input device available"),
+            AudioError::DefaultConfig(err) => {
+                write!(f, "failed to get the device configuration: {err}")
+            }
+            AudioError::SupportedConfigs(err) => {
+                write!(f, "failed to query supported configurations: {err}")
+            }
+            AudioError::UnsupportedConfig(what) => write!(f, "unsupported configuration: {what}"),
+            AudioError::UnsupportedSampleFormat(format) => {
+                write!(f, "sample format {format} is not supported")
+            }
+            AudioError::BuildStream(err) => write!(f, "failed to open the stream: {err}"),
+            AudioError::PlayStream(err) => write!(f, "failed to start the stream: {err}"),
+            AudioError::Stream(err) => write!(f, "stream error: {err}"),
+            AudioError::Wav(err) => write!(f, "failed to read WAV file: {err}"),
+            AudioError::Decode(err) => write!(f, "failed to decode file: {err}"),
+            #[cfg(feature = "opus")]
+            AudioError::Opus(err) => write!(f, "failed to decode Opus: {err}"),
+            AudioError::UnsupportedCodec(what) => write!(f, "unsupported audio: {what}"),
+            AudioError::Record(err) => write!(f, "failed to write recording: {err}"),
+            AudioError::WriteWav(err) => write!(f, "failed to write WAV file: {err}"),
+            AudioError::EncodeFlac(err) => write!(f, "failed to encode FLAC: {err}"),
+            AudioError::Export(err) => write!(f, "export failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AudioError::HostUnavailable(err) => Some(err),
+            AudioError::EnumerateDevices(err) => Some(err),
+            AudioError::DefaultConfig(err) => Some(err),
+            AudioError::SupportedConfigs(err) => Some(err),
+            AudioError::BuildStream(err) => Some(err),
+            AudioError::PlayStream(err) => Some(err),
+            AudioError::Stream(err) => Some(err),
+            AudioError::Wav(err) => Some(err),
+            AudioError::Decode(err) => Some(err),
+            #[cfg(feature = "opus")]
+            AudioError::Opus(err) => Some(err),
+            AudioError::Record(err) => Some(err),
+            AudioError::WriteWav(err) => Some(err),
+            AudioError::Export(err) => Some(err),
+            AudioError::UnknownHost(_)
+            | AudioError::NoDevice
+            | AudioError::UnsupportedConfig(_)
+            | AudioError::UnsupportedSampleFormat(_)
+            | AudioError::UnsupportedCodec(_)
+            | AudioError::EncodeFlac(_) => None,
+        }
+    }
+}
+
+impl From<cpal::HostUnavailable> for AudioError {
+    fn from(err: cpal::HostUnavailable) -> Self {
+        AudioError::HostUnavailable(err)
+    }
+}
+
+impl From<cpal::DevicesError> for AudioError {
+    fn from(err: cpal::DevicesError) -> Self {
+        AudioError::EnumerateDevices(err)
+    }
+}
+
+impl From<cpal::DefaultStreamConfigError> for AudioError {
+    fn from(err: cpal::DefaultStreamConfigError) -> Self {
+        AudioError::DefaultConfig(err)
+    }
+}
+
+impl From<cpal::SupportedStreamConfigsError> for AudioError {
+    fn from(err: cpal::SupportedStreamConfigsError) -> Self {
+        AudioError::SupportedConfigs(err)
+    }
+}
+
+impl From<cpal::BuildStreamError> for AudioError {
+    fn from(err: cpal::BuildStreamError) -> Self {
+        AudioError::BuildStream(err)
+    }
+}
+
+impl From<cpal::PlayStreamError> for AudioError {
+    fn from(err: cpal::PlayStreamError) -> Self {
+        AudioError::PlayStream(err)
+    }
+}
+
+impl From<cpal::StreamError> for AudioError {
+    fn from(err: cpal::StreamError) -> Self {
+        AudioError::Stream(err)
+    }
+}
+
+impl From<hound::Error> for AudioError {
+    fn from(err: hound::Error) -> Self {
+        AudioError::Wav(err)
+    }
+}
+
+impl From<symphonia::core::errors::Error> for AudioError {
+    fn from(err: symphonia::core::errors::Error) -> Self {
+        AudioError::Decode(err)
+    }
+}
+
+#[cfg(feature = "opus")]
+impl From<opus::Error> for AudioError {
+    fn from(err: opus::Error) -> Self {
+        AudioError::Opus(err)
+    }
+}
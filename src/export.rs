@@ -0,0 +1,401 @@
+//! Writing waveforms, spectra and spectrograms to CSV, JSON and NumPy files.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Map, Value};
+
+/// File format of an export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma-separated values, with the metadata in `#` comments.
+    Csv,
+    /// A single JSON document.
+    Json,
+    /// NumPy array, with the metadata and axis labels in a `.npy.json` file beside it.
+    Npy,
+}
+
+impl ExportFormat {
+    /// Every format, in menu order.
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Csv, ExportFormat::Json, ExportFormat::Npy];
+
+    /// File name extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Npy => "npy",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportFormat::Csv => write!(f, "CSV"),
+            ExportFormat::Json => write!(f, "JSON"),
+            ExportFormat::Npy => write!(f, "NumPy .npy"),
+        }
+    }
+}
+
+/// What the export menu can write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportData {
+    /// The samples on screen.
+    Waveform,
+    /// The current spectrum of each trace.
+    Spectrum,
+    /// The spectrogram history.
+    Spectrogram,
+    /// The fractional-octave band levels.
+    Bands,
+}
+
+impl ExportData {
+    /// Everything that can be exported, in menu order.
+    pub const ALL: [ExportData; 4] = [
+        ExportData::Waveform,
+        ExportData::Spectrum,
+        ExportData::Spectrogram,
+        ExportData::Bands,
+    ];
+
+    /// Default file name, before the timestamp and extension.
+    pub fn file_stem(self) -> &'static str {
+        match self {
+            ExportData::Waveform => "waveform",
+            ExportData::Spectrum => "spectrum",
+            ExportData::Spectrogram => "spectrogram",
+            ExportData::Bands => "bands",
+        }
+    }
+}
+
+impl fmt::Display for ExportData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportData::Waveform => write!(f, "Waveform"),
+            ExportData::Spectrum => write!(f, "Spectrum"),
+            ExportData::Spectrogram => write!(f, "Spectrogram"),
+            ExportData::Bands => write!(f, "Octave bands"),
+        }
+    }
+}
+
+/// Descriptive key/value pairs written alongside the data, in insertion order.
+pub type Metadata = Map<String, Value>;
+
+/// One column of a [`Table`].
+pub enum Values {
+    /// Single-precision values, written at f32 precision.
+    F32(Vec<f32>),
+    /// Double-precision values.
+    F64(Vec<f64>),
+}
+
+impl Values {
+    fn len(&self) -> usize {
+        match self {
+            Values::F32(values) => values.len(),
+            Values::F64(values) => values.len(),
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<f64> {
+        match self {
+            Values::F32(values) => values.get(index).map(|&v| v as f64),
+            Values::F64(values) => values.get(index).copied(),
+        }
+    }
+
+    /// Writes a CSV cell; f32 data is printed at f32 precision.
+    fn write_cell(&self, index: usize, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            Values::F32(values) => match values.get(index) {
+                Some(value) => write!(out, "{value}"),
+                None => Ok(()),
+            },
+            Values::F64(values) => match values.get(index) {
+                Some(value) => write!(out, "{value}"),
+                None => Ok(()),
+            },
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Values::F32(values) => f32_json(values),
+            Values::F64(values) => json!(values),
+        }
+    }
+}
+
+/// Named columns of equal length, e.g. frequency against magnitude and phase.
+pub struct Table {
+    /// Written as comments, a JSON object or a sidecar file, depending on the format.
+    pub metadata: Metadata,
+    /// Column names and values, in output order.
+    pub columns: Vec<(String, Values)>,
+}
+
+impl Table {
+    fn rows(&self) -> usize {
+        self.columns
+            .iter()
+            .map(|(_, values)| values.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Writes `#` metadata comments, a header row and one line per row.
+    pub fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        write_csv_metadata(&self.metadata, out)?;
+        let names: Vec<&str> = self.columns.iter().map(|(name, _)| name.as_str()).collect();
+        writeln!(out, "{}", names.join(","))?;
+        for row in 0..self.rows() {
+            for (i, (_, values)) in self.columns.iter().enumerate() {
+                if i > 0 {
+                    out.write_all(b",")?;
+                }
+                values.write_cell(row, out)?;
+            }
+            out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// CSV as [`Table::write_csv`], JSON with one array per
+    /// column, or a float64 `.npy` array with one column per table column.
+    pub fn write(&self, format: ExportFormat, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        match format {
+            ExportFormat::Csv => self.write_csv(&mut out)?,
+            ExportFormat::Json => {
+                let columns: Map<String, Value> = self
+                    .columns
+                    .iter()
+                    .map(|(name, values)| (name.clone(), values.to_json()))
+                    .collect();
+                let document = json!({ "metadata": self.metadata, "columns": columns });
+                serde_json::to_writer(&mut out, &document)?;
+            }
+            ExportFormat::Npy => {
+                let rows = self.rows();
+                let cols = self.columns.len();
+                write_npy_header(&mut out, "<f8", rows, cols)?;
+                for row in 0..rows {
+                    for (_, values) in &self.columns {
+                        let value = values.get(row).unwrap_or(f64::NAN);
+                        out.write_all(&value.to_le_bytes())?;
+                    }
+                }
+                let names: Vec<&str> = self.columns.iter().map(|(name, _)| name.as_str()).collect();
+                write_sidecar(path, json!({ "metadata": self.metadata, "columns": names }))?;
+            }
+        }
+        out.flush()
+    }
+}
+
+/// A grid of levels in dB, e.g. a spectrogram with one row per time step.
+pub struct Matrix {
+    /// Written as comments, a JSON object or a sidecar file, depending on the format.
+    pub metadata: Metadata,
+    /// Name and value of each row, e.g. time in seconds.
+    pub row_axis: (String, Vec<f64>),
+    /// Name and value of each column, e.g. frequency in Hz.
+    pub column_axis: (String, Vec<f64>),
+    /// What the cells hold, e.g. "magnitude_db".
+    pub value_name: String,
+    /// One vector of cells per row.
+    pub rows: Vec<Vec<f32>>,
+}
+
+impl Matrix {
+    /// CSV whose first column and header row hold the axes, JSON with both axes and
+    /// a nested array, or a float32 `.npy` array of shape (rows, columns).
+    pub fn write(&self, format: ExportFormat, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let (row_name, row_values) = &self.row_axis;
+        let (column_name, column_values) = &self.column_axis;
+        match format {
+            ExportFormat::Csv => {
+                write_csv_metadata(&self.metadata, &mut out)?;
+                writeln!(
+                    out,
+                    "# {}: {row_name} down, {column_name} across",
+                    self.value_name
+                )?;
+                write!(out, "{row_name}")?;
+                for value in column_values {
+                    write!(out, ",{value}")?;
+                }
+                out.write_all(b"\n")?;
+                for (label, row) in row_values.iter().zip(&self.rows) {
+                    write!(out, "{label}")?;
+                    for value in row {
+                        write!(out, ",{value}")?;
+                    }
+                    out.write_all(b"\n")?;
+                }
+            }
+            ExportFormat::Json => {
+                let values: Vec<Value> = self.rows.iter().map(|row| f32_json(row)).collect();
+                let document = json!({
+                    "metadata": self.metadata,
+                    row_name.as_str(): row_values,
+                    column_name.as_str(): column_values,
+                    self.value_name.as_str(): values,
+                });
+                serde_json::to_writer(&mut out, &document)?;
+            }
+            ExportFormat::Npy => {
+                write_npy_header(&mut out, "<f4", self.rows.len(), column_values.len())?;
+                for row in &self.rows {
+                    for column in 0..column_values.len() {
+                        let value = row.get(column).copied().unwrap_or(f32::NAN);
+                        out.write_all(&value.to_le_bytes())?;
+                    }
+                }
+                write_sidecar(
+                    path,
+                    json!({
+                        "metadata": self.metadata,
+                        "values": self.value_name,
+                        row_name.as_str(): row_values,
+                        column_name.as_str(): column_values,
+                    }),
+                )?;
+            }
+        }
+        out.flush()
+    }
+}
+
+/// JSON numbers are f64, so f32 data goes through its shortest decimal form to
+/// avoid exporting -1.100000023841858 for -1.1. Non-finite values become null.
+fn f32_json(values: &[f32]) -> Value {
+    values
+        .iter()
+        .map(|&value| match value.to_string().parse::<f64>() {
+            Ok(value) if value.is_finite() => value.into(),
+            _ => Value::Null,
+        })
+        .collect()
+}
+
+/// Comment lines that `numpy.loadtxt` and `pandas.read_csv(comment="#")` skip.
+fn write_csv_metadata(metadata: &Metadata, out: &mut impl Write) -> io::Result<()> {
+    for (key, value) in metadata {
+        match value {
+            Value::String(text) => writeln!(out, "# {key}: {text}")?,
+            value => writeln!(out, "# {key}: {value}")?,
+        }
+    }
+    Ok(())
+}
+
+/// `dir/stem.extension`, or with -2, -3 and so on added to the stem when that file
+/// already exists, so an export never replaces an earlier one.
+pub fn unused_path(dir: &Path, stem: &str, extension: &str) -> PathBuf {
+    let mut path = dir.join(format!("{stem}.{extension}"));
+    let mut n = 1;
+    while path.exists() {
+        n += 1;
+        path = dir.join(format!("{stem}-{n}.{extension}"));
+    }
+    path
+}
+
+/// Writes a version 1.0 `.npy` header for a C-ordered 2-D array.
+fn write_npy_header(out: &mut impl Write, descr: &str, rows: usize, cols: usize) -> io::Result<()> {
+    const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+    let mut header =
+        format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': ({rows}, {cols}), }}");
+    // The data must start on a 64-byte boundary; the header ends in a newline.
+    let unpadded = MAGIC.len() + 2 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(64) - unpadded,
+    ));
+    header.push('\n');
+    out.write_all(MAGIC)?;
+    out.write_all(&(header.len() as u16).to_le_bytes())?;
+    out.write_all(header.as_bytes())
+}
+
+fn write_sidecar(path: &Path, document: Value) -> io::Result<()> {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".json");
+    let mut out = BufWriter::new(File::create(sidecar)?);
+    serde_json::to_writer_pretty(&mut out, &document)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npy_data_starts_on_a_64_byte_boundary() {
+        for rows in [0, 1, 100, 123_456_789] {
+            let mut out = Vec::new();
+            write_npy_header(&mut out, "<f8", rows, 3).unwrap();
+            assert_eq!(out.len() % 64, 0);
+            assert_eq!(&out[..8], b"\x93NUMPY\x01\x00");
+            let header_len = u16::from_le_bytes([out[8], out[9]]) as usize;
+            assert_eq!(header_len + 10, out.len());
+            assert_eq!(out.last(), Some(&b'\n'));
+        }
+    }
+
+    #[test]
+    fn csv_has_metadata_header_and_rows() {
+        let mut metadata = Metadata::new();
+        metadata.insert("source".into(), "test".into());
+        metadata.insert("fft_size".into(), 4.into());
+        let table = Table {
+            metadata,
+            columns: vec![
+                ("frequency_hz".into(), Values::F64(vec![0.0, 12000.0])),
+                ("magnitude_db".into(), Values::F32(vec![-1.1, -200.0])),
+            ],
+        };
+        let mut out = Vec::new();
+        table.write_csv(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "# source: test\n# fft_size: 4\nfrequency_hz,magnitude_db\n0,-1.1\n12000,-200\n"
+        );
+    }
+
+    #[test]
+    fn json_keeps_f32_values_short() {
+        assert_eq!(f32_json(&[-1.1, f32::NAN]).to_string(), "[-1.1,null]");
+    }
+
+    #[test]
+    fn exports_never_overwrite() {
+        let dir = std::env::temp_dir().join(format!("fft-analyzer-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = unused_path(&dir, "spectrum", "csv");
+        assert_eq!(first, dir.join("spectrum.csv"));
+        File::create(&first).unwrap();
+        let second = unused_path(&dir, "spectrum", "csv");
+        assert_eq!(second, dir.join("spectrum-2.csv"));
+        File::create(&second).unwrap();
+        assert_eq!(
+            unused_path(&dir, "spectrum", "csv"),
+            dir.join("spectrum-3.csv")
+        );
+        assert_eq!(
+            unused_path(&dir, "spectrum", "npy"),
+            dir.join("spectrum.npy")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
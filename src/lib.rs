@@ -0,0 +1,32 @@
+//! Audio capture and spectrum analysis behind the `fft-analyzer` application.
+//!
+//! [`capture`] enumerates input devices and streams their samples through a
+//! lock-free ring buffer, [`audio_file`] decodes files into memory, [`source`] puts
+//! devices, files and a signal generator behind one trait, [`dsp`] turns
+//! frames of samples into windowed, averaged spectra and level readings,
+//! [`analysis`] runs them over a stream or a whole recording, and [`recorder`] and
+//! [`export`] write audio and results to disk.
+//!
+//! ```
+//! use fft_analyzer::dsp::{spectrum::SpectrumAnalyzer, window::WindowFunction};
+//!
+//! let samples: Vec<f32> = (0..4096).map(|i| (i as f32 * 0.1).sin()).collect();
+//! let mut analyzer = SpectrumAnalyzer::new(2048, WindowFunction::Hann);
+//! let spectrum = analyzer.process(&samples, 48000).unwrap();
+//! println!("{} bins of {} Hz", spectrum.magnitudes_db.len(), spectrum.bin_width());
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod analysis;
+pub mod audio_file;
+pub mod capture;
+mod decoder;
+pub mod dsp;
+pub mod error;
+pub mod export;
+pub mod recorder;
+pub mod source;
+pub mod types;
+
+pub use error::AudioError;
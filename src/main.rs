@@ -1,84 +1,2237 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{SampleFormat, Stream};
+use axes::{FrequencyAxis, Levels};
+use cpal::traits::DeviceTrait;
 use eframe::egui;
-use std::sync::{Arc, RwLock};
+use fft_analyzer::analysis::{Analysis, FileResults, Progress, Trace};
+use fft_analyzer::audio_file::{AudioFile, FilePlayer};
+use fft_analyzer::capture;
+use fft_analyzer::capture::device_settings::{self, DeviceSettings};
+use fft_analyzer::dsp::averaging::AveragingMode;
+use fft_analyzer::dsp::bands::{self, Band, BandMethod, OctaveFraction};
+use fft_analyzer::dsp::decimate::decimate;
+use fft_analyzer::dsp::metrics::{self, dbfs, LevelMeter};
+use fft_analyzer::dsp::peaks::{self, Interpolation, PeakEstimator, PeakSearch};
+use fft_analyzer::dsp::spectrum::Scaling;
+use fft_analyzer::dsp::units::{Calibration, DensityUnit, MagnitudeScale};
+use fft_analyzer::dsp::weighting::Weighting;
+use fft_analyzer::dsp::window::WindowFunction;
+use fft_analyzer::dsp::MIN_DB;
+use fft_analyzer::error::AudioError;
+use fft_analyzer::export::{self, ExportData, ExportFormat, Matrix, Metadata, Table, Values};
+use fft_analyzer::recorder::{self, RecordFormat, RecordSettings, Recorder};
+use fft_analyzer::source::{AudioSource, DeviceSource, Generator, Signal};
+use fft_analyzer::types::{Channel, Spectrum};
+use markers::{Cursors, Marker, PlotKind, Series};
+use spectrogram::{Colormap, Spectrogram};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+mod axes;
+mod cli;
+mod markers;
+mod spectrogram;
+
+const DEFAULT_FFT_SIZE: usize = 2048;
+const MIN_FFT_SIZE_LOG2: u32 = 8;
+const MAX_FFT_SIZE_LOG2: u32 = 20;
+const MAX_OVERLAP_PERCENT: f32 = 99.0;
+/// Samples the audio callback can queue ahead of the UI before it has to drop them.
+const RING_CAPACITY: usize = 1 << 18;
+const MAX_PLOT_POINTS: usize = 4096;
+const DEFAULT_SPECTROGRAM_ROWS: usize = 256;
+const MAX_SPECTROGRAM_ROWS: usize = 4096;
+const MAX_PEAK_MARKERS: usize = 10;
+/// Bins between automatic spectrum peak markers, about a window's main lobe.
+const PEAK_MARKER_SEPARATION: usize = 4;
+const MAX_PEAK_TABLE_ROWS: usize = 50;
+/// Bottom of the meter strip's bars, in dBFS; they run to full scale.
+const METER_FLOOR_DB: f64 = -60.0;
+const METER_BAR_SIZE: egui::Vec2 = egui::vec2(160.0, 12.0);
+const GENERATOR_SAMPLE_RATE: u32 = 48000;
+/// Stereo, so the mix-downs can be tried out on generated signals too.
+const GENERATOR_CHANNELS: usize = 2;
+const TRACE_COLORS: [egui::Color32; 8] = [
+    egui::Color32::from_rgb(100, 160, 255),
+    egui::Color32::from_rgb(255, 120, 100),
+    egui::Color32::from_rgb(120, 220, 120),
+    egui::Color32::from_rgb(230, 200, 80),
+    egui::Color32::from_rgb(200, 130, 255),
+    egui::Color32::from_rgb(80, 220, 220),
+    egui::Color32::from_rgb(255, 160, 200),
+    egui::Color32::from_rgb(190, 190, 190),
+];
+
+/// State of the device settings window while it is open.
+struct SettingsDialog {
+    host: cpal::HostId,
+    device_name: String,
+    supported: Vec<cpal::SupportedStreamConfigRange>,
+    settings: DeviceSettings,
+    error: Option<String>,
+}
+
+/// A whole-file analysis running on a worker thread, cancelled when dropped.
+struct FileJob {
+    progress: Progress,
+    results: mpsc::Receiver<FileResults>,
+}
+
+impl Drop for FileJob {
+    fn drop(&mut self) {
+        self.progress.cancel();
+    }
+}
 
 struct AppState {
+    host: cpal::Host,
     devices: Vec<cpal::Device>,
     selected_device: usize,
-    audio_data: Arc<RwLock<Vec<f32>>>,
-    _stream: Option<Stream>,
-    is_playing: bool,
+    /// The running device or generator; files are played through `file` instead.
+    source: Option<Box<dyn AudioSource>>,
+    /// Spectra, meters and the filter bank of whichever source is running.
+    analysis: Analysis,
+    /// How magnitude spectra and PSDs are shown, and how to get from full scale to
+    /// volts and sensor units.
+    magnitude_scale: MagnitudeScale,
+    density_unit: DensityUnit,
+    calibration: Calibration,
+    frequency_axis: FrequencyAxis,
+    /// Spectrum plot ranges in Hz and display units, unless auto-scaled.
+    frequency_range: (f64, f64),
+    level_range: (f64, f64),
+    auto_scale: bool,
+    /// Makes the spectrum plot drop any zoom and cursors for the new axis settings.
+    axes_changed: bool,
+    waveform_cursors: Cursors,
+    spectrum_cursors: Cursors,
+    markers: Vec<Marker>,
+    /// The marker the peak search buttons move.
+    active_marker: Option<usize>,
+    /// How many of the highest peaks of each plot get a labelled marker.
+    peak_markers: usize,
+    show_markers: bool,
+    /// Locates the tones in the peak table; rebuilt when the window changes.
+    peak_estimator: PeakEstimator,
+    peak_interpolation: Interpolation,
+    peak_table_rows: usize,
+    show_peak_table: bool,
+    show_bands: bool,
+    band_fraction: OctaveFraction,
+    band_method: BandMethod,
+    show_meters: bool,
+    show_max_hold: bool,
+    show_min_hold: bool,
+    /// Fed from the first trace.
+    spectrogram: Spectrogram,
+    show_waveform: bool,
+    show_spectrum: bool,
+    show_spectrogram: bool,
+    stream_errors: mpsc::Receiver<AudioError>,
+    stream_error_sender: mpsc::Sender<AudioError>,
+    last_error: Option<AudioError>,
+    /// Stream settings chosen in the settings dialog, keyed by host and device name;
+    /// the same name can stand for different hardware under another host.
+    device_settings: HashMap<(cpal::HostId, String), DeviceSettings>,
+    settings_dialog: Option<SettingsDialog>,
+    /// When set, the file replaces the live device as the analysis source.
+    file: Option<FilePlayer>,
+    file_path_input: String,
+    /// What the generator plays when started.
+    generator: Signal,
+    generator_level_db: f32,
+    /// Analyze the whole file at once instead of following the playhead.
+    whole_file: bool,
+    whole_file_stale: bool,
+    file_job: Option<FileJob>,
+    show_file_info: bool,
+    /// Set while the live input is being written to disk.
+    recorder: Option<Recorder>,
+    record_settings: RecordSettings,
+    /// Folder exports are written to.
+    export_dir: String,
+    last_export: Option<PathBuf>,
 }
 
 impl AppState {
-    fn new() -> Self {
-        let host = cpal::default_host();
-        let devices: Vec<cpal::Device> = host.input_devices().unwrap().collect();
-        AppState {
-            devices,
+    fn new(host: cpal::Host) -> Self {
+        let (stream_error_sender, stream_errors) = mpsc::channel();
+        let mut app = AppState {
+            host,
+            devices: Vec::new(),
             selected_device: 0,
-            audio_data: Arc::new(RwLock::new(Vec::new())),
-            _stream: None,
-            is_playing: false,
+            source: None,
+            analysis: Analysis::new(DEFAULT_FFT_SIZE, WindowFunction::Hann),
+            magnitude_scale: MagnitudeScale::Dbfs,
+            density_unit: DensityUnit::DbfsPerHz,
+            calibration: Calibration::default(),
+            frequency_axis: FrequencyAxis::LogDecades,
+            frequency_range: (20.0, 20000.0),
+            level_range: (-120.0, 0.0),
+            auto_scale: false,
+            axes_changed: false,
+            waveform_cursors: Cursors::default(),
+            spectrum_cursors: Cursors::default(),
+            markers: Vec::new(),
+            active_marker: None,
+            peak_markers: 0,
+            show_markers: false,
+            peak_estimator: PeakEstimator::new(WindowFunction::Hann),
+            peak_interpolation: Interpolation::Jacobsen,
+            peak_table_rows: 5,
+            show_peak_table: false,
+            show_bands: false,
+            band_fraction: OctaveFraction::Third,
+            band_method: BandMethod::FftBinning,
+            show_meters: true,
+            show_max_hold: false,
+            show_min_hold: false,
+            spectrogram: Spectrogram::new(DEFAULT_SPECTROGRAM_ROWS),
+            show_waveform: true,
+            show_spectrum: true,
+            show_spectrogram: false,
+            stream_errors,
+            stream_error_sender,
+            last_error: None,
+            device_settings: HashMap::new(),
+            settings_dialog: None,
+            file: None,
+            file_path_input: String::new(),
+            generator: Signal::defaults()[0].clone(),
+            generator_level_db: -6.0,
+            whole_file: false,
+            whole_file_stale: true,
+            file_job: None,
+            show_file_info: false,
+            recorder: None,
+            record_settings: RecordSettings::default(),
+            export_dir: ".".into(),
+            last_export: None,
+        };
+        let result = app.refresh_devices();
+        app.report(result);
+        app
+    }
+
+    /// Records the outcome of a user action for the status bar.
+    fn report(&mut self, result: Result<(), AudioError>) {
+        if let Err(err) = result {
+            self.last_error = Some(err);
         }
     }
 
-    fn start_stream(&mut self) {
-        let device = &self.devices[self.selected_device];
-        let config = device.default_input_config().unwrap();
-        
-        let sample_format = config.sample_format();
-        let config = cpal::StreamConfig::from(config);
+    /// Switches to the host with `id`, keeping the current one and its devices if it
+    /// cannot be opened or enumerated.
+    fn set_host(&mut self, id: cpal::HostId) -> Result<(), AudioError> {
+        let host = cpal::host_from_id(id)?;
+        let devices = capture::input_devices(&host)?;
+        self.stop_stream();
+        self.host = host;
+        self.devices = devices;
+        self.selected_device = 0;
+        Ok(())
+    }
 
-        let audio_data = self.audio_data.clone();
-        let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
+    fn refresh_devices(&mut self) -> Result<(), AudioError> {
+        self.stop_stream();
+        self.devices.clear();
+        self.selected_device = 0;
+        self.devices = capture::input_devices(&self.host)?;
+        Ok(())
+    }
 
-        let stream = match sample_format {
-            SampleFormat::F32 => self.build_input_stream::<f32>(device, &config, err_fn, audio_data),
-            SampleFormat::I16 => self.build_input_stream::<i16>(device, &config, err_fn, audio_data),
-            SampleFormat::U16 => self.build_input_stream::<u16>(device, &config, err_fn, audio_data),
-            _ => panic!("sample format is not supported: {:?}", sample_format),
-        };
+    /// Starts over with empty capture buffers for a source with `channel_count` channels.
+    fn reset_capture(&mut self, channel_count: usize, sample_rate: u32) {
+        self.analysis.reset(channel_count, sample_rate);
+        self.restart_analysis();
+    }
+
+    fn is_running(&self) -> bool {
+        self.source
+            .as_ref()
+            .is_some_and(|source| source.is_running())
+    }
+
+    fn start_stream(&mut self) -> Result<(), AudioError> {
+        self.last_error = None;
+        let device = self
+            .devices
+            .get(self.selected_device)
+            .cloned()
+            .ok_or(AudioError::NoDevice)?;
+        let key = (self.host.id(), device.name().unwrap_or_default());
+        let source = DeviceSource::new(
+            device,
+            self.device_settings.get(&key).copied(),
+            RING_CAPACITY,
+            self.stream_error_sender.clone(),
+        )?;
+        self.start_source(Box::new(source))
+    }
+
+    fn start_generator(&mut self) -> Result<(), AudioError> {
+        self.stop_stream();
+        self.last_error = None;
+        let generator = Generator::new(
+            self.generator.clone(),
+            10f32.powf(self.generator_level_db / 20.0),
+            GENERATOR_SAMPLE_RATE,
+            GENERATOR_CHANNELS,
+        );
+        self.start_source(Box::new(generator))
+    }
 
-        stream.play().unwrap();
-        self._stream = Some(stream);
-        self.is_playing = true;
+    /// Starts `source` and makes it the analysis input in place of any file.
+    fn start_source(&mut self, mut source: Box<dyn AudioSource>) -> Result<(), AudioError> {
+        source.start()?;
+        self.file = None;
+        self.reset_capture(source.channels(), source.sample_rate());
+        self.source = Some(source);
+        Ok(())
     }
 
     fn stop_stream(&mut self) {
-        if let Some(stream) = self._stream.take() {
-            drop(stream);
-        }
-        self.is_playing = false;
-    }
-
-    fn build_input_stream<T>(
-        &self,
-        device: &cpal::Device,
-        config: &cpal::StreamConfig,
-        err_fn: impl Fn(cpal::StreamError) + Send + 'static,
-        audio_data: Arc<RwLock<Vec<f32>>>,
-    ) -> Stream
-    where
-        T: cpal::Sample + cpal::SizedSample + Into<f32>,
-    {
-        let channels = config.channels as usize;
-        device.build_input_stream(
-            config,
-            move |data: &[T], _| {
-                let mut buffer = audio_data.write().unwrap();
-                for frame in data.chunks(channels) {
-                    buffer.push(frame[0].into());
-                    if buffer.len() > 2048 {
-                        let drain_size = buffer.len() - 2048;
-                        buffer.drain(0..drain_size);
+        self.source = None;
+        self.recorder = None;
+    }
+
+    fn start_recording(&mut self) -> Result<(), AudioError> {
+        let source = self.source.as_ref().ok_or(AudioError::NoDevice)?;
+        let recorder = Recorder::start(
+            &self.record_settings,
+            &source.name(),
+            self.analysis.sample_rate(),
+            self.analysis.history.channel_count(),
+            source.tap(),
+            self.stream_error_sender.clone(),
+        )?;
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+}
+
+impl AppState {
+    /// Re-analyzes the buffered history after a setting that affects every frame changed.
+    fn restart_analysis(&mut self) {
+        self.whole_file_stale = true;
+        self.file_job = None;
+        self.spectrogram.clear();
+        self.analysis.restart();
+    }
+
+    /// The bands to feed a filter bank for, while the band view needs one.
+    fn filter_bank_bands(&self) -> Option<OctaveFraction> {
+        let needed = self.show_bands && self.band_method == BandMethod::FilterBank;
+        needed.then_some(self.band_fraction)
+    }
+
+    fn open_settings_dialog(&mut self) -> Result<(), AudioError> {
+        let device = self
+            .devices
+            .get(self.selected_device)
+            .ok_or(AudioError::NoDevice)?;
+        let (host, device_name) = (self.host.id(), device.name().unwrap_or_default());
+        let settings = match self.device_settings.get(&(host, device_name.clone())) {
+            Some(settings) => *settings,
+            None => DeviceSettings::from_default(device)?,
+        };
+        self.settings_dialog = Some(SettingsDialog {
+            host,
+            device_name,
+            supported: device_settings::supported_configs(device)?,
+            settings,
+            error: None,
+        });
+        Ok(())
+    }
+
+    fn settings_dialog(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.settings_dialog else {
+            return;
+        };
+        let mut open = true;
+        let mut apply = false;
+        egui::Window::new(format!("Device settings: {}", dialog.device_name))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let supported = &dialog.supported;
+                let settings = &mut dialog.settings;
+                egui::Grid::new("device_settings_grid").show(ui, |ui| {
+                    ui.label("Sample format:");
+                    egui::ComboBox::from_id_source("settings_format")
+                        .selected_text(settings.sample_format.to_string())
+                        .show_ui(ui, |ui| {
+                            for format in device_settings::sample_formats(supported) {
+                                let label = format!("{format} ({}-bit)", format.sample_size() * 8);
+                                ui.selectable_value(&mut settings.sample_format, format, label);
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Channels:");
+                    egui::ComboBox::from_id_source("settings_channels")
+                        .selected_text(settings.channels.to_string())
+                        .show_ui(ui, |ui| {
+                            for channels in
+                                device_settings::channel_counts(supported, settings.sample_format)
+                            {
+                                ui.selectable_value(
+                                    &mut settings.channels,
+                                    channels,
+                                    channels.to_string(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Sample rate:");
+                    egui::ComboBox::from_id_source("settings_rate")
+                        .selected_text(format!("{} Hz", settings.sample_rate))
+                        .show_ui(ui, |ui| {
+                            for rate in device_settings::sample_rates(
+                                supported,
+                                settings.sample_format,
+                                settings.channels,
+                            ) {
+                                ui.selectable_value(
+                                    &mut settings.sample_rate,
+                                    rate,
+                                    format!("{rate} Hz"),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Buffer size:");
+                    ui.horizontal(|ui| {
+                        let mut fixed = settings.buffer_size.is_some();
+                        ui.checkbox(&mut fixed, "Fixed");
+                        let ranges = device_settings::buffer_size_ranges(supported, settings);
+                        match (fixed, &mut settings.buffer_size) {
+                            (true, Some(frames)) => {
+                                ui.add(egui::DragValue::new(frames).suffix(" frames"));
+                                *frames = device_settings::nearest_buffer_size(&ranges, *frames);
+                            }
+                            (true, None) => {
+                                settings.buffer_size =
+                                    Some(device_settings::nearest_buffer_size(&ranges, 1024));
+                            }
+                            (false, _) => settings.buffer_size = None,
+                        }
+                        if !ranges.is_empty() {
+                            let ranges: Vec<String> = ranges
+                                .iter()
+                                .map(|(min, max)| format!("{min}–{max}"))
+                                .collect();
+                            ui.label(format!("({})", ranges.join(", ")));
+                        }
+                    });
+                    ui.end_row();
+                });
+
+                if let Some(error) = &dialog.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                ui.horizontal(|ui| {
+                    apply = ui.button("Apply").clicked();
+                    if ui.button("Use device default").clicked() {
+                        let key = (dialog.host, dialog.device_name.clone());
+                        self.device_settings.remove(&key);
+                        dialog.error = None;
+                        if let Some(device) = self
+                            .devices
+                            .iter()
+                            .find(|device| device.name().ok().as_ref() == Some(&dialog.device_name))
+                        {
+                            if let Ok(settings) = DeviceSettings::from_default(device) {
+                                dialog.settings = settings;
+                            }
+                        }
+                    }
+                });
+            });
+
+        if apply {
+            match dialog.settings.validate(&dialog.supported) {
+                Ok(_) => {
+                    dialog.error = None;
+                    let key = (dialog.host, dialog.device_name.clone());
+                    self.device_settings.insert(key, dialog.settings);
+                    if self.is_running() {
+                        self.stop_stream();
+                        let result = self.start_stream();
+                        self.report(result);
+                    }
+                }
+                Err(err) => dialog.error = Some(err.to_string()),
+            }
+        }
+        if !open {
+            self.settings_dialog = None;
+        }
+    }
+
+    fn open_file(&mut self, path: &Path) -> Result<(), AudioError> {
+        let file = AudioFile::open(path)?;
+        self.stop_stream();
+        self.last_error = None;
+        self.file_path_input = path.display().to_string();
+        self.reset_capture(file.channels, file.sample_rate);
+        self.file = Some(FilePlayer::new(file));
+        self.seek_file(0);
+        Ok(())
+    }
+
+    /// Moves the playhead and preloads the frame before it so the views reflect
+    /// the new position even while paused.
+    fn seek_file(&mut self, frame: usize) {
+        let Some(player) = &mut self.file else {
+            return;
+        };
+        player.seek(frame);
+        let start = frame.saturating_sub(self.analysis.analyzer.fft_size());
+        self.analysis.preload(player.file.interleaved(start, frame));
+        self.restart_analysis();
+    }
+
+    /// Starts computing the power-averaged spectrum of the entire file for every
+    /// trace, and frames spread evenly across it for the spectrogram, on a worker
+    /// thread in place of any earlier run.
+    fn analyze_whole_file(&mut self) {
+        self.whole_file_stale = false;
+        let Some(player) = &self.file else {
+            return;
+        };
+        let file = player.file.clone();
+        let mut job = self
+            .analysis
+            .whole_file(self.spectrogram.history_len(), MAX_PLOT_POINTS);
+        let progress = Progress::default();
+        let (sender, results) = mpsc::channel();
+        let worker_progress = progress.clone();
+        std::thread::Builder::new()
+            .name("file analysis".into())
+            .spawn(move || {
+                let samples = &file.samples;
+                let results = job.run_with_progress(
+                    samples,
+                    file.channels,
+                    file.sample_rate,
+                    &worker_progress,
+                );
+                if let Some(results) = results {
+                    let _ = sender.send(results);
+                }
+            })
+            .expect("failed to spawn the file analysis thread");
+        self.file_job = Some(FileJob { progress, results });
+    }
+
+    /// Shows the results of the whole-file analysis once the worker has them.
+    fn poll_file_job(&mut self) {
+        let Some(job) = &self.file_job else {
+            return;
+        };
+        match job.results.try_recv() {
+            Ok(results) => {
+                self.spectrogram.clear();
+                for row in &results.rows {
+                    self.spectrogram.push(row, results.row_duration);
+                }
+                self.analysis.set_file_results(results);
+                self.file_job = None;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => self.file_job = None,
+            Err(mpsc::TryRecvError::Empty) => {}
+        }
+    }
+
+    fn file_controls(&mut self, ui: &mut egui::Ui) {
+        ui.label("File:");
+        let response = ui.add(
+            egui::TextEdit::singleline(&mut self.file_path_input)
+                .hint_text("path to an audio file")
+                .desired_width(200.0),
+        );
+        let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        if ui.button("Open").clicked() || submitted {
+            let path = self.file_path_input.clone();
+            let result = self.open_file(Path::new(&path));
+            self.report(result);
+        }
+    }
+
+    fn generator_controls(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Generator", |ui| {
+            egui::ComboBox::from_label("Signal")
+                .selected_text(self.generator.to_string())
+                .show_ui(ui, |ui| {
+                    for signal in Signal::defaults() {
+                        let selected = self.generator.same_kind(&signal);
+                        let clicked = ui.selectable_label(selected, signal.to_string()).clicked();
+                        if clicked && !selected {
+                            self.generator = signal;
+                        }
+                    }
+                });
+            match &mut self.generator {
+                Signal::Sine { frequency } => {
+                    ui.horizontal(|ui| {
+                        ui.label("Frequency:");
+                        ui.add(frequency_drag(frequency));
+                    });
+                }
+                Signal::Multitone { frequencies } => {
+                    ui.label("Frequencies:");
+                    for frequency in frequencies.iter_mut() {
+                        ui.add(frequency_drag(frequency));
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Add").clicked() {
+                            let next = frequencies.last().map_or(1000.0, |f| f * 2.0);
+                            frequencies.push(next.min(GENERATOR_SAMPLE_RATE as f64 / 2.0));
+                        }
+                        if frequencies.len() > 1 && ui.button("Remove").clicked() {
+                            frequencies.pop();
+                        }
+                    });
+                }
+                Signal::Sweep {
+                    start,
+                    end,
+                    duration,
+                } => {
+                    ui.horizontal(|ui| {
+                        ui.label("From:");
+                        ui.add(frequency_drag(start));
+                        ui.label("to:");
+                        ui.add(frequency_drag(end));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Every:");
+                        ui.add(
+                            egui::DragValue::new(duration)
+                                .clamp_range(0.1..=600.0)
+                                .suffix(" s"),
+                        );
+                    });
+                }
+                Signal::WhiteNoise | Signal::PinkNoise | Signal::Silence => {}
+            }
+            ui.horizontal(|ui| {
+                ui.label("Level:");
+                ui.add(
+                    egui::DragValue::new(&mut self.generator_level_db)
+                        .clamp_range(-120.0..=0.0)
+                        .speed(0.1)
+                        .suffix(" dBFS"),
+                );
+            });
+            ui.label(format!(
+                "{GENERATOR_SAMPLE_RATE} Hz, {GENERATOR_CHANNELS} channels"
+            ));
+            if ui.button("Start generator").clicked() {
+                let result = self.start_generator();
+                self.report(result);
+                ui.close_menu();
+            }
+        });
+    }
+
+    fn record_controls(&mut self, ui: &mut egui::Ui) {
+        let recording = self.recorder.is_some();
+        ui.add_enabled_ui(!recording, |ui| {
+            ui.menu_button("Recording", |ui| {
+                let settings = &mut self.record_settings;
+                egui::ComboBox::from_label("Format")
+                    .selected_text(settings.format.to_string())
+                    .show_ui(ui, |ui| {
+                        for format in RecordFormat::ALL {
+                            ui.selectable_value(&mut settings.format, format, format.to_string());
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.label("File name:");
+                    ui.text_edit_singleline(&mut settings.template);
+                    ui.label(format!(".{}", settings.format.extension()));
+                });
+                ui.label(format!("Placeholders: {}", recorder::TEMPLATE_HELP));
+                ui.horizontal(|ui| {
+                    let mut split = settings.max_file_bytes.is_some();
+                    ui.checkbox(&mut split, "Split files at");
+                    let mut megabytes = settings.max_file_bytes.unwrap_or(1 << 30) / 1_000_000;
+                    ui.add_enabled(
+                        split,
+                        egui::DragValue::new(&mut megabytes)
+                            .clamp_range(1..=1_000_000)
+                            .suffix(" MB"),
+                    );
+                    settings.max_file_bytes = split.then_some(megabytes * 1_000_000);
+                });
+            });
+        });
+
+        if let Some(recorder) = &self.recorder {
+            if ui.button("⏹ Stop recording").clicked() {
+                self.recorder = None;
+            } else {
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!("● {}", format_time(recorder.duration())),
+                );
+                if recorder.dropped() > 0 {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!("{} samples dropped", recorder.dropped()),
+                    )
+                    .on_hover_text("The recording fell behind the device and has gaps");
+                }
+            }
+        } else if ui
+            .add_enabled(self.is_running(), egui::Button::new("⏺ Record"))
+            .on_disabled_hover_text("Start the input stream to record it")
+            .clicked()
+        {
+            let result = self.start_recording();
+            self.report(result);
+        }
+    }
+
+    fn export_controls(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Export", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Folder:");
+                ui.text_edit_singleline(&mut self.export_dir);
+            });
+            egui::Grid::new("export_grid").show(ui, |ui| {
+                for data in ExportData::ALL {
+                    ui.label(data.to_string());
+                    for format in ExportFormat::ALL {
+                        if ui.button(format.to_string()).clicked() {
+                            let result = self.export(data, format);
+                            self.report(result);
+                            ui.close_menu();
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    /// Writes the requested data to a time-stamped file in the export folder, next to
+    /// any earlier exports.
+    fn export(&mut self, data: ExportData, format: ExportFormat) -> Result<(), AudioError> {
+        let stem = format!(
+            "{}-{}",
+            data.file_stem(),
+            chrono::Local::now().format("%Y%m%d-%H%M%S-%3f")
+        );
+        let path = export::unused_path(Path::new(&self.export_dir), &stem, format.extension());
+        match data {
+            ExportData::Waveform => self.waveform_table().write(format, &path),
+            ExportData::Spectrum => self.spectrum_table().write(format, &path),
+            ExportData::Spectrogram => self.spectrogram_matrix().write(format, &path),
+            ExportData::Bands => self.bands_table().write(format, &path),
+        }
+        .map_err(AudioError::Export)?;
+        self.last_export = Some(path);
+        Ok(())
+    }
+
+    fn export_metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new();
+        let source = match (&self.file, &self.source) {
+            (Some(player), _) => player.file.path.display().to_string(),
+            (None, Some(source)) => source.name(),
+            (None, None) => String::new(),
+        };
+        metadata.insert("source".into(), source.into());
+        let exported_at = chrono::Local::now().to_rfc3339();
+        metadata.insert("exported_at".into(), exported_at.into());
+        metadata.insert("sample_rate_hz".into(), self.analysis.sample_rate().into());
+        metadata
+    }
+
+    /// Adds the settings that shaped the spectra.
+    fn analysis_metadata(&self, metadata: &mut Metadata) {
+        let window = self.analysis.analyzer.window();
+        metadata.insert("fft_size".into(), self.analysis.analyzer.fft_size().into());
+        metadata.insert("window".into(), window.function.to_string().into());
+        metadata.insert("window_coherent_gain".into(), window.coherent_gain.into());
+        metadata.insert("window_enbw_bins".into(), window.enbw.into());
+        metadata.insert(
+            "scaling".into(),
+            self.analysis.analyzer.scaling().to_string().into(),
+        );
+        metadata.insert("unit".into(), self.levels().unit.into());
+        let (volts, units) = self.calibration_in_use();
+        let calibration = &self.calibration;
+        if volts {
+            let full_scale = calibration.full_scale_volts;
+            metadata.insert("full_scale_v_rms".into(), full_scale.into());
+        }
+        if units {
+            let sensitivity = calibration.volts_per_unit;
+            metadata.insert(format!("v_per_{}", calibration.unit), sensitivity.into());
+        }
+        metadata.insert(
+            "overlap_percent".into(),
+            self.analysis.overlap_percent.into(),
+        );
+        metadata.insert("hop_size".into(), self.analysis.hop_size().into());
+        match self.analysis.averaging {
+            _ if self.whole_file && self.file.is_some() => {
+                metadata.insert("averaging".into(), "power mean over the whole file".into());
+            }
+            AveragingMode::Off => {
+                metadata.insert("averaging".into(), "none".into());
+            }
+            AveragingMode::Exponential { time_constant } => {
+                metadata.insert("averaging".into(), "exponential power mean".into());
+                metadata.insert("averaging_time_constant_s".into(), time_constant.into());
+            }
+            AveragingMode::Linear { frames } => {
+                metadata.insert("averaging".into(), "power mean of the latest frames".into());
+                metadata.insert("averaging_frames".into(), frames.into());
+            }
+            AveragingMode::Infinite => {
+                metadata.insert("averaging".into(), "power mean since reset".into());
+            }
+        }
+    }
+
+    /// Every buffered sample of each channel, against time since capture started.
+    fn waveform_table(&mut self) -> Table {
+        let mut metadata = self.export_metadata();
+        let sample_rate = self.analysis.sample_rate().max(1) as f64;
+        let buffer = &mut self.analysis.history;
+        let (oldest, written) = (buffer.oldest(), buffer.written());
+        let len = (written - oldest) as usize;
+        metadata.insert("channels".into(), buffer.channel_count().into());
+        metadata.insert("first_sample".into(), oldest.into());
+
+        let times = (oldest..written).map(|n| n as f64 / sample_rate).collect();
+        let mut columns = vec![("time_s".to_string(), Values::F64(times))];
+        for index in 0..buffer.channel_count() {
+            let channel = Channel::Single(index);
+            let mut samples = Vec::new();
+            buffer.copy_frame(channel, written, len, &mut samples);
+            columns.push((channel.to_string(), Values::F32(samples)));
+        }
+        Table { metadata, columns }
+    }
+
+    /// Magnitude and phase of every displayed trace against frequency, in the shown
+    /// unit; a PSD both in decibels and linear.
+    fn spectrum_table(&self) -> Table {
+        let mut metadata = self.export_metadata();
+        self.analysis_metadata(&mut metadata);
+        let levels = self.levels();
+        let convert =
+            |db: &[f32]| -> Vec<f32> { db.iter().map(|&db| levels.convert(db) as f32).collect() };
+        let suffix = if levels.linear { "" } else { "_db" };
+        let psd = self.analysis.analyzer.scaling() == Scaling::Psd;
+        let mut columns = Vec::new();
+        for trace in &self.analysis.traces {
+            let Some(spectrum) = &trace.spectrum else {
+                continue;
+            };
+            if columns.is_empty() {
+                let frequencies = (0..spectrum.magnitudes_db.len())
+                    .map(|bin| spectrum.frequency(bin))
+                    .collect();
+                columns.push(("frequency_hz".to_string(), Values::F64(frequencies)));
+                metadata.insert("frames_averaged".into(), spectrum.averages.into());
+            }
+            let weighting = trace.weighting.to_string();
+            metadata.insert(format!("{} weighting", trace.channel), weighting.into());
+            let magnitudes = convert(&spectrum.magnitudes_db);
+            if psd {
+                let linear = magnitudes
+                    .iter()
+                    .map(|&db| 10f64.powf(db as f64 / 10.0))
+                    .collect();
+                columns.push((format!("{} psd_db", trace.channel), Values::F32(magnitudes)));
+                let unit = self.density_unit.linear_label(&self.calibration);
+                let name = format!("{} psd_{}", trace.channel, unit.replace('²', "2"));
+                columns.push((name.replace('/', "_per_"), Values::F64(linear)));
+            } else {
+                let name = format!("{} magnitude{suffix}", trace.channel);
+                columns.push((name, Values::F32(magnitudes)));
+            }
+            let phases = Values::F32(spectrum.phases.clone());
+            columns.push((format!("{} phase_rad", trace.channel), phases));
+            if let (true, Some(hold)) = (self.show_max_hold, trace.averager.max_hold()) {
+                let values = Values::F32(convert(&hold.magnitudes_db));
+                columns.push((format!("{} max_hold{suffix}", trace.channel), values));
+            }
+            if let (true, Some(hold)) = (self.show_min_hold, trace.averager.min_hold()) {
+                let values = Values::F32(convert(&hold.magnitudes_db));
+                columns.push((format!("{} min_hold{suffix}", trace.channel), values));
+            }
+        }
+        Table { metadata, columns }
+    }
+
+    /// Band edges and the band levels of the first trace.
+    fn bands_table(&self) -> Table {
+        let mut metadata = self.export_metadata();
+        self.analysis_metadata(&mut metadata);
+        let levels = self.magnitude_levels(self.analysis.traces[0].weighting);
+        metadata.insert("bands".into(), self.band_fraction.to_string().into());
+        metadata.insert("band_method".into(), self.band_method.to_string().into());
+        metadata.insert("unit".into(), levels.unit.clone().into());
+        let mut columns = Vec::new();
+        if let Some((bands, band_levels)) = self.band_levels() {
+            let band_values =
+                |value: fn(&Band) -> f64| Values::F64(bands.iter().map(value).collect());
+            columns.push(("nominal_hz".to_string(), band_values(|band| band.nominal)));
+            columns.push(("lower_hz".to_string(), band_values(|band| band.lower)));
+            columns.push(("upper_hz".to_string(), band_values(|band| band.upper)));
+            let suffix = if levels.linear { "" } else { "_db" };
+            let values = band_levels.iter().map(|&db| levels.convert(db) as f32);
+            let name = format!("{} band_level{suffix}", self.analysis.traces[0].channel);
+            columns.push((name, Values::F32(values.collect())));
+        }
+        Table { metadata, columns }
+    }
+
+    /// The spectrogram history, oldest row first.
+    fn spectrogram_matrix(&self) -> Matrix {
+        let mut metadata = self.export_metadata();
+        self.analysis_metadata(&mut metadata);
+        let spectrogram = &self.spectrogram;
+        metadata.insert(
+            "channel".into(),
+            self.analysis.traces[0].channel.to_string().into(),
+        );
+        metadata.insert("row_duration_s".into(), spectrogram.row_duration().into());
+        let bins_per_column = spectrogram.bins_per_column();
+        metadata.insert("bins_per_column".into(), bins_per_column.into());
+        let times = (0..spectrogram.row_count())
+            .map(|row| row as f64 * spectrogram.row_duration())
+            .collect();
+        Matrix {
+            metadata,
+            row_axis: ("time_s".into(), times),
+            column_axis: ("frequency_hz".into(), spectrogram.column_frequencies()),
+            value_name: "magnitude_db".into(),
+            rows: spectrogram.rows().map(<[f32]>::to_vec).collect(),
+        }
+    }
+
+    fn transport_bar(&mut self, ui: &mut egui::Ui) {
+        let Some(player) = &mut self.file else {
+            return;
+        };
+        if player.is_playing() {
+            if ui.button("Pause").clicked() {
+                player.pause();
+            }
+        } else if ui.button("Play").clicked() {
+            player.play();
+        }
+
+        let sample_rate = player.file.sample_rate as f64;
+        let duration = player.file.duration();
+        let mut seconds = player.position() as f64 / sample_rate;
+        ui.spacing_mut().slider_width = (ui.available_width() - 300.0).max(100.0);
+        let seek = ui
+            .add(egui::Slider::new(&mut seconds, 0.0..=duration).show_value(false))
+            .changed();
+        ui.label(format!(
+            "{} / {}",
+            format_time(seconds),
+            format_time(duration)
+        ));
+        if seek {
+            self.seek_file((seconds * sample_rate) as usize);
+        }
+
+        ui.separator();
+        if ui.checkbox(&mut self.whole_file, "Whole file").changed() {
+            self.restart_analysis();
+        }
+        if let Some(job) = &self.file_job {
+            ui.add(
+                egui::ProgressBar::new(job.progress.fraction())
+                    .desired_width(100.0)
+                    .show_percentage(),
+            );
+            if ui.button("Cancel").clicked() {
+                self.whole_file = false;
+                self.restart_analysis();
+            }
+        }
+        ui.toggle_value(&mut self.show_file_info, "Info");
+        if ui.button("Close").clicked() {
+            self.close_file();
+        }
+    }
+
+    /// Goes back to having no source, keeping the traces for the next one.
+    fn close_file(&mut self) {
+        self.file = None;
+        self.whole_file = false;
+        self.reset_capture(self.analysis.history.channel_count(), 0);
+    }
+
+    fn file_info_window(&mut self, ctx: &egui::Context) {
+        let Some(player) = &self.file else {
+            return;
+        };
+        let file = &player.file;
+        egui::Window::new("File info")
+            .open(&mut self.show_file_info)
+            .show(ctx, |ui| {
+                egui::Grid::new("file_info_grid")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        let mut row = |key: &str, value: String| {
+                            ui.label(key);
+                            ui.label(value);
+                            ui.end_row();
+                        };
+                        row("Path", file.path.display().to_string());
+                        row("Encoding", file.encoding.clone());
+                        row("Sample rate", format!("{} Hz", file.sample_rate));
+                        row("Channels", file.channels.to_string());
+                        if let Some(layout) = &file.channel_layout {
+                            row("Layout", layout.clone());
+                        }
+                        row("Duration", format_time(file.duration()));
+                        for (key, value) in &file.tags {
+                            row(key, value.clone());
+                        }
+                    });
+            });
+    }
+
+    /// Picks up errors reported by the audio thread, stopping the stream if it died.
+    fn poll_stream_errors(&mut self) {
+        while let Ok(err) = self.stream_errors.try_recv() {
+            if let AudioError::Stream(cpal::StreamError::DeviceNotAvailable) = err {
+                self.stop_stream();
+            }
+            self.last_error = Some(err);
+        }
+    }
+
+    /// The file when one is open, otherwise the running device or generator.
+    fn active_source(&mut self) -> Option<&mut (dyn AudioSource + 'static)> {
+        match &mut self.file {
+            Some(player) => Some(player),
+            None => self.source.as_deref_mut(),
+        }
+    }
+
+    /// Analyzes every frame completed since the last repaint, stepping by the hop size.
+    fn process_new_frames(&mut self) {
+        let mut incoming = Vec::new();
+        if let Some(source) = self.active_source() {
+            source.read(&mut incoming);
+        }
+        let spectrogram = &mut self.spectrogram;
+        self.analysis.process(&incoming, |spectrum, duration| {
+            spectrogram.push(spectrum, duration)
+        });
+        if let Some(recorder) = &mut self.recorder {
+            // The writer has already reported why it stopped.
+            if !recorder.write(incoming) {
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// The bands of the first trace and their weighted levels in dBFS, from its
+    /// spectrum or the filter bank.
+    fn band_levels(&self) -> Option<(Vec<Band>, Vec<f32>)> {
+        match self.band_method {
+            BandMethod::FftBinning => {
+                let spectrum = self.analysis.traces[0].spectrum.as_ref()?;
+                let bands = self.band_fraction.audio_bands(spectrum.sample_rate);
+                let scaling = self.analysis.analyzer.scaling();
+                let enbw = self.analysis.analyzer.window().enbw;
+                let levels = bands::spectrum_band_levels(spectrum, scaling, enbw, &bands);
+                Some((bands, levels))
+            }
+            BandMethod::FilterBank => {
+                let bank = self.analysis.filter_bank.as_ref()?;
+                let levels = bank.weighted_levels_db(self.analysis.traces[0].weighting);
+                Some((bank.bands().to_vec(), levels))
+            }
+        }
+    }
+
+    fn channel_controls(&mut self, ui: &mut egui::Ui) {
+        let available = Channel::all(self.analysis.history.channel_count());
+        let mut selected = self.analysis.traces[0].channel;
+        ui.label("Channel:");
+        egui::ComboBox::from_id_source("channel_select")
+            .selected_text(selected.to_string())
+            .show_ui(ui, |ui| {
+                for &channel in &available {
+                    ui.selectable_value(&mut selected, channel, channel.to_string());
+                }
+            });
+        if selected != self.analysis.traces[0].channel {
+            self.analysis
+                .traces
+                .retain(|trace| trace.channel != selected);
+            self.analysis.traces.insert(0, Trace::new(selected));
+            self.restart_analysis();
+        }
+
+        ui.menu_button("Overlay", |ui| {
+            for &channel in available.iter().filter(|&&channel| channel != selected) {
+                let shown = self
+                    .analysis
+                    .traces
+                    .iter()
+                    .any(|trace| trace.channel == channel);
+                let mut checked = shown;
+                ui.checkbox(&mut checked, channel.to_string());
+                if checked && !shown {
+                    self.analysis.traces.push(Trace::new(channel));
+                    self.restart_analysis();
+                } else if !checked && shown {
+                    self.analysis
+                        .traces
+                        .retain(|trace| trace.channel != channel);
+                }
+            }
+        });
+
+        ui.menu_button("Weighting", |ui| {
+            let mut changed = false;
+            egui::Grid::new("weighting_grid").show(ui, |ui| {
+                for trace in &mut self.analysis.traces {
+                    ui.label(trace.channel.to_string());
+                    for weighting in Weighting::ALL {
+                        let text = weighting.to_string();
+                        changed |= ui
+                            .selectable_value(&mut trace.weighting, weighting, text)
+                            .changed();
+                    }
+                    ui.end_row();
+                }
+            });
+            if changed {
+                self.restart_analysis();
+            }
+        });
+    }
+
+    /// The frequency-weighted level of every trace, then a row per captured channel
+    /// with its levels, peaks held since the last reset, crest factor, DC offset and
+    /// clips. Levels are Fast time-weighted, or cover the whole file.
+    fn meter_strip(&mut self, ctx: &egui::Context) {
+        if !self.show_meters {
+            return;
+        }
+        egui::TopBottomPanel::bottom("meter_strip").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Level:");
+                for trace in &self.analysis.traces {
+                    let Some(meter) = &trace.meter else {
+                        continue;
+                    };
+                    let levels = self.magnitude_levels(trace.weighting);
+                    let level = levels.convert(dbfs(meter.level()) as f32);
+                    let text = format!("{} {}", trace.channel, levels.format(level, 1));
+                    ui.colored_label(trace_color(trace), text);
+                }
+                if ui.button("Reset holds").clicked() {
+                    self.analysis
+                        .meters
+                        .iter_mut()
+                        .for_each(LevelMeter::reset_holds);
+                }
+            });
+            egui::Grid::new("meter_grid").striped(true).show(ui, |ui| {
+                let headings = [
+                    "",
+                    "",
+                    "RMS",
+                    "Peak",
+                    "Hold",
+                    "True peak",
+                    "Hold",
+                    "Crest",
+                    "DC",
+                    "Clips",
+                ];
+                for heading in headings {
+                    ui.strong(heading);
+                }
+                ui.end_row();
+                for (index, meter) in self.analysis.meters.iter().enumerate() {
+                    ui.label(Channel::Single(index).to_string());
+                    meter_bar(ui, meter);
+                    ui.label(format!("{:.1} dBFS", dbfs(meter.rms())));
+                    ui.label(format!("{:.1} dBFS", dbfs(meter.peak())));
+                    ui.label(format!("{:.1} dBFS", dbfs(meter.peak_hold())));
+                    ui.label(format!("{:.1} dBTP", dbfs(meter.true_peak())));
+                    ui.label(format!("{:.1} dBTP", dbfs(meter.true_peak_hold())));
+                    ui.label(format!("{:.1} dB", meter.crest_factor_db()));
+                    ui.label(format!("{:+.3} %", meter.dc_offset() * 100.0));
+                    let clips = meter.clips().to_string();
+                    if meter.clips() > 0 {
+                        ui.colored_label(egui::Color32::RED, clips);
+                    } else {
+                        ui.label(clips);
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    fn analysis_controls(&mut self, ui: &mut egui::Ui) {
+        let mut fft_size = self.analysis.analyzer.fft_size();
+        ui.label("FFT size:");
+        egui::ComboBox::from_id_source("fft_size_select")
+            .selected_text(fft_size.to_string())
+            .show_ui(ui, |ui| {
+                for size in (MIN_FFT_SIZE_LOG2..=MAX_FFT_SIZE_LOG2).map(|log2| 1 << log2) {
+                    ui.selectable_value(&mut fft_size, size, size.to_string());
+                }
+            });
+        if fft_size != self.analysis.analyzer.fft_size() {
+            self.analysis.set_fft_size(fft_size);
+            self.restart_analysis();
+        }
+
+        ui.label("Overlap:");
+        ui.add(
+            egui::DragValue::new(&mut self.analysis.overlap_percent)
+                .suffix(" %")
+                .speed(1.0)
+                .clamp_range(0.0..=MAX_OVERLAP_PERCENT),
+        );
+
+        let mut window = self.analysis.analyzer.window().function;
+        ui.label("Window:");
+        egui::ComboBox::from_id_source("window_select")
+            .selected_text(window.to_string())
+            .show_ui(ui, |ui| {
+                for candidate in WindowFunction::ALL {
+                    let selected = window.same_kind(&candidate);
+                    if ui
+                        .selectable_label(selected, candidate.to_string())
+                        .clicked()
+                        && !selected
+                    {
+                        window = candidate;
+                    }
+                }
+            });
+        match &mut window {
+            WindowFunction::Kaiser { beta } => {
+                ui.add(
+                    egui::DragValue::new(beta)
+                        .prefix("β ")
+                        .speed(0.1)
+                        .clamp_range(0.0..=40.0),
+                );
+            }
+            WindowFunction::Gaussian { sigma } => {
+                ui.add(
+                    egui::DragValue::new(sigma)
+                        .prefix("σ ")
+                        .speed(0.005)
+                        .clamp_range(0.05..=0.5),
+                );
+            }
+            _ => {}
+        }
+        if window != self.analysis.analyzer.window().function {
+            self.analysis.analyzer.set_window(window);
+            self.restart_analysis();
+        }
+
+        let mut scaling = self.analysis.analyzer.scaling();
+        egui::ComboBox::from_id_source("scaling_select")
+            .selected_text(scaling.to_string())
+            .show_ui(ui, |ui| {
+                for candidate in Scaling::ALL {
+                    ui.selectable_value(&mut scaling, candidate, candidate.to_string());
+                }
+            });
+        if scaling != self.analysis.analyzer.scaling() {
+            // Welch's method is an average; one periodogram is too noisy to read a
+            // floor from.
+            if scaling == Scaling::Psd && self.analysis.averaging == AveragingMode::Off {
+                self.analysis.averaging = AveragingMode::Linear { frames: 16 };
+            }
+            self.analysis.analyzer.set_scaling(scaling);
+            self.restart_analysis();
+            self.level_range = self.default_level_range();
+            self.axes_changed = true;
+        }
+
+        let window = self.analysis.analyzer.window();
+        ui.label(format!(
+            "CG {:.2} dB, ENBW {:.2} bins",
+            20.0 * window.coherent_gain.log10(),
+            window.enbw
+        ));
+    }
+
+    fn axis_controls(&mut self, ui: &mut egui::Ui) {
+        let before = (
+            self.frequency_axis,
+            self.frequency_range,
+            self.magnitude_scale,
+            self.density_unit,
+            self.level_range,
+            self.auto_scale,
+        );
+        ui.label("Frequency:");
+        egui::ComboBox::from_id_source("frequency_axis_select")
+            .selected_text(self.frequency_axis.to_string())
+            .show_ui(ui, |ui| {
+                for candidate in FrequencyAxis::ALL {
+                    let label = candidate.to_string();
+                    ui.selectable_value(&mut self.frequency_axis, candidate, label);
+                }
+            });
+        // A log axis can't reach 0 Hz.
+        let lowest = if self.frequency_axis.is_log() {
+            1.0
+        } else {
+            0.0
+        };
+        ui.add_enabled_ui(!self.auto_scale, |ui| {
+            let (min, max) = &mut self.frequency_range;
+            ui.add(
+                egui::DragValue::new(min)
+                    .suffix(" Hz")
+                    .speed(10.0)
+                    .clamp_range(lowest..=1e6),
+            );
+            ui.label("to");
+            ui.add(
+                egui::DragValue::new(max)
+                    .suffix(" Hz")
+                    .speed(10.0)
+                    .clamp_range(lowest..=1e6),
+            );
+            *min = min.max(lowest);
+            *max = max.max(*min + 1.0);
+        });
+
+        ui.separator();
+        ui.label("Level:");
+        let units = (self.magnitude_scale, self.density_unit);
+        if self.analysis.analyzer.scaling() == Scaling::Psd {
+            egui::ComboBox::from_id_source("density_unit_select")
+                .selected_text(self.density_unit.linear_label(&self.calibration))
+                .show_ui(ui, |ui| {
+                    for candidate in DensityUnit::ALL {
+                        let label = candidate.linear_label(&self.calibration);
+                        ui.selectable_value(&mut self.density_unit, candidate, label);
+                    }
+                });
+        } else {
+            egui::ComboBox::from_id_source("magnitude_scale_select")
+                .selected_text(self.magnitude_scale.to_string())
+                .show_ui(ui, |ui| {
+                    for candidate in MagnitudeScale::ALL {
+                        let label = candidate.to_string();
+                        ui.selectable_value(&mut self.magnitude_scale, candidate, label);
                     }
+                });
+        }
+        if units != (self.magnitude_scale, self.density_unit) {
+            self.level_range = self.default_level_range();
+        }
+        if self.calibration_in_use().0 {
+            self.calibration_menu(ui);
+        }
+        let levels = self.levels();
+        ui.add_enabled_ui(!self.auto_scale, |ui| {
+            let (min, max) = &mut self.level_range;
+            let (speed, gap) = if levels.linear {
+                (0.01, 1e-6)
+            } else {
+                (1.0, 1.0)
+            };
+            let suffix = format!(" {}", levels.unit);
+            ui.add(egui::DragValue::new(min).suffix(&suffix).speed(speed));
+            ui.label("to");
+            ui.add(egui::DragValue::new(max).suffix(&suffix).speed(speed));
+            *max = max.max(*min + gap);
+        });
+        ui.checkbox(&mut self.auto_scale, "Auto");
+
+        let after = (
+            self.frequency_axis,
+            self.frequency_range,
+            self.magnitude_scale,
+            self.density_unit,
+            self.level_range,
+            self.auto_scale,
+        );
+        if after != before {
+            self.axes_changed = true;
+        }
+    }
+
+    fn calibration_menu(&mut self, ui: &mut egui::Ui) {
+        let units = self.calibration_in_use().1;
+        ui.menu_button("Calibration", |ui| {
+            let calibration = &mut self.calibration;
+            egui::Grid::new("calibration_grid").show(ui, |ui| {
+                ui.label("Full-scale sine:");
+                ui.add(
+                    egui::DragValue::new(&mut calibration.full_scale_volts)
+                        .suffix(" V RMS")
+                        .speed(0.01)
+                        .clamp_range(1e-6..=1000.0),
+                );
+                ui.end_row();
+                if !units {
+                    return;
+                }
+                // dB SPL is only meaningful in pascals.
+                let spl = self.magnitude_scale == MagnitudeScale::DbSpl
+                    && self.analysis.analyzer.scaling() != Scaling::Psd;
+                if !spl {
+                    ui.label("Unit:");
+                    ui.add(egui::TextEdit::singleline(&mut calibration.unit).desired_width(60.0));
+                    ui.end_row();
                 }
+                let unit = if spl { "Pa" } else { &calibration.unit };
+                ui.label("Sensitivity:");
+                let mut millivolts = calibration.volts_per_unit * 1000.0;
+                ui.add(
+                    egui::DragValue::new(&mut millivolts)
+                        .suffix(format!(" mV/{unit}"))
+                        .speed(0.1)
+                        .clamp_range(1e-3..=1e6),
+                );
+                calibration.volts_per_unit = millivolts / 1000.0;
+                ui.end_row();
+            });
+        });
+    }
+
+    /// How the analyzer's dBFS or dBFS/Hz levels are shown and exported. The unit is
+    /// marked with the weighting when every trace has the same one.
+    fn levels(&self) -> Levels {
+        let weighting = self.shared_weighting();
+        match self.analysis.analyzer.scaling() {
+            Scaling::Amplitude | Scaling::Noise => self.magnitude_levels(weighting),
+            Scaling::Psd => Levels {
+                offset_db: self.density_unit.offset_db(&self.calibration),
+                linear: false,
+                unit: weighting.label(&self.density_unit.db_label(&self.calibration)),
             },
-            err_fn,
-            None,
-        ).unwrap()
+        }
+    }
+
+    /// The weighting of every trace, or Z when they differ and each trace's name
+    /// carries its own.
+    fn shared_weighting(&self) -> Weighting {
+        let weighting = self.analysis.traces[0].weighting;
+        if self
+            .analysis
+            .traces
+            .iter()
+            .all(|trace| trace.weighting == weighting)
+        {
+            weighting
+        } else {
+            Weighting::Z
+        }
+    }
+
+    /// How levels in dBFS are shown, as for magnitude spectra; band and meter levels
+    /// are powers whatever the spectrum scaling.
+    fn magnitude_levels(&self, weighting: Weighting) -> Levels {
+        Levels {
+            offset_db: self.magnitude_scale.offset_db(&self.calibration),
+            linear: !self.magnitude_scale.is_db(),
+            unit: weighting.label(self.magnitude_scale.unit()),
+        }
+    }
+
+    /// Whether the shown levels depend on the full-scale voltage, and on the sensor
+    /// sensitivity.
+    fn calibration_in_use(&self) -> (bool, bool) {
+        match self.analysis.analyzer.scaling() {
+            Scaling::Amplitude | Scaling::Noise => (
+                self.magnitude_scale.needs_calibration(),
+                self.magnitude_scale == MagnitudeScale::DbSpl,
+            ),
+            Scaling::Psd => (
+                self.density_unit != DensityUnit::DbfsPerHz,
+                self.density_unit == DensityUnit::UnitsSquaredPerHz,
+            ),
+        }
+    }
+
+    /// A level range that suits typical signals in the shown unit.
+    fn default_level_range(&self) -> (f64, f64) {
+        let levels = self.levels();
+        if levels.linear {
+            return (0.0, 1.0);
+        }
+        let (min, max) = match self.analysis.analyzer.scaling() {
+            Scaling::Amplitude | Scaling::Noise => (-120.0, 0.0),
+            Scaling::Psd => (-180.0, -40.0),
+        };
+        // Whole tens keep the gridlines on round numbers.
+        let offset = (levels.offset_db / 10.0).round() * 10.0;
+        (min + offset, max + offset)
+    }
+
+    /// Frequency and level ranges that fit the displayed spectra, ignoring silent
+    /// bins. Decibel ranges are widened to whole tens so they don't jitter.
+    fn auto_range(&self, lines: &[SpectrumLine], levels: &Levels) -> ((f64, f64), (f64, f64)) {
+        let axis = self.frequency_axis;
+        let frequencies = match self
+            .analysis
+            .traces
+            .iter()
+            .find_map(|trace| trace.spectrum.as_ref())
+        {
+            Some(spectrum) => {
+                let lowest = if axis.is_log() {
+                    spectrum.bin_width()
+                } else {
+                    0.0
+                };
+                (lowest, spectrum.sample_rate as f64 / 2.0)
+            }
+            None => self.frequency_range,
+        };
+        let floor = levels.convert(MIN_DB);
+        let (min, max) = lines
+            .iter()
+            .flat_map(|line| &line.points)
+            .map(|point| point[1])
+            .filter(|&level| level > floor)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), level| {
+                (min.min(level), max.max(level))
+            });
+        let levels = if min > max {
+            self.level_range
+        } else if levels.linear {
+            (0.0, max * 1.05)
+        } else {
+            (
+                (min / 10.0).floor() * 10.0,
+                (max / 10.0).ceil() * 10.0 + 10.0,
+            )
+        };
+        (frequencies, levels)
+    }
+
+    fn averaging_controls(&mut self, ui: &mut egui::Ui) {
+        let whole_file = self.whole_file && self.file.is_some();
+        ui.add_enabled_ui(!whole_file, |ui| {
+            let mut mode = self.analysis.averaging;
+            ui.label("Averaging:");
+            egui::ComboBox::from_id_source("averaging_select")
+                .selected_text(mode.to_string())
+                .show_ui(ui, |ui| {
+                    for candidate in AveragingMode::ALL {
+                        let selected = mode.same_kind(&candidate);
+                        let clicked = ui
+                            .selectable_label(selected, candidate.to_string())
+                            .clicked();
+                        if clicked && !selected {
+                            mode = candidate;
+                        }
+                    }
+                });
+            match &mut mode {
+                AveragingMode::Exponential { time_constant } => {
+                    ui.add(
+                        egui::DragValue::new(time_constant)
+                            .prefix("τ ")
+                            .suffix(" s")
+                            .speed(0.05)
+                            .clamp_range(0.01..=600.0),
+                    );
+                }
+                AveragingMode::Linear { frames } => {
+                    ui.add(
+                        egui::DragValue::new(frames)
+                            .suffix(" frames")
+                            .clamp_range(2..=10_000),
+                    );
+                }
+                AveragingMode::Off | AveragingMode::Infinite => {}
+            }
+            // Each trace's averager picks up the new mode with its next frame.
+            self.analysis.averaging = mode;
+        });
+
+        ui.checkbox(&mut self.show_max_hold, "Max hold");
+        ui.checkbox(&mut self.show_min_hold, "Min hold");
+        if ui
+            .add_enabled(!whole_file, egui::Button::new("Reset"))
+            .clicked()
+        {
+            for trace in &mut self.analysis.traces {
+                trace.averager.reset();
+            }
+        }
+        let count = self.analysis.traces[0].averager.count();
+        let plural = if count == 1 { "" } else { "s" };
+        ui.label(format!("{count} frame{plural}"));
+    }
+
+    fn view_controls(&mut self, ui: &mut egui::Ui) {
+        ui.label("Views:");
+        ui.checkbox(&mut self.show_waveform, "Waveform");
+        ui.checkbox(&mut self.show_spectrum, "Spectrum");
+        ui.checkbox(&mut self.show_spectrogram, "Spectrogram");
+        ui.checkbox(&mut self.show_markers, "Markers");
+        ui.checkbox(&mut self.show_peak_table, "Peak table");
+        if ui.checkbox(&mut self.show_bands, "Octave bands").changed() {
+            // A whole-file analysis only runs the filter bank while the bands are shown.
+            self.analysis.set_filter_bank(self.filter_bank_bands());
+            self.whole_file_stale = true;
+        }
+        ui.checkbox(&mut self.show_meters, "Meters");
+        if self.show_bands {
+            self.band_controls(ui);
+        }
+        if !self.show_spectrogram {
+            return;
+        }
+
+        ui.separator();
+        let mut colormap = self.spectrogram.colormap();
+        ui.label("Colormap:");
+        egui::ComboBox::from_id_source("colormap_select")
+            .selected_text(colormap.to_string())
+            .show_ui(ui, |ui| {
+                for candidate in Colormap::ALL {
+                    ui.selectable_value(&mut colormap, candidate, candidate.to_string());
+                }
+            });
+        self.spectrogram.set_colormap(colormap);
+
+        let (mut min_db, mut max_db) = self.spectrogram.db_range();
+        ui.label("Range:");
+        ui.add(egui::Slider::new(&mut min_db, -200.0..=0.0).suffix(" dB"));
+        ui.add(egui::Slider::new(&mut max_db, -200.0..=20.0).suffix(" dB"));
+        self.spectrogram
+            .set_db_range(min_db, max_db.max(min_db + 1.0));
+
+        let mut history_len = self.spectrogram.history_len();
+        ui.label("History:");
+        ui.add(
+            egui::DragValue::new(&mut history_len)
+                .suffix(" rows")
+                .clamp_range(16..=MAX_SPECTROGRAM_ROWS),
+        );
+        self.spectrogram.set_history_len(history_len);
+    }
+
+    fn waveform_plot(&mut self, ui: &mut egui::Ui, height: f32) {
+        let markers = self.marker_labels(PlotKind::Waveform);
+        egui::plot::Plot::new("waveform_plot")
+            .height(height)
+            .width(ui.available_width())
+            .legend(egui::plot::Legend::default())
+            .allow_drag(!self.waveform_cursors.enabled)
+            .show(ui, |plot_ui| {
+                for trace in &self.analysis.traces {
+                    let points: Vec<[f64; 2]> = trace
+                        .waveform
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &sample)| [i as f64 * trace.waveform_step, sample as f64])
+                        .collect();
+                    plot_ui.line(
+                        egui::plot::Line::new(egui::plot::PlotPoints::from_iter(decimate(
+                            points,
+                            MAX_PLOT_POINTS,
+                        )))
+                        .color(trace_color(trace))
+                        .name(trace.channel.to_string()),
+                    );
+                }
+                self.waveform_cursors.show(plot_ui);
+                draw_markers(plot_ui, markers);
+            });
+    }
+
+    fn spectrum_plot(&mut self, ui: &mut egui::Ui, height: f32) {
+        let levels = self.levels();
+        let axis = self.frequency_axis;
+        let mut lines = Vec::new();
+        for trace in &self.analysis.traces {
+            let Some(spectrum) = &trace.spectrum else {
+                continue;
+            };
+            lines.push(SpectrumLine {
+                points: spectrum_points(spectrum, &levels, axis),
+                color: trace_color(trace),
+                style: egui::plot::LineStyle::Solid,
+                name: trace.name(),
+            });
+            if let (true, Some(hold)) = (self.show_max_hold, trace.averager.max_hold()) {
+                lines.push(SpectrumLine {
+                    points: spectrum_points(&hold, &levels, axis),
+                    color: trace_color(trace),
+                    style: egui::plot::LineStyle::dashed_loose(),
+                    name: format!("{} max hold", trace.name()),
+                });
+            }
+            if let (true, Some(hold)) = (self.show_min_hold, trace.averager.min_hold()) {
+                lines.push(SpectrumLine {
+                    points: spectrum_points(&hold, &levels, axis),
+                    color: trace_color(trace),
+                    style: egui::plot::LineStyle::dotted_loose(),
+                    name: format!("{} min hold", trace.name()),
+                });
+            }
+        }
+        let (frequencies, level_range) = if self.auto_scale {
+            self.auto_range(&lines, &levels)
+        } else {
+            (self.frequency_range, self.level_range)
+        };
+
+        let markers = self.marker_labels(PlotKind::Spectrum);
+        let axis_levels = levels.clone();
+        let mut plot = egui::plot::Plot::new("spectrum_plot")
+            .height(height)
+            .width(ui.available_width())
+            .allow_drag(!self.spectrum_cursors.enabled)
+            .include_x(axis.to_plot(frequencies.0))
+            .include_x(axis.to_plot(frequencies.1))
+            .include_y(level_range.0)
+            .include_y(level_range.1)
+            .legend(egui::plot::Legend::default())
+            .x_axis_formatter(move |x, _| axes::format_frequency(axis.frequency_at(x)))
+            .y_axis_formatter(move |level, _| axis_levels.format(level, 0))
+            .label_formatter(move |name, point| {
+                let frequency = axes::format_frequency(axis.frequency_at(point.x));
+                let level = levels.format(point.y, 1);
+                if name.is_empty() {
+                    format!("{frequency}\n{level}")
+                } else {
+                    format!("{name}\n{frequency}\n{level}")
+                }
+            });
+        if axis.is_log() {
+            plot = plot.x_grid_spacer(move |input| axis.log_grid(input));
+        }
+        if std::mem::take(&mut self.axes_changed) {
+            plot = plot.reset();
+            self.spectrum_cursors.clear();
+        }
+        plot.show(ui, |plot_ui| {
+            for line in lines {
+                plot_ui.line(
+                    egui::plot::Line::new(egui::plot::PlotPoints::from(line.points))
+                        .color(line.color)
+                        .style(line.style)
+                        .name(line.name),
+                );
+            }
+            self.spectrum_cursors.show(plot_ui);
+            draw_markers(plot_ui, markers);
+        });
+    }
+
+    fn band_controls(&mut self, ui: &mut egui::Ui) {
+        let (fraction, method) = (self.band_fraction, self.band_method);
+        egui::ComboBox::from_id_source("band_fraction_select")
+            .selected_text(self.band_fraction.to_string())
+            .show_ui(ui, |ui| {
+                for candidate in OctaveFraction::ALL {
+                    let text = candidate.to_string();
+                    ui.selectable_value(&mut self.band_fraction, candidate, text);
+                }
+            });
+        egui::ComboBox::from_id_source("band_method_select")
+            .selected_text(self.band_method.to_string())
+            .show_ui(ui, |ui| {
+                for candidate in BandMethod::ALL {
+                    let text = candidate.to_string();
+                    ui.selectable_value(&mut self.band_method, candidate, text);
+                }
+            });
+        if (fraction, method) != (self.band_fraction, self.band_method) {
+            self.analysis.set_filter_bank(self.filter_bank_bands());
+            self.whole_file_stale = true;
+        }
+    }
+
+    /// The band levels of the first trace as bars on a log frequency axis, each as
+    /// wide as its band, rising from the bottom of the default magnitude range.
+    fn band_plot(&mut self, ui: &mut egui::Ui, height: f32) {
+        let levels = self.magnitude_levels(self.analysis.traces[0].weighting);
+        let floor = if levels.linear {
+            0.0
+        } else {
+            -120.0 + (levels.offset_db / 10.0).round() * 10.0
+        };
+        let (bands, band_levels) = self.band_levels().unwrap_or_default();
+        let bars = bands
+            .iter()
+            .zip(&band_levels)
+            .map(|(band, &db)| {
+                let level = levels.convert(db).max(floor);
+                let name = format!(
+                    "{}\n{}",
+                    axes::format_frequency(band.nominal),
+                    levels.format(level, 1)
+                );
+                egui::plot::Bar::new(band.centre.log10(), level - floor)
+                    .base_offset(floor)
+                    .width((band.upper / band.lower).log10() * 0.8)
+                    .name(name)
+            })
+            .collect();
+        let chart = egui::plot::BarChart::new(bars)
+            .color(TRACE_COLORS[0])
+            .name(self.analysis.traces[0].name())
+            .element_formatter(Box::new(|bar, _| bar.name.clone()));
+
+        // Octave centres label the axis whatever the fraction, as on a sound level meter.
+        let (low, high) = match (bands.first(), bands.last()) {
+            (Some(first), Some(last)) => (first.lower, last.upper),
+            _ => (0.0, 0.0),
+        };
+        let octaves: Vec<(f64, f64)> = OctaveFraction::Full
+            .bands(low, high)
+            .iter()
+            .map(|band| (band.centre.log10(), band.nominal))
+            .collect();
+        let marks = octaves.clone();
+        let ceiling = if levels.linear { 1.0 } else { floor + 120.0 };
+        let axis_levels = levels.clone();
+        egui::plot::Plot::new("band_plot")
+            .height(height)
+            .width(ui.available_width())
+            .include_y(floor)
+            .include_y(ceiling)
+            .x_grid_spacer(move |_| {
+                marks
+                    .iter()
+                    .map(|&(value, _)| egui::plot::GridMark {
+                        value,
+                        step_size: 1.0,
+                    })
+                    .collect()
+            })
+            .x_axis_formatter(move |x, _| {
+                octaves
+                    .iter()
+                    .min_by(|a, b| (a.0 - x).abs().total_cmp(&(b.0 - x).abs()))
+                    .map(|&(_, nominal)| axes::format_frequency(nominal))
+                    .unwrap_or_default()
+            })
+            .y_axis_formatter(move |level, _| axis_levels.format(level, 0))
+            .show(ui, |plot_ui| plot_ui.bar_chart(chart));
+    }
+
+    /// The first trace of a plot, for markers and cursor readouts.
+    fn series(&self, plot: PlotKind) -> Option<Series<'_>> {
+        let trace = &self.analysis.traces[0];
+        match plot {
+            PlotKind::Waveform => Some(Series::waveform(&trace.waveform, trace.waveform_step)),
+            PlotKind::Spectrum => trace.spectrum.as_ref().map(|spectrum| Series {
+                values: Cow::Borrowed(&spectrum.magnitudes_db),
+                step: spectrum.bin_width(),
+                first: 1,
+            }),
+        }
+        .filter(|series| !series.values.is_empty())
+    }
+
+    fn cursors(&self, plot: PlotKind) -> &Cursors {
+        match plot {
+            PlotKind::Waveform => &self.waveform_cursors,
+            PlotKind::Spectrum => &self.spectrum_cursors,
+        }
+    }
+
+    /// A marker position (Hz, or samples into the waveform) at a plot x coordinate.
+    fn position_at(&self, plot: PlotKind, x: f64) -> f64 {
+        match plot {
+            PlotKind::Waveform => x,
+            PlotKind::Spectrum => self.frequency_axis.frequency_at(x),
+        }
+    }
+
+    /// A value of the first trace in the units on screen.
+    fn display_level(&self, plot: PlotKind, value: f32) -> f64 {
+        match plot {
+            PlotKind::Waveform => value as f64,
+            PlotKind::Spectrum => self.levels().convert(value),
+        }
+    }
+
+    fn format_position(&self, plot: PlotKind, position: f64) -> String {
+        match plot {
+            PlotKind::Waveform => {
+                axes::format_duration(position / self.analysis.sample_rate().max(1) as f64)
+            }
+            PlotKind::Spectrum => axes::format_frequency(position),
+        }
+    }
+
+    fn format_level(&self, plot: PlotKind, level: f64) -> String {
+        match plot {
+            PlotKind::Waveform => format!("{level:.4}"),
+            PlotKind::Spectrum => self.levels().format(level, 1),
+        }
+    }
+
+    fn format_level_delta(&self, plot: PlotKind, delta: f64) -> String {
+        match plot {
+            PlotKind::Waveform => format!("{delta:.4}"),
+            PlotKind::Spectrum => self.levels().format_delta(delta),
+        }
+    }
+
+    /// Positions of the automatic peak markers, highest first.
+    fn peak_positions(&self, plot: PlotKind) -> Vec<f64> {
+        let Some(series) = self.series(plot) else {
+            return Vec::new();
+        };
+        let separation = match plot {
+            PlotKind::Waveform => series.values.len() / 32,
+            PlotKind::Spectrum => PEAK_MARKER_SEPARATION,
+        };
+        series.strongest_peaks(self.peak_markers, separation)
+    }
+
+    /// Plot coordinates of a marker position on the first trace.
+    fn marker_point(&self, plot: PlotKind, position: f64) -> Option<[f64; 2]> {
+        let level = self.display_level(plot, self.series(plot)?.value(position)?);
+        let x = match plot {
+            PlotKind::Waveform => position,
+            PlotKind::Spectrum => self.frequency_axis.to_plot(position),
+        };
+        Some([x, level])
+    }
+
+    /// Every marker on a plot with its label, and whether it is the active one.
+    fn marker_labels(&self, plot: PlotKind) -> Vec<([f64; 2], String, bool)> {
+        let mut labels = Vec::new();
+        for (i, marker) in self.markers.iter().enumerate() {
+            if marker.plot != plot {
+                continue;
+            }
+            if let Some(point) = self.marker_point(plot, marker.position) {
+                labels.push((point, format!("M{}", i + 1), self.active_marker == Some(i)));
+            }
+        }
+        for (i, position) in self.peak_positions(plot).into_iter().enumerate() {
+            if let Some(point) = self.marker_point(plot, position) {
+                let label = format!(
+                    "P{} {}, {}",
+                    i + 1,
+                    self.format_position(plot, position),
+                    self.format_level(plot, point[1])
+                );
+                labels.push((point, label, false));
+            }
+        }
+        labels
+    }
+
+    /// Adds a marker on the highest peak of a plot and makes it the active one.
+    fn add_marker(&mut self, plot: PlotKind) {
+        let Some(series) = self.series(plot) else {
+            return;
+        };
+        let position = series
+            .find_peak(0.0, PeakSearch::Highest)
+            .unwrap_or_else(|| series.position(series.values.len() / 2));
+        self.markers.push(Marker { plot, position });
+        self.active_marker = Some(self.markers.len() - 1);
+    }
+
+    fn move_active_marker(&mut self, search: PeakSearch) {
+        let Some(marker) = self.active_marker.and_then(|i| self.markers.get(i)) else {
+            return;
+        };
+        let found = self
+            .series(marker.plot)
+            .and_then(|series| series.find_peak(marker.position, search));
+        if let (Some(position), Some(i)) = (found, self.active_marker) {
+            self.markers[i].position = position;
+        }
+    }
+
+    fn marker_panel(&mut self, ctx: &egui::Context) {
+        if !self.show_markers {
+            return;
+        }
+        egui::SidePanel::right("marker_panel").show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for plot in PlotKind::ALL {
+                    let shown = match plot {
+                        PlotKind::Waveform => self.show_waveform,
+                        PlotKind::Spectrum => self.show_spectrum,
+                    };
+                    if !shown {
+                        continue;
+                    }
+                    ui.heading(plot.to_string());
+                    ui.horizontal(|ui| {
+                        let cursors = match plot {
+                            PlotKind::Waveform => &mut self.waveform_cursors,
+                            PlotKind::Spectrum => &mut self.spectrum_cursors,
+                        };
+                        ui.checkbox(&mut cursors.enabled, "Cursors");
+                        if ui.button("Add marker").clicked() {
+                            self.add_marker(plot);
+                        }
+                    });
+                    self.cursor_readouts(ui, plot);
+                    ui.separator();
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Peak markers:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.peak_markers)
+                            .clamp_range(0..=MAX_PEAK_MARKERS),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    for search in PeakSearch::ALL {
+                        let button = egui::Button::new(search.to_string());
+                        if ui
+                            .add_enabled(self.active_marker.is_some(), button)
+                            .clicked()
+                        {
+                            self.move_active_marker(search);
+                        }
+                    }
+                });
+                self.marker_table(ui);
+            });
+        });
+    }
+
+    /// The highest peaks of the first trace's spectrum, located between bins, with
+    /// their nearest notes.
+    fn peak_table(&mut self, ctx: &egui::Context) {
+        if !self.show_peak_table {
+            return;
+        }
+        let window = self.analysis.analyzer.window().function;
+        if self.peak_estimator.window() != window {
+            self.peak_estimator = PeakEstimator::new(window);
+        }
+        egui::SidePanel::right("peak_table").show(ctx, |ui| {
+            ui.heading("Peaks");
+            ui.horizontal(|ui| {
+                ui.label("Interpolation:");
+                egui::ComboBox::from_id_source("peak_interpolation_select")
+                    .selected_text(self.peak_interpolation.to_string())
+                    .show_ui(ui, |ui| {
+                        for candidate in Interpolation::ALL {
+                            ui.selectable_value(
+                                &mut self.peak_interpolation,
+                                candidate,
+                                candidate.to_string(),
+                            );
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                ui.label("Rows:");
+                ui.add(
+                    egui::DragValue::new(&mut self.peak_table_rows)
+                        .clamp_range(1..=MAX_PEAK_TABLE_ROWS),
+                );
+            });
+
+            let Some(spectrum) = &self.analysis.traces[0].spectrum else {
+                return;
+            };
+            let has_phases = spectrum.phases.iter().all(|phase| phase.is_finite());
+            if self.peak_interpolation == Interpolation::Jacobsen && !has_phases {
+                ui.weak("Averaged spectra have no phases; using magnitudes only.");
+            }
+            let peaks = self.peak_estimator.strongest_weighted(
+                spectrum,
+                self.analysis.traces[0].weighting,
+                self.peak_table_rows,
+                PEAK_MARKER_SEPARATION,
+                self.peak_interpolation,
+            );
+            let levels = self.levels();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("peak_table_rows")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for heading in ["", "Frequency", "Level", "Note"] {
+                            ui.strong(heading);
+                        }
+                        ui.end_row();
+                        for (i, peak) in peaks.iter().enumerate() {
+                            ui.label(format!("{}", i + 1));
+                            ui.label(format!("{:.3} Hz", peak.frequency));
+                            ui.label(levels.format(levels.convert(peak.level_db), 2));
+                            ui.label(peaks::note_name(peak.frequency).unwrap_or_default());
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+    }
+
+    /// Positions of both cursor pairs, their differences, and the first trace's
+    /// level at the vertical cursors.
+    fn cursor_readouts(&self, ui: &mut egui::Ui, plot: PlotKind) {
+        let cursors = self.cursors(plot);
+        egui::Grid::new(("cursor_readouts", plot))
+            .num_columns(2)
+            .show(ui, |ui| {
+                let mut row = |key: &str, value: String| {
+                    ui.label(key);
+                    ui.label(value);
+                    ui.end_row();
+                };
+                if let Some(x) = cursors.vertical() {
+                    let [a, b] = x.map(|x| self.position_at(plot, x));
+                    let name = match plot {
+                        PlotKind::Waveform => "t",
+                        PlotKind::Spectrum => "f",
+                    };
+                    row(&format!("{name}1"), self.format_position(plot, a));
+                    row(&format!("{name}2"), self.format_position(plot, b));
+                    row(&format!("Δ{name}"), self.format_position(plot, b - a));
+                    if plot == PlotKind::Waveform && b != a {
+                        let seconds = (b - a).abs() / self.analysis.sample_rate().max(1) as f64;
+                        row("1/Δt", axes::format_frequency(1.0 / seconds));
+                    }
+                    let levels = self.series(plot).and_then(|series| {
+                        let level =
+                            |position| Some(self.display_level(plot, series.value(position)?));
+                        Some([level(a)?, level(b)?])
+                    });
+                    if let Some([level_a, level_b]) = levels {
+                        row(
+                            &format!("Trace at {name}1"),
+                            self.format_level(plot, level_a),
+                        );
+                        row(
+                            &format!("Trace at {name}2"),
+                            self.format_level(plot, level_b),
+                        );
+                        row("Δ trace", self.format_level_delta(plot, level_b - level_a));
+                    }
+                }
+                if let Some([a, b]) = cursors.horizontal() {
+                    row("Level 1", self.format_level(plot, a));
+                    row("Level 2", self.format_level(plot, b));
+                    row("Δ level", self.format_level_delta(plot, b - a));
+                }
+            });
+    }
+
+    /// Every marker with its position and level, and both relative to the active
+    /// marker when it is on the same plot.
+    fn marker_table(&mut self, ui: &mut egui::Ui) {
+        let mut rows = Vec::new();
+        for (i, marker) in self.markers.iter().enumerate() {
+            rows.push((Some(i), format!("M{}", i + 1), marker.plot, marker.position));
+        }
+        for plot in PlotKind::ALL {
+            for (i, position) in self.peak_positions(plot).into_iter().enumerate() {
+                rows.push((None, format!("P{}", i + 1), plot, position));
+            }
+        }
+        if rows.is_empty() {
+            return;
+        }
+        let active = self
+            .active_marker
+            .and_then(|i| self.markers.get(i))
+            .and_then(|marker| Some((*marker, self.marker_point(marker.plot, marker.position)?)));
+
+        let mut remove = None;
+        egui::Grid::new("marker_table")
+            .striped(true)
+            .show(ui, |ui| {
+                for heading in ["", "Plot", "Position", "Level", "Δ position", "Δ level", ""] {
+                    ui.strong(heading);
+                }
+                ui.end_row();
+                for (index, name, plot, position) in rows {
+                    let selected = index.is_some() && index == self.active_marker;
+                    if ui.selectable_label(selected, name).clicked() && index.is_some() {
+                        self.active_marker = index;
+                    }
+                    ui.label(plot.to_string());
+                    ui.label(self.format_position(plot, position));
+                    let point = self.marker_point(plot, position);
+                    ui.label(
+                        point.map_or(String::new(), |[_, level]| self.format_level(plot, level)),
+                    );
+                    match (active, point) {
+                        (Some((marker, [_, active_level])), Some([_, level]))
+                            if marker.plot == plot && index != self.active_marker =>
+                        {
+                            ui.label(self.format_position(plot, position - marker.position));
+                            ui.label(self.format_level_delta(plot, level - active_level));
+                        }
+                        _ => {
+                            ui.label("");
+                            ui.label("");
+                        }
+                    }
+                    if index.is_some() && ui.small_button("✖").clicked() {
+                        remove = index;
+                    }
+                    ui.end_row();
+                }
+            });
+        if let Some(i) = remove {
+            self.markers.remove(i);
+            self.active_marker = match self.active_marker {
+                Some(active) if active == i => None,
+                Some(active) if active > i => Some(active - 1),
+                active => active,
+            };
+        }
+    }
+
+    fn spectrogram_plot(&mut self, ui: &mut egui::Ui, height: f32) {
+        let texture = self
+            .spectrogram
+            .texture(ui.ctx())
+            .map(|texture| texture.id());
+        let nyquist = self.spectrogram.nyquist();
+        let duration = self.spectrogram.duration();
+
+        egui::plot::Plot::new("spectrogram_plot")
+            .height(height)
+            .width(ui.available_width())
+            .allow_boxed_zoom(false)
+            .x_axis_formatter(|hz, _| axes::format_frequency(hz))
+            .y_axis_formatter(|s, _| format!("{:.1} s", -s))
+            .show(ui, |plot_ui| {
+                if let Some(texture) = texture {
+                    plot_ui.image(egui::plot::PlotImage::new(
+                        texture,
+                        egui::plot::PlotPoint::new(nyquist / 2.0, -duration / 2.0),
+                        egui::vec2(nyquist as f32, duration as f32),
+                    ));
+                }
+            });
     }
 }
 
@@ -86,64 +2239,323 @@ impl eframe::App for AppState {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
+                ui.label("Host:");
+                let mut host_id = self.host.id();
+                egui::ComboBox::from_id_source("host_select")
+                    .selected_text(host_id.name())
+                    .show_ui(ui, |ui| {
+                        for id in cpal::available_hosts() {
+                            ui.selectable_value(&mut host_id, id, id.name());
+                        }
+                    });
+                if host_id != self.host.id() {
+                    let result = self.set_host(host_id);
+                    self.report(result);
+                }
+
                 ui.label("Audio Device:");
-                if egui::ComboBox::from_label("device_select")
-                    .selected_text(self.devices[self.selected_device].name().unwrap_or("Unknown Device".into()))
+                let previous_device = self.selected_device;
+                let selected_name = match self.devices.get(self.selected_device) {
+                    Some(device) => device.name().unwrap_or("Unknown Device".into()),
+                    None => "No input devices".into(),
+                };
+                egui::ComboBox::from_id_source("device_select")
+                    .selected_text(selected_name)
                     .show_ui(ui, |ui| {
                         for (i, dev) in self.devices.iter().enumerate() {
                             let name = dev.name().unwrap_or("Unknown Device".into());
                             ui.selectable_value(&mut self.selected_device, i, name);
                         }
-                    })
-                    .response
-                    .changed()
+                    });
+                if self.selected_device != previous_device && self.is_running() {
+                    self.stop_stream();
+                    let result = self.start_stream();
+                    self.report(result);
+                }
+                if ui
+                    .add_enabled(!self.devices.is_empty(), egui::Button::new("Settings…"))
+                    .clicked()
                 {
-                    if self.is_playing {
-                        self.stop_stream();
-                        self.start_stream();
-                    }
+                    let result = self.open_settings_dialog();
+                    self.report(result);
+                }
+                if ui.button("Refresh").clicked() {
+                    let result = self.refresh_devices();
+                    self.report(result);
                 }
 
-                if self.is_playing {
+                ui.separator();
+                self.file_controls(ui);
+                self.generator_controls(ui);
+
+                ui.separator();
+                self.analysis_controls(ui);
+                ui.separator();
+
+                if self.is_running() {
                     if ui.button("Stop").clicked() {
                         self.stop_stream();
                     }
+                } else if ui
+                    .add_enabled(!self.devices.is_empty(), egui::Button::new("Start"))
+                    .clicked()
+                {
+                    let result = self.start_stream();
+                    self.report(result);
+                }
+            });
+            ui.horizontal(|ui| {
+                self.channel_controls(ui);
+                ui.separator();
+                self.view_controls(ui);
+                ui.separator();
+                if self.show_spectrum {
+                    self.averaging_controls(ui);
+                    ui.separator();
+                }
+                self.record_controls(ui);
+                ui.separator();
+                self.export_controls(ui);
+            });
+            if self.show_spectrum {
+                ui.horizontal(|ui| self.axis_controls(ui));
+            }
+        });
+
+        self.settings_dialog(ctx);
+        self.file_info_window(ctx);
+        self.poll_stream_errors();
+
+        let dropped = ctx.input(|i| i.raw.dropped_files.first().and_then(|f| f.path.clone()));
+        if let Some(path) = dropped {
+            let result = self.open_file(&path);
+            self.report(result);
+        }
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if let Some(err) = &self.last_error {
+                    ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+                } else if self.is_running() {
+                    ui.label("Running");
+                } else if self.devices.is_empty() {
+                    ui.label("No input device");
                 } else {
-                    if ui.button("Start").clicked() {
-                        self.start_stream();
+                    ui.label("Stopped");
+                }
+                if let Some(player) = &self.file {
+                    let file = &player.file;
+                    let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+                    ui.separator();
+                    ui.label(format!(
+                        "{name}: {} Hz, {} ch, {}, {}",
+                        file.sample_rate,
+                        file.channels,
+                        file.encoding,
+                        format_time(file.duration())
+                    ));
+                }
+                if let Some(source) = &self.source {
+                    let mut info = format!(
+                        "{}: {} Hz, {} ch",
+                        source.name(),
+                        source.sample_rate(),
+                        source.channels()
+                    );
+                    if let Some(format) = source.sample_format() {
+                        info += &format!(", {} ({}-bit)", format, format.sample_size() * 8);
                     }
+                    ui.separator();
+                    ui.label(info);
+                    ui.separator();
+                    ui.label(format!("Overflows: {}", source.overflows()));
+                }
+                if let Some(path) = &self.last_export {
+                    ui.separator();
+                    ui.label(format!("Exported {}", path.display()));
+                }
+                if let Some(recorder) = &self.recorder {
+                    ui.separator();
+                    ui.label(format!(
+                        "Recording to {} ({}, {} samples dropped)",
+                        recorder.path().display(),
+                        format_time(recorder.duration()),
+                        recorder.dropped()
+                    ));
                 }
             });
         });
 
+        if self.file.is_some() {
+            egui::TopBottomPanel::bottom("transport_bar").show(ctx, |ui| {
+                ui.horizontal(|ui| self.transport_bar(ui));
+            });
+        }
+
+        self.analysis.set_filter_bank(self.filter_bank_bands());
+        if self.whole_file && self.file.is_some() {
+            if self.whole_file_stale {
+                self.analyze_whole_file();
+            }
+            self.poll_file_job();
+        } else {
+            self.process_new_frames();
+        }
+
+        self.meter_strip(ctx);
+        self.marker_panel(ctx);
+        self.peak_table(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            let audio_data = self.audio_data.read().unwrap();
-            let points: Vec<[f64; 2]> = audio_data
-                .iter()
-                .enumerate()
-                .map(|(i, &sample)| [i as f64, sample as f64])
-                .collect();
-            
-            egui::plot::Plot::new("waveform_plot")
-                .height(ui.available_height())
-                .width(ui.available_width())
-                .show(ui, |plot_ui| {
-                    plot_ui.line(egui::plot::Line::new(
-                        egui::plot::PlotPoints::from_iter(points.into_iter()),
-                    ));
-                });
+            let views = [
+                self.show_waveform,
+                self.show_spectrum,
+                self.show_bands,
+                self.show_spectrogram,
+            ]
+            .iter()
+            .filter(|&&shown| shown)
+            .count();
+            let spacing = ui.spacing().item_spacing.y;
+            let plot_height = ui.available_height() / views.max(1) as f32 - spacing;
+
+            if self.show_waveform {
+                self.waveform_plot(ui, plot_height);
+            }
+            if self.show_spectrum {
+                self.spectrum_plot(ui, plot_height);
+            }
+            if self.show_bands {
+                self.band_plot(ui, plot_height);
+            }
+            if self.show_spectrogram {
+                self.spectrogram_plot(ui, plot_height);
+            }
         });
 
         ctx.request_repaint();
     }
 }
 
+/// A horizontal bar from [`METER_FLOOR_DB`] to full scale: the RMS level in dark
+/// green inside the sample peak, which turns red at the clip level, and a tick at the
+/// held true peak.
+fn meter_bar(ui: &mut egui::Ui, meter: &LevelMeter) {
+    let (rect, _) = ui.allocate_exact_size(METER_BAR_SIZE, egui::Sense::hover());
+    let x = |level: f64| {
+        let t = (dbfs(level) - METER_FLOOR_DB) / -METER_FLOOR_DB;
+        rect.lerp_inside(egui::vec2(t.clamp(0.0, 1.0) as f32, 0.0))
+            .x
+    };
+    let painter = ui.painter();
+    let up_to = |level: f64| egui::Rect::from_x_y_ranges(rect.left()..=x(level), rect.y_range());
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    let peak_color = if meter.peak() >= metrics::CLIP_LEVEL as f64 {
+        egui::Color32::RED
+    } else {
+        egui::Color32::from_rgb(80, 200, 80)
+    };
+    painter.rect_filled(up_to(meter.peak()), 0.0, peak_color);
+    painter.rect_filled(
+        up_to(meter.rms()),
+        0.0,
+        egui::Color32::from_rgb(30, 110, 30),
+    );
+    let hold = x(meter.true_peak_hold());
+    painter.vline(
+        hold,
+        rect.y_range(),
+        egui::Stroke::new(2.0, markers::CURSOR_COLOR),
+    );
+}
+
+/// Draws markers as triangles pointing down at their points, labelled above. The
+/// active marker is drawn larger.
+fn draw_markers(plot_ui: &mut egui::plot::PlotUi, markers: Vec<([f64; 2], String, bool)>) {
+    for (point, label, active) in markers {
+        plot_ui.points(
+            egui::plot::Points::new(point)
+                .shape(egui::plot::MarkerShape::Down)
+                .filled(true)
+                .radius(if active { 7.0 } else { 5.0 })
+                .color(markers::CURSOR_COLOR),
+        );
+        plot_ui.text(
+            egui::plot::Text::new(point.into(), label)
+                .anchor(egui::Align2::CENTER_BOTTOM)
+                .color(markers::CURSOR_COLOR),
+        );
+    }
+}
+
+/// A spectrum line in the spectrum plot, built before the plot so that auto-scaling
+/// can see it.
+struct SpectrumLine {
+    points: Vec<[f64; 2]>,
+    color: egui::Color32,
+    style: egui::plot::LineStyle,
+    name: String,
+}
+
+/// A spectrum as plot points in the shown units, thinned out to at most
+/// `MAX_PLOT_POINTS`. A log axis has no place for DC.
+fn spectrum_points(spectrum: &Spectrum, levels: &Levels, axis: FrequencyAxis) -> Vec<[f64; 2]> {
+    let first = if axis.is_log() { 1 } else { 0 };
+    let points = spectrum
+        .magnitudes_db
+        .iter()
+        .enumerate()
+        .skip(first)
+        .map(|(bin, &db)| [axis.to_plot(spectrum.frequency(bin)), levels.convert(db)])
+        .collect();
+    decimate(points, MAX_PLOT_POINTS)
+}
+
+/// Edits a generator frequency, which has to stay below Nyquist.
+fn frequency_drag(value: &mut f64) -> egui::DragValue<'_> {
+    egui::DragValue::new(value)
+        .clamp_range(1.0..=GENERATOR_SAMPLE_RATE as f64 / 2.0)
+        .suffix(" Hz")
+}
+
+fn format_time(seconds: f64) -> String {
+    format!("{}:{:04.1}", (seconds / 60.0) as u64, seconds % 60.0)
+}
+
+/// Tells the traces apart in every plot, the same colour for the same channel.
+fn trace_color(trace: &Trace) -> egui::Color32 {
+    let index = match trace.channel {
+        Channel::Single(index) => index,
+        mix => Channel::MIXES.iter().position(|&m| m == mix).unwrap() + 2,
+    };
+    TRACE_COLORS[index % TRACE_COLORS.len()]
+}
+
 fn main() {
-    let mut app = AppState::new();
+    let options = cli::parse_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("error: {err}\n\n{}", cli::USAGE);
+        std::process::exit(2);
+    });
+    let host = match options.host.as_deref() {
+        Some(name) => capture::host_from_name(name).unwrap_or_else(|err| {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }),
+        None => cpal::default_host(),
+    };
+    if let Some(command) = options.command {
+        if let Err(err) = cli::run(command, &host) {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let app = AppState::new(host);
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
-        "Audio Waveform Viewer",
+        "FFT Analyzer",
         native_options,
         Box::new(|_cc| Box::new(app)),
-    );
+    )
+    .expect("failed to start the GUI");
 }
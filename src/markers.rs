@@ -0,0 +1,199 @@
+use eframe::egui;
+use eframe::egui::plot::{HLine, LineStyle, PlotUi, VLine};
+use std::borrow::Cow;
+use std::fmt;
+
+use fft_analyzer::dsp::peaks::{self, PeakSearch};
+
+/// How close in points a press has to be to grab a cursor.
+const GRAB_DISTANCE: f32 = 8.0;
+pub const CURSOR_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 200, 60);
+
+/// The plots that have cursors and markers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PlotKind {
+    Waveform,
+    Spectrum,
+}
+
+impl PlotKind {
+    pub const ALL: [PlotKind; 2] = [PlotKind::Waveform, PlotKind::Spectrum];
+}
+
+impl fmt::Display for PlotKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlotKind::Waveform => write!(f, "Waveform"),
+            PlotKind::Spectrum => write!(f, "Spectrum"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CursorLine {
+    Vertical(usize),
+    Horizontal(usize),
+}
+
+/// Two vertical and two horizontal cursors in plot coordinates. They are placed a
+/// third of the way in from the edges of the view when first shown, and dragged with
+/// the primary button, so the plot must not pan with it while they are enabled.
+#[derive(Default)]
+pub struct Cursors {
+    pub enabled: bool,
+    /// Vertical then horizontal positions, once placed.
+    positions: Option<([f64; 2], [f64; 2])>,
+    dragging: Option<CursorLine>,
+}
+
+impl Cursors {
+    /// Positions of the vertical cursors.
+    pub fn vertical(&self) -> Option<[f64; 2]> {
+        self.positions.filter(|_| self.enabled).map(|(x, _)| x)
+    }
+
+    /// Positions of the horizontal cursors.
+    pub fn horizontal(&self) -> Option<[f64; 2]> {
+        self.positions.filter(|_| self.enabled).map(|(_, y)| y)
+    }
+
+    /// Forgets the positions, e.g. after the axes changed what they mean.
+    pub fn clear(&mut self) {
+        self.positions = None;
+    }
+
+    /// Handles dragging and draws the cursors; call from inside `Plot::show`.
+    pub fn show(&mut self, plot_ui: &mut PlotUi) {
+        if !self.enabled {
+            return;
+        }
+        let bounds = plot_ui.plot_bounds();
+        let (min, max) = (bounds.min(), bounds.max());
+        if !bounds.is_valid() {
+            return;
+        }
+        let (x, y) = self.positions.get_or_insert_with(|| {
+            let at = |axis: usize, t: f64| min[axis] + (max[axis] - min[axis]) * t;
+            (
+                [at(0, 1.0 / 3.0), at(0, 2.0 / 3.0)],
+                [at(1, 1.0 / 3.0), at(1, 2.0 / 3.0)],
+            )
+        });
+
+        let (pressed, down) = plot_ui.ctx().input(|input| {
+            (
+                input.pointer.primary_pressed(),
+                input.pointer.primary_down(),
+            )
+        });
+        let pointer = plot_ui.pointer_coordinate();
+        if !down {
+            self.dragging = None;
+        } else if let (true, Some(pointer)) = (pressed && plot_ui.plot_hovered(), pointer) {
+            // The nearest line within reach, measured on screen.
+            let at = plot_ui.screen_from_plot(pointer);
+            let mut nearest = None;
+            let mut nearest_distance = GRAB_DISTANCE;
+            for i in 0..2 {
+                let vertical = plot_ui.screen_from_plot([x[i], pointer.y].into());
+                let horizontal = plot_ui.screen_from_plot([pointer.x, y[i]].into());
+                for (line, distance) in [
+                    (CursorLine::Vertical(i), (vertical.x - at.x).abs()),
+                    (CursorLine::Horizontal(i), (horizontal.y - at.y).abs()),
+                ] {
+                    if distance < nearest_distance {
+                        nearest = Some(line);
+                        nearest_distance = distance;
+                    }
+                }
+            }
+            self.dragging = nearest;
+        }
+        match (self.dragging, pointer) {
+            (Some(CursorLine::Vertical(i)), Some(pointer)) => x[i] = pointer.x,
+            (Some(CursorLine::Horizontal(i)), Some(pointer)) => y[i] = pointer.y,
+            _ => {}
+        }
+
+        for (i, style) in [LineStyle::Solid, LineStyle::dashed_dense()]
+            .into_iter()
+            .enumerate()
+        {
+            plot_ui.vline(VLine::new(x[i]).color(CURSOR_COLOR).style(style));
+            plot_ui.hline(HLine::new(y[i]).color(CURSOR_COLOR).style(style));
+        }
+    }
+}
+
+/// A marker on the first trace of a plot, at a frequency in Hz on the spectrum or an
+/// offset in samples on the waveform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Marker {
+    pub plot: PlotKind,
+    pub position: f64,
+}
+
+/// The first trace of a plot as evenly spaced values for markers to sit on.
+pub struct Series<'a> {
+    pub values: Cow<'a, [f32]>,
+    /// Position units between consecutive values.
+    pub step: f64,
+    /// The first index markers may use; the spectrum leaves out DC.
+    pub first: usize,
+}
+
+impl<'a> Series<'a> {
+    /// A waveform `step` samples between points. Above 1 it is an envelope of the
+    /// minimum and maximum of each bucket, and markers sit on the maxima, which are
+    /// the points a peak search expects.
+    pub fn waveform(values: &'a [f32], step: f64) -> Self {
+        let (values, step) = if step > 1.0 {
+            let maxima = values.iter().skip(1).step_by(2).copied().collect();
+            (Cow::Owned(maxima), 2.0 * step)
+        } else {
+            (Cow::Borrowed(values), step)
+        };
+        Series {
+            values,
+            step,
+            first: 0,
+        }
+    }
+}
+
+impl Series<'_> {
+    /// The index nearest a position.
+    pub fn index(&self, position: f64) -> usize {
+        let last = self.values.len().saturating_sub(1);
+        ((position / self.step).round().max(0.0) as usize).clamp(self.first.min(last), last)
+    }
+
+    pub fn position(&self, index: usize) -> f64 {
+        index as f64 * self.step
+    }
+
+    /// The value nearest a position.
+    pub fn value(&self, position: f64) -> Option<f32> {
+        self.values.get(self.index(position)).copied()
+    }
+
+    /// Where `search` leads from `position`.
+    pub fn find_peak(&self, position: f64, search: PeakSearch) -> Option<f64> {
+        let values = self.values.get(self.first..)?;
+        let from = self.index(position).saturating_sub(self.first);
+        let index = peaks::find_peak(values, from, search)?;
+        Some(self.position(index + self.first))
+    }
+
+    /// Positions of up to `count` of the highest peaks, highest first, at least
+    /// `min_separation` values apart.
+    pub fn strongest_peaks(&self, count: usize, min_separation: usize) -> Vec<f64> {
+        let Some(values) = self.values.get(self.first..) else {
+            return Vec::new();
+        };
+        peaks::strongest_peaks(values, count, min_separation)
+            .into_iter()
+            .map(|index| self.position(index + self.first))
+            .collect()
+    }
+}
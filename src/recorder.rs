@@ -0,0 +1,727 @@
+//! Recording captured audio to WAV and FLAC files on a writer thread.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use flacenc::component::{BitRepr, Frame, FrameHeader, SampleSizeSpec, StreamInfo};
+use flacenc::error::Verify;
+use flacenc::source::{Fill, FrameBuf};
+
+use crate::capture::tap::TapReader;
+use crate::error::AudioError;
+
+/// Placeholders understood by [`RecordSettings::template`].
+pub const TEMPLATE_HELP: &str = "{date} {time} {device} {rate} {channels} {n} (part number)";
+
+/// Hound refuses to write WAV files past the 4 GiB the header can describe.
+const WAV_SIZE_LIMIT: u64 = u32::MAX as u64;
+const WAV_HEADER_BYTES: u64 = 44;
+const FLAC_BLOCK_SIZE: usize = 4096;
+const FLAC_MIN_BLOCK_SIZE: usize = 64;
+/// "fLaC" followed by a STREAMINFO block.
+const FLAC_HEADER_BYTES: u64 = 4 + 4 + 34;
+/// How often the writer drains a tap.
+const TAP_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Container and sample encoding of a recording.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// 16-bit integer PCM in WAV.
+    Wav16,
+    /// 24-bit integer PCM in WAV.
+    Wav24,
+    /// 32-bit float PCM in WAV; keeps overs above full scale.
+    Wav32Float,
+    /// 16-bit lossless FLAC.
+    Flac16,
+    /// 24-bit lossless FLAC.
+    Flac24,
+}
+
+impl RecordFormat {
+    /// Every format, in menu order.
+    pub const ALL: [RecordFormat; 5] = [
+        RecordFormat::Wav16,
+        RecordFormat::Wav24,
+        RecordFormat::Wav32Float,
+        RecordFormat::Flac16,
+        RecordFormat::Flac24,
+    ];
+
+    /// File name extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            RecordFormat::Wav16 | RecordFormat::Wav24 | RecordFormat::Wav32Float => "wav",
+            RecordFormat::Flac16 | RecordFormat::Flac24 => "flac",
+        }
+    }
+
+    fn bits(self) -> u16 {
+        match self {
+            RecordFormat::Wav16 | RecordFormat::Flac16 => 16,
+            RecordFormat::Wav24 | RecordFormat::Flac24 => 24,
+            RecordFormat::Wav32Float => 32,
+        }
+    }
+}
+
+impl fmt::Display for RecordFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordFormat::Wav16 => write!(f, "WAV 16-bit"),
+            RecordFormat::Wav24 => write!(f, "WAV 24-bit"),
+            RecordFormat::Wav32Float => write!(f, "WAV 32-bit float"),
+            RecordFormat::Flac16 => write!(f, "FLAC 16-bit"),
+            RecordFormat::Flac24 => write!(f, "FLAC 24-bit"),
+        }
+    }
+}
+
+/// How and where to record.
+#[derive(Clone, Debug)]
+pub struct RecordSettings {
+    /// Encoding of every file.
+    pub format: RecordFormat,
+    /// File name without extension; may include directories and the placeholders
+    /// listed in [`TEMPLATE_HELP`].
+    pub template: String,
+    /// Start a new file once the current one reaches this many bytes.
+    pub max_file_bytes: Option<u64>,
+}
+
+impl Default for RecordSettings {
+    fn default() -> Self {
+        RecordSettings {
+            format: RecordFormat::Wav24,
+            template: "recording-{date}-{time}".into(),
+            max_file_bytes: None,
+        }
+    }
+}
+
+/// Values substituted into the file name template; fixed for the whole recording
+/// so that split parts share their date and time.
+struct FileNamer {
+    template: String,
+    extension: &'static str,
+    date: String,
+    time: String,
+    device: String,
+    sample_rate: u32,
+    channels: usize,
+}
+
+impl FileNamer {
+    fn path(&self, part: usize) -> PathBuf {
+        let mut name = self
+            .template
+            .replace("{date}", &self.date)
+            .replace("{time}", &self.time)
+            .replace("{device}", &self.device)
+            .replace("{rate}", &self.sample_rate.to_string())
+            .replace("{channels}", &self.channels.to_string());
+        if name.contains("{n}") {
+            name = name.replace("{n}", &format!("{part:03}"));
+        } else if part > 1 {
+            // Without a part number every split would overwrite the first file.
+            name = format!("{name}-{part:03}");
+        }
+        PathBuf::from(format!("{name}.{}", self.extension))
+    }
+}
+
+/// Keeps device names like "hw:CARD=PCH,DEV=0" from turning into directories.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Scales a sample in [-1, 1] to a signed integer of `bits` bits, clipping overs.
+fn quantize(sample: f32, bits: u16) -> i32 {
+    let scale = (1i64 << (bits - 1)) as f32;
+    (sample * scale).round().clamp(-scale, scale - 1.0) as i32
+}
+
+/// Streams interleaved samples to disk from a writer thread, splitting files at the
+/// configured size. Given a device's tap the thread drains it itself, so the
+/// recording is complete however rarely the UI gets to read; otherwise the caller
+/// forwards samples with [`Recorder::write`]. Neither the audio callback nor the UI
+/// ever waits on the disk.
+pub struct Recorder {
+    sender: Option<mpsc::Sender<Vec<f32>>>,
+    thread: Option<JoinHandle<()>>,
+    tapped: bool,
+    sample_rate: u32,
+    status: Arc<Status>,
+}
+
+/// What the writer thread reports back while it runs.
+struct Status {
+    path: Mutex<PathBuf>,
+    frames: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Recorder {
+    /// Creates the first file right away so bad templates and permissions are reported
+    /// before recording starts. Records what `tap` delivers if given one. Later write
+    /// errors are sent to `errors`.
+    pub fn start(
+        settings: &RecordSettings,
+        device: &str,
+        sample_rate: u32,
+        channels: usize,
+        tap: Option<TapReader>,
+        errors: mpsc::Sender<AudioError>,
+    ) -> Result<Self, AudioError> {
+        let now = chrono::Local::now();
+        let namer = FileNamer {
+            template: settings.template.clone(),
+            extension: settings.format.extension(),
+            date: now.format("%Y-%m-%d").to_string(),
+            time: now.format("%H%M%S").to_string(),
+            device: sanitize(device),
+            sample_rate,
+            channels,
+        };
+        let first_path = namer.path(1);
+        let writer = Writer::create(&first_path, settings.format, sample_rate, channels)?;
+        let status = Arc::new(Status {
+            path: Mutex::new(first_path),
+            frames: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        });
+        let tapped = tap.is_some();
+
+        let mut limit = settings.max_file_bytes.unwrap_or(u64::MAX);
+        if settings.format.extension() == "wav" {
+            limit = limit.min(WAV_SIZE_LIMIT);
+        }
+        let (sender, receiver) = mpsc::channel::<Vec<f32>>();
+        let mut thread = WriterThread {
+            namer,
+            format: settings.format,
+            sample_rate,
+            channels,
+            limit,
+            part: 1,
+            status: status.clone(),
+        };
+        let handle = std::thread::Builder::new()
+            .name("recorder".into())
+            .spawn(move || {
+                if let Err(err) = thread.run(writer, receiver, tap) {
+                    let _ = errors.send(err);
+                }
+            })
+            .map_err(AudioError::Record)?;
+
+        Ok(Recorder {
+            sender: Some(sender),
+            thread: Some(handle),
+            tapped,
+            sample_rate,
+            status,
+        })
+    }
+
+    /// Queues interleaved samples for writing, unless the recorder drains a tap, which
+    /// already delivers them. Returns `false` once the writer has stopped because of
+    /// an error.
+    pub fn write(&mut self, samples: Vec<f32>) -> bool {
+        if !self.tapped && !samples.is_empty() {
+            if let Some(sender) = &self.sender {
+                // A closed channel means the writer stopped, which the check below sees.
+                let _ = sender.send(samples);
+            }
+        }
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Seconds of audio written so far.
+    pub fn duration(&self) -> f64 {
+        self.status.frames.load(Ordering::Relaxed) as f64 / self.sample_rate as f64
+    }
+
+    /// Samples missing from the recording because the writer fell behind the device.
+    pub fn dropped(&self) -> u64 {
+        self.status.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The file currently being written.
+    pub fn path(&self) -> PathBuf {
+        self.status.path.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Recorder {
+    /// Lets the writer drain its queue and finalize the file headers.
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct WriterThread {
+    namer: FileNamer,
+    format: RecordFormat,
+    sample_rate: u32,
+    channels: usize,
+    limit: u64,
+    part: usize,
+    status: Arc<Status>,
+}
+
+impl WriterThread {
+    fn run(
+        &mut self,
+        mut writer: Writer,
+        receiver: mpsc::Receiver<Vec<f32>>,
+        mut tap: Option<TapReader>,
+    ) -> Result<(), AudioError> {
+        let mut chunk = Vec::new();
+        let mut stopping = false;
+        while !stopping {
+            match &mut tap {
+                // Only the tap delivers samples; the channel closing says when to stop.
+                Some(tap) => {
+                    let received = receiver.recv_timeout(TAP_POLL_INTERVAL);
+                    stopping = received == Err(mpsc::RecvTimeoutError::Disconnected);
+                    chunk.clear();
+                    tap.read(&mut chunk);
+                    let dropped = tap.overflows();
+                    self.status.dropped.store(dropped, Ordering::Relaxed);
+                }
+                None => match receiver.recv() {
+                    Ok(samples) => chunk = samples,
+                    Err(_) => break,
+                },
+            }
+            let mut frames = &chunk[..];
+            while !frames.is_empty() {
+                let room = writer.room(self.limit, self.channels);
+                if room == 0 {
+                    writer.finish()?;
+                    self.part += 1;
+                    let path = self.namer.path(self.part);
+                    writer = Writer::create(&path, self.format, self.sample_rate, self.channels)?;
+                    *self.status.path.lock().unwrap() = path;
+                    continue;
+                }
+                let len = room.saturating_mul(self.channels).min(frames.len());
+                writer.write(&frames[..len])?;
+                frames = &frames[len..];
+                let written = (len / self.channels) as u64;
+                self.status.frames.fetch_add(written, Ordering::Relaxed);
+            }
+        }
+        writer.finish()
+    }
+}
+
+enum Writer {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Flac(Box<FlacWriter>),
+}
+
+impl Writer {
+    fn create(
+        path: &Path,
+        format: RecordFormat,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Result<Self, AudioError> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(AudioError::Record)?;
+        }
+        match format {
+            RecordFormat::Wav16 | RecordFormat::Wav24 | RecordFormat::Wav32Float => {
+                let spec = hound::WavSpec {
+                    channels: channels as u16,
+                    sample_rate,
+                    bits_per_sample: format.bits(),
+                    sample_format: match format {
+                        RecordFormat::Wav32Float => hound::SampleFormat::Float,
+                        _ => hound::SampleFormat::Int,
+                    },
+                };
+                let writer = hound::WavWriter::create(path, spec).map_err(AudioError::WriteWav)?;
+                Ok(Writer::Wav(writer))
+            }
+            RecordFormat::Flac16 | RecordFormat::Flac24 => {
+                let bits = format.bits() as usize;
+                let writer = FlacWriter::create(path, sample_rate, channels, bits)?;
+                Ok(Writer::Flac(Box::new(writer)))
+            }
+        }
+    }
+
+    /// Frames that still fit under `limit` bytes. FLAC sizes are only known after
+    /// encoding and parts end on block boundaries, so they may overshoot by a block.
+    fn room(&self, limit: u64, channels: usize) -> usize {
+        match self {
+            Writer::Wav(writer) => {
+                let spec = writer.spec();
+                let frame_bytes = channels as u64 * spec.bits_per_sample as u64 / 8;
+                let written =
+                    WAV_HEADER_BYTES + writer.len() as u64 * spec.bits_per_sample as u64 / 8;
+                (limit.saturating_sub(written) / frame_bytes).min(usize::MAX as u64) as usize
+            }
+            // Complete the block in progress; the next part starts once it is encoded.
+            Writer::Flac(writer) if writer.bytes >= limit => {
+                FLAC_BLOCK_SIZE.saturating_sub(writer.pending.len() / channels)
+            }
+            Writer::Flac(_) => usize::MAX,
+        }
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), AudioError> {
+        match self {
+            Writer::Wav(writer) => {
+                let spec = writer.spec();
+                for &sample in samples {
+                    match spec.sample_format {
+                        hound::SampleFormat::Float => writer.write_sample(sample),
+                        hound::SampleFormat::Int => {
+                            writer.write_sample(quantize(sample, spec.bits_per_sample))
+                        }
+                    }
+                    .map_err(AudioError::WriteWav)?;
+                }
+                Ok(())
+            }
+            Writer::Flac(writer) => writer.write(samples),
+        }
+    }
+
+    fn finish(self) -> Result<(), AudioError> {
+        match self {
+            Writer::Wav(writer) => writer.finalize().map_err(AudioError::WriteWav),
+            Writer::Flac(writer) => writer.finish(),
+        }
+    }
+}
+
+fn flac_error(err: impl fmt::Display) -> AudioError {
+    AudioError::EncodeFlac(err.to_string())
+}
+
+/// Encodes blocks as they fill and writes them straight to disk, then rewrites
+/// STREAMINFO with the final sizes and sample count when finished. A block is only
+/// encoded once the samples after it could make a block of their own, so a tail too
+/// short for flacenc joins the last block instead. That makes the last block longer
+/// than the rest, so frames carry their first sample number, as in a variable block
+/// size stream, rather than a frame number.
+struct FlacWriter {
+    file: BufWriter<File>,
+    config: flacenc::error::Verified<flacenc::config::Encoder>,
+    info: StreamInfo,
+    channels: usize,
+    bits: u16,
+    block: FrameBuf,
+    /// Quantized interleaved samples waiting for a full block.
+    pending: Vec<i32>,
+    /// Frames written so far, which is where the next block starts.
+    total_frames: usize,
+    bytes: u64,
+}
+
+impl FlacWriter {
+    fn create(
+        path: &Path,
+        sample_rate: u32,
+        channels: usize,
+        bits: usize,
+    ) -> Result<Self, AudioError> {
+        let config = flacenc::config::Encoder::default()
+            .into_verified()
+            .map_err(|(_, err)| flac_error(err))?;
+        let info = StreamInfo::new(sample_rate as usize, channels, bits).map_err(flac_error)?;
+        let block = FrameBuf::with_size(channels, FLAC_BLOCK_SIZE).map_err(flac_error)?;
+        let mut file = BufWriter::new(File::create(path).map_err(AudioError::Record)?);
+        file.write_all(b"fLaC").map_err(AudioError::Record)?;
+        write_stream_info(&mut file, &info)?;
+        Ok(FlacWriter {
+            file,
+            config,
+            info,
+            channels,
+            bits: bits as u16,
+            block,
+            pending: Vec::with_capacity((FLAC_BLOCK_SIZE + FLAC_MIN_BLOCK_SIZE) * channels),
+            total_frames: 0,
+            bytes: FLAC_HEADER_BYTES,
+        })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), AudioError> {
+        let block_len = FLAC_BLOCK_SIZE * self.channels;
+        let held_len = block_len + FLAC_MIN_BLOCK_SIZE * self.channels;
+        for &sample in samples {
+            self.pending.push(quantize(sample, self.bits));
+            if self.pending.len() == held_len {
+                self.block
+                    .fill_interleaved(&self.pending[..block_len])
+                    .map_err(flac_error)?;
+                self.pending.drain(..block_len);
+                let frame = encode_block(&self.config, &self.block, self.total_frames, &self.info)?;
+                self.write_frame(&frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<(), AudioError> {
+        self.info.update_frame_info(frame);
+        let mut sink = flacenc::bitsink::ByteSink::new();
+        frame.write(&mut sink).map_err(flac_error)?;
+        self.file
+            .write_all(sink.as_slice())
+            .map_err(AudioError::Record)?;
+        self.bytes += sink.as_slice().len() as u64;
+        self.total_frames += frame.block_size();
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), AudioError> {
+        let frames = self.pending.len() / self.channels;
+        if frames >= FLAC_MIN_BLOCK_SIZE {
+            let mut block = FrameBuf::with_size(self.channels, frames).map_err(flac_error)?;
+            block.fill_interleaved(&self.pending).map_err(flac_error)?;
+            let frame = encode_block(&self.config, &block, self.total_frames, &self.info)?;
+            self.write_frame(&frame)?;
+        } else if frames > 0 {
+            // Only a recording shorter than flacenc's smallest block gets here. Its one
+            // frame is written by hand as the short last block of a fixed-size stream.
+            let frame = verbatim_frame(&self.pending, self.channels, self.bits)?;
+            self.file.write_all(&frame).map_err(AudioError::Record)?;
+            self.bytes += frame.len() as u64;
+            self.total_frames = frames;
+            self.info
+                .set_block_sizes(FLAC_BLOCK_SIZE, FLAC_BLOCK_SIZE)
+                .map_err(flac_error)?;
+            let len = frame.len();
+            self.info.set_frame_sizes(len, len).map_err(flac_error)?;
+        }
+        self.info.set_total_samples(self.total_frames);
+        self.file
+            .seek(SeekFrom::Start(4))
+            .map_err(AudioError::Record)?;
+        write_stream_info(&mut self.file, &self.info)?;
+        self.file.flush().map_err(AudioError::Record)
+    }
+}
+
+/// Encodes a block starting `first_sample` frames into the stream.
+fn encode_block(
+    config: &flacenc::error::Verified<flacenc::config::Encoder>,
+    block: &FrameBuf,
+    first_sample: usize,
+    info: &StreamInfo,
+) -> Result<Frame, AudioError> {
+    let frame =
+        flacenc::encode_fixed_size_frame(config, block, 0, info).map_err(|err| match err {
+            flacenc::error::EncodeError::Config(err) => flac_error(err),
+            err => flac_error(format!("{err:?}")),
+        })?;
+    // flacenc only numbers frames, so the header is rebuilt with the sample number.
+    let (header, subframes) = frame.into_parts();
+    let sample_size = header
+        .bits_per_sample()
+        .and_then(|bits| SampleSizeSpec::from_bits(bits as u8))
+        .unwrap_or(SampleSizeSpec::Unspecified);
+    let header = FrameHeader::new_variable_size(
+        header.block_size(),
+        header.channel_assignment().clone(),
+        sample_size,
+        first_sample,
+    )
+    .map_err(flac_error)?;
+    Frame::new(header, subframes.into_iter()).map_err(flac_error)
+}
+
+/// The first and only frame of a fixed-size stream, holding the interleaved `samples`
+/// verbatim. flacenc refuses blocks shorter than [`FLAC_MIN_BLOCK_SIZE`], which FLAC
+/// allows for the last one.
+fn verbatim_frame(samples: &[i32], channels: usize, bits: u16) -> Result<Vec<u8>, AudioError> {
+    let frames = samples.len() / channels;
+    let sample_size = match bits {
+        16 => 0b100,
+        24 => 0b110,
+        bits => return Err(flac_error(format!("{bits}-bit samples"))),
+    };
+    // Sync code with fixed block sizes; the block size in a byte after the frame
+    // number and the sample rate from STREAMINFO; independent channels; frame 0.
+    let mut frame = vec![
+        0xFF,
+        0xF8,
+        0x60,
+        ((channels as u8 - 1) << 4) | (sample_size << 1),
+        0,
+        (frames - 1) as u8,
+    ];
+    frame.push(crc8(&frame));
+    let bytes = bits as usize / 8;
+    for channel in 0..channels {
+        // Verbatim, without wasted bits.
+        frame.push(0x02);
+        for sample in samples.iter().skip(channel).step_by(channels) {
+            frame.extend_from_slice(&sample.to_be_bytes()[4 - bytes..]);
+        }
+    }
+    let crc = crc16(&frame);
+    frame.extend_from_slice(&crc.to_be_bytes());
+    Ok(frame)
+}
+
+/// CRC-8 with polynomial 0x07, which protects a FLAC frame header.
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| match crc & 0x80 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x07,
+        })
+    })
+}
+
+/// CRC-16 with polynomial 0x8005, which ends a FLAC frame.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| match crc & 0x8000 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x8005,
+        })
+    })
+}
+
+/// Writes STREAMINFO as the only, and therefore last, metadata block.
+fn write_stream_info(file: &mut BufWriter<File>, info: &StreamInfo) -> Result<(), AudioError> {
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    info.write(&mut sink).map_err(flac_error)?;
+    let len = sink.as_slice().len() as u32;
+    let header = [0x80, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+    file.write_all(&header).map_err(AudioError::Record)?;
+    file.write_all(sink.as_slice()).map_err(AudioError::Record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_file::AudioFile;
+
+    fn namer(template: &str) -> FileNamer {
+        FileNamer {
+            template: template.into(),
+            extension: "wav",
+            date: "20240102".into(),
+            time: "030405".into(),
+            device: sanitize("hw:CARD=PCH,DEV=0"),
+            sample_rate: 48000,
+            channels: 2,
+        }
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        let namer = namer("{date}-{time}-{device}-{rate}-{channels}");
+        assert_eq!(
+            namer.path(1),
+            PathBuf::from("20240102-030405-hw_CARD=PCH,DEV=0-48000-2.wav")
+        );
+    }
+
+    #[test]
+    fn numbers_split_parts() {
+        assert_eq!(namer("take").path(1), PathBuf::from("take.wav"));
+        assert_eq!(namer("take").path(2), PathBuf::from("take-002.wav"));
+        assert_eq!(namer("take-{n}").path(1), PathBuf::from("take-001.wav"));
+    }
+
+    #[test]
+    fn quantizes_and_clips() {
+        assert_eq!(quantize(0.5, 16), 16384);
+        assert_eq!(quantize(-1.0, 16), -32768);
+        assert_eq!(quantize(1.0, 16), 32767);
+        assert_eq!(quantize(2.0, 24), (1 << 23) - 1);
+    }
+
+    #[test]
+    fn records_wav_and_flac_losslessly() {
+        let dir = std::env::temp_dir().join(format!("fft-analyzer-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Longer than one FLAC block, ending in a tail of 904 frames, and of 10 frames,
+        // which is too short for a block of its own; and shorter than any block.
+        for frames in [5000, FLAC_BLOCK_SIZE * 2 + 10, 10] {
+            // Exactly representable at 16 bits.
+            let samples: Vec<f32> = (0..frames * 2)
+                .map(|i| ((i % 200) as f32 - 100.0) / 128.0)
+                .collect();
+            for format in [RecordFormat::Wav16, RecordFormat::Flac16] {
+                let settings = RecordSettings {
+                    format,
+                    template: dir.join("take").display().to_string(),
+                    max_file_bytes: None,
+                };
+                let (errors, _) = mpsc::channel();
+                let mut recorder =
+                    Recorder::start(&settings, "test", 48000, 2, None, errors).unwrap();
+                assert!(recorder.write(samples.clone()));
+                let path = recorder.path();
+                drop(recorder);
+                let file = AudioFile::open(&path).unwrap();
+                assert_eq!((file.sample_rate, file.channels), (48000, 2));
+                assert_eq!(file.frames(), frames, "{format}");
+                assert_eq!(file.samples, samples, "{format}");
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn frame_checksums() {
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc16(b"123456789"), 0xFEE8);
+    }
+
+    #[test]
+    fn records_from_a_tap() {
+        let dir = std::env::temp_dir().join(format!("fft-analyzer-tap-{}", std::process::id()));
+        let settings = RecordSettings {
+            format: RecordFormat::Wav32Float,
+            template: dir.join("take").display().to_string(),
+            max_file_bytes: None,
+        };
+        let (mut input, tap) = crate::capture::tap::tap(8);
+        let (errors, _) = mpsc::channel();
+        let mut recorder =
+            Recorder::start(&settings, "test", 48000, 2, Some(tap.attach()), errors).unwrap();
+        input.push_all([0.5, -0.5].into_iter());
+        // Already delivered through the tap, so not written twice.
+        assert!(recorder.write(vec![0.25, -0.25]));
+        // Too much for the tap at once.
+        input.push_all([0.0; 10].into_iter());
+        std::thread::sleep(TAP_POLL_INTERVAL * 5);
+        assert_eq!(recorder.dropped(), 10);
+        input.push_all([0.125, -0.125].into_iter());
+        let path = recorder.path();
+        drop(recorder);
+
+        let file = AudioFile::open(&path).unwrap();
+        assert_eq!(file.samples, [0.5, -0.5, 0.125, -0.125]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
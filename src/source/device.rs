@@ -0,0 +1,113 @@
+use std::sync::mpsc;
+
+use cpal::traits::DeviceTrait;
+use cpal::SampleFormat;
+
+use super::AudioSource;
+use crate::capture::device_settings::DeviceSettings;
+use crate::capture::ring_buffer::{self, Consumer};
+use crate::capture::tap::{self, Tap, TapReader};
+use crate::capture::{self, InputStream};
+use crate::error::AudioError;
+
+/// Live input from a cpal device, queued through a ring buffer between the audio
+/// callback and [`AudioSource::read`].
+pub struct DeviceSource {
+    device: cpal::Device,
+    name: String,
+    settings: Option<DeviceSettings>,
+    config: cpal::StreamConfig,
+    sample_format: SampleFormat,
+    ring_capacity: usize,
+    errors: mpsc::Sender<AudioError>,
+    running: Option<(InputStream, Consumer, Tap)>,
+    /// Overflows of streams already stopped, so the count survives a restart.
+    past_overflows: u64,
+}
+
+impl DeviceSource {
+    /// Prepares `device` with the given settings, or its default configuration. The
+    /// stream opens on [`AudioSource::start`]; errors it raises while running are
+    /// sent to `errors`, and up to `ring_capacity` samples are queued between reads.
+    pub fn new(
+        device: cpal::Device,
+        settings: Option<DeviceSettings>,
+        ring_capacity: usize,
+        errors: mpsc::Sender<AudioError>,
+    ) -> Result<Self, AudioError> {
+        let (config, sample_format) = capture::stream_config(&device, settings.as_ref())?;
+        Ok(DeviceSource {
+            name: device.name().unwrap_or_default(),
+            device,
+            settings,
+            config,
+            sample_format,
+            ring_capacity,
+            errors,
+            running: None,
+            past_overflows: 0,
+        })
+    }
+}
+
+impl AudioSource for DeviceSource {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate.0
+    }
+
+    fn channels(&self) -> usize {
+        self.config.channels as usize
+    }
+
+    fn start(&mut self) -> Result<(), AudioError> {
+        if self.running.is_some() {
+            return Ok(());
+        }
+        let (producer, consumer) = ring_buffer::ring_buffer(self.ring_capacity);
+        let (tap_input, tap) = tap::tap(self.ring_capacity);
+        let input = capture::open_input(
+            &self.device,
+            self.settings.as_ref(),
+            producer,
+            tap_input,
+            self.errors.clone(),
+        )?;
+        self.config = input.config.clone();
+        self.sample_format = input.sample_format;
+        self.running = Some((input, consumer, tap));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some((_, consumer, _)) = self.running.take() {
+            self.past_overflows += consumer.overflows();
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    fn read(&mut self, out: &mut Vec<f32>) {
+        if let Some((_, consumer, _)) = &mut self.running {
+            consumer.pop_each(|sample| out.push(sample));
+        }
+    }
+
+    fn tap(&self) -> Option<TapReader> {
+        self.running.as_ref().map(|(_, _, tap)| tap.attach())
+    }
+
+    fn overflows(&self) -> u64 {
+        let running = self.running.as_ref().map_or(0, |(_, c, _)| c.overflows());
+        self.past_overflows + running
+    }
+
+    fn sample_format(&self) -> Option<SampleFormat> {
+        Some(self.sample_format)
+    }
+}
@@ -0,0 +1,365 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+use super::{AudioSource, Pacer, Pacing};
+use crate::error::AudioError;
+
+/// A test signal the [`Generator`] can synthesize.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Signal {
+    /// A single tone.
+    Sine {
+        /// Frequency in Hz.
+        frequency: f64,
+    },
+    /// Equal-level tones summed, with phases spread to keep the crest factor low.
+    Multitone {
+        /// Frequency of each tone in Hz.
+        frequencies: Vec<f64>,
+    },
+    /// Uniformly distributed noise with a flat spectrum.
+    WhiteNoise,
+    /// Noise falling 3 dB per octave, so every octave carries the same power.
+    PinkNoise,
+    /// A logarithmic sine sweep, restarted every `duration` seconds.
+    Sweep {
+        /// First frequency in Hz.
+        start: f64,
+        /// Last frequency in Hz.
+        end: f64,
+        /// Seconds from `start` to `end`.
+        duration: f64,
+    },
+    /// Digital silence.
+    Silence,
+}
+
+impl Signal {
+    /// One signal of each kind with typical parameters, in display order.
+    pub fn defaults() -> [Signal; 6] {
+        [
+            Signal::Sine { frequency: 1000.0 },
+            Signal::Multitone {
+                frequencies: vec![100.0, 1000.0, 10000.0],
+            },
+            Signal::WhiteNoise,
+            Signal::PinkNoise,
+            Signal::Sweep {
+                start: 20.0,
+                end: 20000.0,
+                duration: 5.0,
+            },
+            Signal::Silence,
+        ]
+    }
+
+    /// Whether `other` is the same kind of signal, ignoring its parameters.
+    pub fn same_kind(&self, other: &Signal) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Signal::Sine { .. } => write!(f, "Sine"),
+            Signal::Multitone { .. } => write!(f, "Multitone"),
+            Signal::WhiteNoise => write!(f, "White noise"),
+            Signal::PinkNoise => write!(f, "Pink noise"),
+            Signal::Sweep { .. } => write!(f, "Sweep"),
+            Signal::Silence => write!(f, "Silence"),
+        }
+    }
+}
+
+/// Synthesizes a [`Signal`] on every channel, from a fixed noise seed so that two
+/// generators with the same settings produce identical samples.
+pub struct Generator {
+    signal: Signal,
+    amplitude: f32,
+    sample_rate: u32,
+    channels: usize,
+    pacer: Pacer,
+    /// Frames generated so far.
+    position: u64,
+    noise: Noise,
+}
+
+impl Generator {
+    /// A stopped generator producing `signal` with a peak of `amplitude` relative to
+    /// full scale, paced in real time.
+    pub fn new(signal: Signal, amplitude: f32, sample_rate: u32, channels: usize) -> Self {
+        Generator {
+            signal,
+            amplitude,
+            sample_rate,
+            channels: channels.max(1),
+            pacer: Pacer::new(Pacing::RealTime),
+            position: 0,
+            noise: Noise::new(),
+        }
+    }
+
+    /// Changes how many frames each [`AudioSource::read`] delivers.
+    pub fn with_pacing(mut self, pacing: Pacing) -> Self {
+        self.pacer.pacing = pacing;
+        self
+    }
+
+    /// The signal being generated.
+    pub fn signal(&self) -> &Signal {
+        &self.signal
+    }
+
+    /// Appends the next `frames` frames to `out`, independently of the pacing.
+    pub fn generate(&mut self, frames: usize, out: &mut Vec<f32>) {
+        out.reserve(frames * self.channels);
+        for _ in 0..frames {
+            let sample = self.amplitude * self.next_sample();
+            out.extend(std::iter::repeat_n(sample, self.channels));
+            self.position += 1;
+        }
+    }
+
+    /// The next sample at unit amplitude.
+    fn next_sample(&mut self) -> f32 {
+        let sample_rate = self.sample_rate as f64;
+        let t = self.position as f64 / sample_rate;
+        match &self.signal {
+            Signal::Sine { frequency } => (2.0 * PI * frequency * t).sin() as f32,
+            Signal::Multitone { frequencies } => {
+                let n = frequencies.len() as f64;
+                let sum: f64 = frequencies
+                    .iter()
+                    .enumerate()
+                    .map(|(k, frequency)| {
+                        // Schroeder phases.
+                        let phase = PI * (k * k) as f64 / n;
+                        (2.0 * PI * frequency * t + phase).sin()
+                    })
+                    .sum();
+                (sum / n.max(1.0)) as f32
+            }
+            Signal::WhiteNoise => self.noise.white(),
+            Signal::PinkNoise => self.noise.pink(),
+            &Signal::Sweep {
+                start,
+                end,
+                duration,
+            } => {
+                let period = (duration * sample_rate).max(1.0) as u64;
+                let t = (self.position % period) as f64 / sample_rate;
+                let rate = (end / start).ln() / duration;
+                // Integral of start * e^(rate * t), or a plain tone if there is no sweep.
+                let phase = if rate.abs() < 1e-12 {
+                    2.0 * PI * start * t
+                } else {
+                    2.0 * PI * start * ((rate * t).exp() - 1.0) / rate
+                };
+                phase.sin() as f32
+            }
+            Signal::Silence => 0.0,
+        }
+    }
+}
+
+impl AudioSource for Generator {
+    fn name(&self) -> String {
+        let level = 20.0 * self.amplitude.log10();
+        let signal = match &self.signal {
+            Signal::Sine { frequency } => format!("Sine {frequency} Hz"),
+            Signal::Multitone { frequencies } => format!("Multitone, {} tones", frequencies.len()),
+            Signal::Sweep { start, end, .. } => format!("Sweep {start}-{end} Hz"),
+            signal => signal.to_string(),
+        };
+        format!("{signal} at {level:.1} dBFS")
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn start(&mut self) -> Result<(), AudioError> {
+        self.pacer.start(self.position);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.pacer.stop();
+    }
+
+    fn is_running(&self) -> bool {
+        self.pacer.is_running()
+    }
+
+    fn read(&mut self, out: &mut Vec<f32>) {
+        let target = self.pacer.target(self.position, self.sample_rate);
+        self.generate((target - self.position) as usize, out);
+    }
+}
+
+/// xorshift64* white noise and Paul Kellet's pink noise filter.
+struct Noise {
+    state: u64,
+    pink: [f32; 7],
+}
+
+impl Noise {
+    fn new() -> Self {
+        Noise {
+            state: 0x9E37_79B9_7F4A_7C15,
+            pink: [0.0; 7],
+        }
+    }
+
+    /// Uniform in [-1, 1).
+    fn white(&mut self) -> f32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let bits = self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 40;
+        bits as f32 / (1u32 << 23) as f32 - 1.0
+    }
+
+    /// Peaks near ±1, though unlike white noise it is not strictly bounded.
+    fn pink(&mut self) -> f32 {
+        let white = self.white();
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[..6].iter().sum::<f32>() + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink * 0.11
+    }
+}
+
+/// `frames` samples of a sine, the test signal of choice throughout the crate.
+#[cfg(test)]
+pub(crate) fn sine(frequency: f64, amplitude: f32, sample_rate: u32, frames: usize) -> Vec<f32> {
+    let mut out = Vec::new();
+    Generator::new(Signal::Sine { frequency }, amplitude, sample_rate, 1)
+        .generate(frames, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::averaging::average_spectrum;
+    use crate::dsp::spectrum::SpectrumAnalyzer;
+    use crate::dsp::window::WindowFunction;
+    use crate::types::Spectrum;
+
+    fn generate(signal: Signal, amplitude: f32, frames: usize) -> Vec<f32> {
+        let mut out = Vec::new();
+        Generator::new(signal, amplitude, 48000, 1).generate(frames, &mut out);
+        out
+    }
+
+    fn spectrum(samples: &[f32]) -> Spectrum {
+        let mut analyzer = SpectrumAnalyzer::new(4096, WindowFunction::Hann);
+        average_spectrum(&mut analyzer, samples, 2048, 48000).unwrap()
+    }
+
+    /// Power of the bins from `low` to `high` Hz, in dB.
+    fn band_db(spectrum: &Spectrum, low: f64, high: f64) -> f64 {
+        let power: f64 = (0..spectrum.magnitudes_db.len())
+            .filter(|&bin| (low..high).contains(&spectrum.frequency(bin)))
+            .map(|bin| 10f64.powf(spectrum.magnitudes_db[bin] as f64 / 10.0))
+            .sum();
+        10.0 * power.log10()
+    }
+
+    #[test]
+    fn sine_reads_its_level() {
+        let samples = generate(Signal::Sine { frequency: 750.0 }, 0.1, 48000);
+        let spectrum = spectrum(&samples);
+        // 750 Hz is bin 64 of a 4096-point transform at 48 kHz.
+        assert!((spectrum.magnitudes_db[64] + 20.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn multitone_stays_within_its_amplitude() {
+        let frequencies = vec![375.0, 750.0, 1500.0, 3000.0];
+        let samples = generate(Signal::Multitone { frequencies }, 1.0, 48000);
+        assert!(samples.iter().all(|s| s.abs() <= 1.0));
+        let spectrum = spectrum(&samples);
+        for bin in [32, 64, 128, 256] {
+            assert!((spectrum.magnitudes_db[bin] + 12.04).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn white_noise_is_flat_and_bounded() {
+        let samples = generate(Signal::WhiteNoise, 0.5, 96000);
+        assert!(samples.iter().all(|s| s.abs() <= 0.5));
+        let spectrum = spectrum(&samples);
+        let low = band_db(&spectrum, 1000.0, 2000.0);
+        let high = band_db(&spectrum, 10000.0, 11000.0);
+        assert!((low - high).abs() < 1.0, "{low} {high}");
+    }
+
+    #[test]
+    fn pink_noise_has_equal_power_per_octave() {
+        let samples = generate(Signal::PinkNoise, 1.0, 480000);
+        let spectrum = spectrum(&samples);
+        let low = band_db(&spectrum, 250.0, 500.0);
+        let high = band_db(&spectrum, 4000.0, 8000.0);
+        assert!((low - high).abs() < 1.0, "{low} {high}");
+    }
+
+    #[test]
+    fn sweep_covers_its_range() {
+        let sweep = Signal::Sweep {
+            start: 100.0,
+            end: 10000.0,
+            duration: 1.0,
+        };
+        let spectrum = spectrum(&generate(sweep, 1.0, 48000));
+        let inside = band_db(&spectrum, 200.0, 5000.0);
+        assert!(inside - band_db(&spectrum, 15000.0, 20000.0) > 40.0);
+        assert!(inside - band_db(&spectrum, 20.0, 50.0) > 40.0);
+    }
+
+    #[test]
+    fn silence_is_zero() {
+        assert!(generate(Signal::Silence, 1.0, 1000)
+            .iter()
+            .all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn output_is_reproducible_and_continuous() {
+        let mut generator = Generator::new(Signal::PinkNoise, 1.0, 48000, 1);
+        let mut pieces = Vec::new();
+        generator.generate(1000, &mut pieces);
+        generator.generate(500, &mut pieces);
+        assert_eq!(pieces, generate(Signal::PinkNoise, 1.0, 1500));
+    }
+
+    #[test]
+    fn fixed_pacing_only_delivers_while_running() {
+        let mut generator = Generator::new(Signal::defaults()[0].clone(), 1.0, 48000, 2)
+            .with_pacing(Pacing::Frames(256));
+        let mut out = Vec::new();
+        generator.read(&mut out);
+        assert!(out.is_empty());
+        generator.start().unwrap();
+        generator.read(&mut out);
+        generator.read(&mut out);
+        assert_eq!(out.len(), 2 * 256 * 2);
+        // Both channels carry the same signal.
+        assert!(out.chunks(2).all(|frame| frame[0] == frame[1]));
+        generator.stop();
+        generator.read(&mut out);
+        assert_eq!(out.len(), 2 * 256 * 2);
+    }
+}
@@ -0,0 +1,194 @@
+//! Where samples come from: an input device, a decoded file or a signal generator,
+//! all behind the [`AudioSource`] trait so the analysis does not care which.
+
+mod device;
+mod generator;
+
+use std::time::Instant;
+
+use cpal::SampleFormat;
+
+use crate::capture::tap::TapReader;
+use crate::error::AudioError;
+
+pub use device::DeviceSource;
+#[cfg(test)]
+pub(crate) use generator::sine;
+pub use generator::{Generator, Signal};
+
+/// A stream of interleaved f32 samples in [-1, 1].
+///
+/// Sources are polled: the reader calls [`AudioSource::read`] whenever it is ready
+/// for more, typically once per repaint, and gets everything delivered since.
+pub trait AudioSource {
+    /// What is being captured, e.g. the device name or the generated signal.
+    fn name(&self) -> String;
+
+    /// Frames per second.
+    fn sample_rate(&self) -> u32;
+
+    /// Samples per frame.
+    fn channels(&self) -> usize;
+
+    /// Starts or resumes delivering samples.
+    fn start(&mut self) -> Result<(), AudioError>;
+
+    /// Stops delivering samples; [`AudioSource::start`] resumes.
+    fn stop(&mut self);
+
+    /// Whether samples are being delivered.
+    fn is_running(&self) -> bool;
+
+    /// Appends the interleaved samples delivered since the previous call to `out`.
+    fn read(&mut self, out: &mut Vec<f32>);
+
+    /// Samples lost because the reader fell behind.
+    fn overflows(&self) -> u64 {
+        0
+    }
+
+    /// A reader of every sample delivered from now on, for consumers that have to
+    /// keep up with a device on their own thread however rarely [`AudioSource::read`]
+    /// is called. Sources that produce samples on demand have none.
+    fn tap(&self) -> Option<TapReader> {
+        None
+    }
+
+    /// Sample type of the underlying hardware, for sources that have one.
+    fn sample_format(&self) -> Option<SampleFormat> {
+        None
+    }
+}
+
+/// How fast a file or generator produces samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pacing {
+    /// As many frames as a device would have captured in the time since starting.
+    RealTime,
+    /// This many frames per read regardless of time, so runs are reproducible.
+    Frames(usize),
+}
+
+/// Tracks how far a paced source should have got.
+pub(crate) struct Pacer {
+    pub pacing: Pacing,
+    running_since: Option<(Instant, u64)>,
+}
+
+impl Pacer {
+    pub fn new(pacing: Pacing) -> Self {
+        Pacer {
+            pacing,
+            running_since: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running_since.is_some()
+    }
+
+    /// Starts counting from frame `position`.
+    pub fn start(&mut self, position: u64) {
+        self.running_since = Some((Instant::now(), position));
+    }
+
+    pub fn stop(&mut self) {
+        self.running_since = None;
+    }
+
+    /// The frame a source now at `position` should advance to.
+    pub fn target(&self, position: u64, sample_rate: u32) -> u64 {
+        let Some((started, start_position)) = self.running_since else {
+            return position;
+        };
+        match self.pacing {
+            Pacing::RealTime => {
+                let elapsed = started.elapsed().as_secs_f64() * sample_rate as f64;
+                (start_position + elapsed as u64).max(position)
+            }
+            Pacing::Frames(frames) => position + frames as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::audio_file::{AudioFile, FilePlayer};
+    use crate::capture::MultiChannelBuffer;
+    use crate::dsp::averaging::PowerAverage;
+    use crate::dsp::spectrum::SpectrumAnalyzer;
+    use crate::dsp::window::WindowFunction;
+    use crate::types::Channel;
+
+    /// Feeds `source` through the capture buffer and analyzer the way the GUI does,
+    /// returning the averaged spectrum of `channel`.
+    fn analyze(source: &mut dyn AudioSource, channel: Channel, reads: usize) -> Vec<f32> {
+        const FFT_SIZE: usize = 1024;
+        let mut buffer = MultiChannelBuffer::new(source.channels(), 4 * FFT_SIZE);
+        let mut analyzer = SpectrumAnalyzer::new(FFT_SIZE, WindowFunction::Hann);
+        let mut average = PowerAverage::default();
+        let mut incoming = Vec::new();
+        let mut frame = Vec::new();
+        let mut next_frame_end = FFT_SIZE as u64;
+        source.start().unwrap();
+        for _ in 0..reads {
+            incoming.clear();
+            source.read(&mut incoming);
+            for &sample in &incoming {
+                buffer.push_interleaved(sample);
+            }
+            while buffer.copy_frame(channel, next_frame_end, FFT_SIZE, &mut frame) {
+                average.add(&analyzer.process(&frame, source.sample_rate()).unwrap());
+                next_frame_end += FFT_SIZE as u64 / 2;
+            }
+        }
+        source.stop();
+        average.spectrum().unwrap().magnitudes_db
+    }
+
+    #[test]
+    fn generator_runs_through_the_pipeline() {
+        // 3 kHz is bin 64 at 48 kHz.
+        let signal = Signal::Sine { frequency: 3000.0 };
+        let mut generator = Generator::new(signal, 0.5, 48000, 2).with_pacing(Pacing::Frames(300));
+        let magnitudes = analyze(&mut generator, Channel::Sum, 20);
+        assert!(magnitudes[64].abs() < 0.01);
+        assert!(!generator.is_running());
+    }
+
+    #[test]
+    fn file_runs_through_the_pipeline() {
+        let mut generator = Generator::new(Signal::Sine { frequency: 3000.0 }, 0.25, 48000, 1);
+        let mut samples = Vec::new();
+        generator.generate(4800, &mut samples);
+        let file = AudioFile {
+            path: PathBuf::from("tone.wav"),
+            sample_rate: 48000,
+            channels: 1,
+            encoding: "PCM".into(),
+            channel_layout: None,
+            tags: Vec::new(),
+            samples,
+        };
+        let mut player = FilePlayer::new(file).with_pacing(Pacing::Frames(1000));
+        assert_eq!(player.name(), "tone.wav");
+        let magnitudes = analyze(&mut player, Channel::Single(0), 10);
+        assert!((magnitudes[64] + 12.04).abs() < 0.01);
+        // Playback stops at the end of the file.
+        assert_eq!(player.position(), 4800);
+        assert!(!player.is_playing());
+    }
+
+    #[test]
+    fn real_time_pacing_follows_the_clock() {
+        let mut pacer = Pacer::new(Pacing::RealTime);
+        assert_eq!(pacer.target(10, 48000), 10);
+        pacer.start(10);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let target = pacer.target(10, 48000);
+        assert!(target >= 10 + 960, "{target}");
+    }
+}
@@ -0,0 +1,296 @@
+use eframe::egui;
+use std::collections::VecDeque;
+use std::fmt;
+
+use fft_analyzer::types::Spectrum;
+
+/// Spectra wider than this are reduced by keeping the loudest bin of each group of columns.
+const MAX_COLUMNS: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Grayscale,
+    Jet,
+}
+
+impl Colormap {
+    pub const ALL: [Colormap; 4] = [
+        Colormap::Viridis,
+        Colormap::Magma,
+        Colormap::Grayscale,
+        Colormap::Jet,
+    ];
+
+    /// Maps `t` in [0, 1] to a colour.
+    pub fn color(&self, t: f32) -> egui::Color32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Colormap::Viridis => interpolate(&VIRIDIS, t),
+            Colormap::Magma => interpolate(&MAGMA, t),
+            Colormap::Grayscale => {
+                let v = (t * 255.0).round() as u8;
+                egui::Color32::from_rgb(v, v, v)
+            }
+            Colormap::Jet => {
+                let channel = |offset: f32| {
+                    let v = (1.5 - (4.0 * t - offset).abs()).clamp(0.0, 1.0);
+                    (v * 255.0).round() as u8
+                };
+                egui::Color32::from_rgb(channel(3.0), channel(2.0), channel(1.0))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Colormap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Colormap::Viridis => write!(f, "Viridis"),
+            Colormap::Magma => write!(f, "Magma"),
+            Colormap::Grayscale => write!(f, "Grayscale"),
+            Colormap::Jet => write!(f, "Jet"),
+        }
+    }
+}
+
+/// Evenly spaced samples of matplotlib's viridis map.
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+/// Evenly spaced samples of matplotlib's magma map.
+const MAGMA: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [28, 16, 68],
+    [79, 18, 123],
+    [129, 37, 129],
+    [181, 54, 122],
+    [229, 80, 100],
+    [251, 135, 97],
+    [254, 194, 135],
+    [252, 253, 191],
+];
+
+fn interpolate(stops: &[[u8; 3]], t: f32) -> egui::Color32 {
+    let position = t * (stops.len() - 1) as f32;
+    let index = (position as usize).min(stops.len() - 2);
+    let frac = position - index as f32;
+    let mix = |channel: usize| {
+        let a = stops[index][channel] as f32;
+        let b = stops[index + 1][channel] as f32;
+        (a + (b - a) * frac).round() as u8
+    };
+    egui::Color32::from_rgb(mix(0), mix(1), mix(2))
+}
+
+/// Scrolling time-frequency image built from successive spectra, newest row on top.
+pub struct Spectrogram {
+    rows: VecDeque<Vec<f32>>,
+    history_len: usize,
+    colormap: Colormap,
+    min_db: f32,
+    max_db: f32,
+    nyquist: f64,
+    bin_width: f64,
+    /// Spectrum bins merged into each column.
+    bins_per_column: usize,
+    row_duration: f64,
+    image: egui::ColorImage,
+    texture: Option<egui::TextureHandle>,
+    /// The image must be repainted from `rows`, e.g. after the colormap changed.
+    dirty: bool,
+    /// The image changed since it was last uploaded.
+    modified: bool,
+}
+
+impl Spectrogram {
+    pub fn new(history_len: usize) -> Self {
+        Spectrogram {
+            rows: VecDeque::with_capacity(history_len),
+            history_len,
+            colormap: Colormap::Viridis,
+            min_db: -120.0,
+            max_db: 0.0,
+            nyquist: 0.0,
+            bin_width: 0.0,
+            bins_per_column: 1,
+            row_duration: 0.0,
+            image: egui::ColorImage::new([0, 0], egui::Color32::BLACK),
+            texture: None,
+            dirty: true,
+            modified: true,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.dirty = true;
+    }
+
+    /// Appends one spectrum; `hop_duration` is the time in seconds since the previous one.
+    pub fn push(&mut self, spectrum: &Spectrum, hop_duration: f64) {
+        let bins_per_column = spectrum.magnitudes_db.len().div_ceil(MAX_COLUMNS).max(1);
+        let row = reduce_columns(&spectrum.magnitudes_db, bins_per_column);
+        if self
+            .rows
+            .front()
+            .is_some_and(|last| last.len() != row.len())
+        {
+            self.rows.clear();
+        }
+        self.nyquist = spectrum.frequency(spectrum.magnitudes_db.len() - 1);
+        self.bin_width = spectrum.bin_width();
+        self.bins_per_column = bins_per_column;
+        self.row_duration = hop_duration;
+
+        if self.rows.len() == self.history_len {
+            self.rows.pop_back();
+        }
+        if !self.dirty && self.image.size == [row.len(), self.history_len] {
+            self.scroll_image(&row);
+        } else {
+            self.dirty = true;
+        }
+        self.rows.push_front(row);
+        self.modified = true;
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history_len
+    }
+
+    pub fn set_history_len(&mut self, history_len: usize) {
+        if history_len != self.history_len {
+            self.rows.truncate(history_len);
+            self.history_len = history_len;
+            self.dirty = true;
+        }
+    }
+
+    pub fn colormap(&self) -> Colormap {
+        self.colormap
+    }
+
+    pub fn set_colormap(&mut self, colormap: Colormap) {
+        if colormap != self.colormap {
+            self.colormap = colormap;
+            self.dirty = true;
+        }
+    }
+
+    pub fn db_range(&self) -> (f32, f32) {
+        (self.min_db, self.max_db)
+    }
+
+    pub fn set_db_range(&mut self, min_db: f32, max_db: f32) {
+        if (min_db, max_db) != (self.min_db, self.max_db) {
+            self.min_db = min_db;
+            self.max_db = max_db;
+            self.dirty = true;
+        }
+    }
+
+    /// Frequency covered by the image, in Hz.
+    pub fn nyquist(&self) -> f64 {
+        self.nyquist
+    }
+
+    /// Rows from oldest to newest, each holding one level in dB per column.
+    pub fn rows(&self) -> impl Iterator<Item = &[f32]> {
+        self.rows.iter().rev().map(Vec::as_slice)
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Seconds between consecutive rows.
+    pub fn row_duration(&self) -> f64 {
+        self.row_duration
+    }
+
+    /// Spectrum bins merged into each column; a column holds the loudest of its bins.
+    pub fn bins_per_column(&self) -> usize {
+        self.bins_per_column
+    }
+
+    /// Centre frequency of each column, in Hz.
+    pub fn column_frequencies(&self) -> Vec<f64> {
+        let width = self.rows.front().map_or(0, Vec::len);
+        let group = self.bins_per_column as f64;
+        (0..width)
+            .map(|column| (column as f64 * group + (group - 1.0) / 2.0) * self.bin_width)
+            .collect()
+    }
+
+    /// Time covered by a full history, in seconds.
+    pub fn duration(&self) -> f64 {
+        self.row_duration * self.history_len as f64
+    }
+
+    /// Uploads the image if it changed and returns the texture to draw.
+    pub fn texture(&mut self, ctx: &egui::Context) -> Option<&egui::TextureHandle> {
+        let width = self.rows.front()?.len();
+        if self.dirty {
+            self.image = egui::ColorImage::new([width, self.history_len], egui::Color32::BLACK);
+            for y in 0..self.rows.len() {
+                self.paint_row(y);
+            }
+            self.dirty = false;
+            self.modified = true;
+        }
+        match &mut self.texture {
+            Some(texture) if self.modified => {
+                texture.set(self.image.clone(), egui::TextureOptions::LINEAR)
+            }
+            Some(_) => {}
+            None => {
+                self.texture = Some(ctx.load_texture(
+                    "spectrogram",
+                    self.image.clone(),
+                    egui::TextureOptions::LINEAR,
+                ));
+            }
+        }
+        self.modified = false;
+        self.texture.as_ref()
+    }
+
+    fn scroll_image(&mut self, row: &[f32]) {
+        let width = row.len();
+        self.image
+            .pixels
+            .copy_within(..width * (self.history_len - 1), width);
+        let colors: Vec<egui::Color32> = row.iter().map(|&db| self.color(db)).collect();
+        self.image.pixels[..width].copy_from_slice(&colors);
+    }
+
+    fn paint_row(&mut self, y: usize) {
+        let width = self.image.size[0];
+        for x in 0..width {
+            self.image.pixels[y * width + x] = self.color(self.rows[y][x]);
+        }
+    }
+
+    fn color(&self, db: f32) -> egui::Color32 {
+        let span = (self.max_db - self.min_db).max(f32::EPSILON);
+        self.colormap.color((db - self.min_db) / span)
+    }
+}
+
+fn reduce_columns(magnitudes_db: &[f32], group: usize) -> Vec<f32> {
+    magnitudes_db
+        .chunks(group)
+        .map(|chunk| chunk.iter().copied().fold(f32::MIN, f32::max))
+        .collect()
+}
@@ -0,0 +1,160 @@
+//! Data types shared by the capture, DSP and export code.
+
+use std::fmt;
+
+/// A signal derived from the captured channels: either one of them as-is, or a
+/// mix-down of the first two, treated as left and right.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// One captured channel, counting from zero.
+    Single(usize),
+    /// L+R
+    Sum,
+    /// L−R
+    Difference,
+    /// (L+R)/2
+    Mid,
+    /// (L−R)/2
+    Side,
+}
+
+impl Channel {
+    /// Every mix-down, in display order.
+    pub const MIXES: [Channel; 4] = [
+        Channel::Sum,
+        Channel::Difference,
+        Channel::Mid,
+        Channel::Side,
+    ];
+
+    /// Every channel and mix-down available for a stream with `channel_count` channels.
+    pub fn all(channel_count: usize) -> Vec<Channel> {
+        let mut all: Vec<Channel> = (0..channel_count).map(Channel::Single).collect();
+        if channel_count >= 2 {
+            all.extend(Channel::MIXES);
+        }
+        all
+    }
+
+    /// Whether a stream with `channel_count` channels can provide this signal.
+    pub fn is_available(&self, channel_count: usize) -> bool {
+        match *self {
+            Channel::Single(index) => index < channel_count,
+            _ => channel_count >= 2,
+        }
+    }
+
+    /// Combines a left and right sample according to this mix-down.
+    pub fn mix(&self, left: f32, right: f32) -> f32 {
+        match self {
+            Channel::Single(0) => left,
+            Channel::Single(_) => right,
+            Channel::Sum => left + right,
+            Channel::Difference => left - right,
+            Channel::Mid => (left + right) * 0.5,
+            Channel::Side => (left - right) * 0.5,
+        }
+    }
+
+    /// Copies this channel or mix-down of interleaved `samples`, `channel_count` per
+    /// frame, into `out`.
+    pub fn extract(&self, samples: &[f32], channel_count: usize, out: &mut Vec<f32>) {
+        out.clear();
+        out.extend(
+            samples
+                .chunks_exact(channel_count)
+                .map(|frame| match *self {
+                    Channel::Single(index) => frame[index],
+                    mix => mix.mix(frame[0], frame[1]),
+                }),
+        );
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Channel::Single(index) => write!(f, "Ch {}", index + 1),
+            Channel::Sum => write!(f, "L+R"),
+            Channel::Difference => write!(f, "L−R"),
+            Channel::Mid => write!(f, "Mid"),
+            Channel::Side => write!(f, "Side"),
+        }
+    }
+}
+
+/// A one-sided magnitude spectrum in dB, from DC to Nyquist.
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    /// Sample rate of the analyzed signal, in Hz.
+    pub sample_rate: u32,
+    /// Transform length the spectrum was computed with.
+    pub fft_size: usize,
+    /// Level of each of the `fft_size / 2 + 1` bins in dB.
+    pub magnitudes_db: Vec<f32>,
+    /// Phase of each bin in radians, relative to the start of the frame. NaN once
+    /// spectra have been averaged, since power averaging discards phase.
+    pub phases: Vec<f32>,
+    /// Number of frames that went into this spectrum.
+    pub averages: usize,
+}
+
+impl Spectrum {
+    /// Spacing between bins, in Hz.
+    pub fn bin_width(&self) -> f64 {
+        self.sample_rate as f64 / self.fft_size as f64
+    }
+
+    /// Centre frequency of `bin`, in Hz.
+    pub fn frequency(&self, bin: usize) -> f64 {
+        bin as f64 * self.bin_width()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixes_need_two_channels() {
+        assert_eq!(Channel::all(1), vec![Channel::Single(0)]);
+        assert_eq!(Channel::all(2).len(), 2 + Channel::MIXES.len());
+        assert!(!Channel::Side.is_available(1));
+        assert!(Channel::Single(2).is_available(3));
+    }
+
+    #[test]
+    fn mix_downs() {
+        assert_eq!(Channel::Single(0).mix(0.5, 0.25), 0.5);
+        assert_eq!(Channel::Single(1).mix(0.5, 0.25), 0.25);
+        assert_eq!(Channel::Sum.mix(0.5, 0.25), 0.75);
+        assert_eq!(Channel::Difference.mix(0.5, 0.25), 0.25);
+        assert_eq!(Channel::Mid.mix(0.5, 0.25), 0.375);
+        assert_eq!(Channel::Side.mix(0.5, 0.25), 0.125);
+    }
+
+    #[test]
+    fn extracts_from_interleaved_frames() {
+        let samples = [1.0, 0.5, 0.25, -0.25, 0.0, 1.0];
+        let mut out = Vec::new();
+        Channel::Single(1).extract(&samples, 2, &mut out);
+        assert_eq!(out, [0.5, -0.25, 1.0]);
+        Channel::Sum.extract(&samples, 2, &mut out);
+        assert_eq!(out, [1.5, 0.0, 1.0]);
+        Channel::Single(2).extract(&samples, 3, &mut out);
+        assert_eq!(out, [0.25, 1.0]);
+    }
+
+    #[test]
+    fn bin_frequencies() {
+        let spectrum = Spectrum {
+            sample_rate: 48000,
+            fft_size: 1024,
+            magnitudes_db: vec![0.0; 513],
+            phases: vec![0.0; 513],
+            averages: 1,
+        };
+        assert_eq!(spectrum.bin_width(), 46.875);
+        assert_eq!(spectrum.frequency(512), 24000.0);
+    }
+}